@@ -6,370 +6,3223 @@
 //! - Persistence through an append-only log
 //! - CRUD operations (Create, Read, Update, Delete)
 
-use fs_err::File;
-use parking_lot::RwLock;
+use parking_lot::{Mutex, RwLock, RwLockWriteGuard};
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
 
-use crate::parser;
-use crate::{index::IdIndex, model::Row};
+use crate::parser::{self, Command, CommandOutcome, CommandOutput};
+use crate::{index::IdIndex, model::{Row, unix_now}};
 use crate::errors::DbError;
-use crate::storage::Storage;
+use crate::query::{like_matches, AggregateFunc, CompareOp, Field, Predicate};
+use crate::storage::{self, LogEntry, LogSink, LogStats, LogWarning, RepairReport, ReplayPolicy, Storage, StorageBackend};
 use std::fs;
-use std::io::{BufRead, BufReader};
+use std::io::{self, BufRead, BufReader};
+use std::collections::{BTreeMap, BTreeSet, BinaryHeap, HashMap, HashSet};
+use std::ops::RangeInclusive;
 use std::path::{Path, PathBuf};
-use std::sync::Arc;
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::{Arc, Weak};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// Default upper bound on the length of a row's name, in characters.
+pub const DEFAULT_MAX_NAME_LEN: usize = 256;
+
+/// Row count interval at which the database should be compacted.
+const COMPACT_EVERY: usize = 50_000;
 
 /// The main database structure that manages all database operations.
 ///
 /// # Components
 ///
 /// - `rows`: In-memory storage of all database rows
-/// - `index`: Hash-based index mapping IDs to row positions for O(1) lookups
-/// - `storage`: Persistence layer handling the append-only log
+/// - `index`: Ordered index mapping IDs to row positions for fast lookups and ID ranges
+/// - `storage`: Persistence layer handling the append-only log, or `None`
+///   for an in-memory database
+/// - `max_name_len`: Longest name accepted by `insert`
+/// - `age_range`: Ages accepted by `insert` and `update`, if restricted
+/// - `snapshot_on_shutdown`: Whether `shutdown` also compacts the log into a snapshot
+/// - `capacity`: Maximum number of rows, if bounded, and the `eviction_policy` applied at it
+/// - `case_insensitive_names`: Whether `select_by_name` ignores case
+/// - `undo_stack`: Inverses of the writes since the last compaction, for `undo`
+/// - `read_only`: Whether writes are rejected with `DbError::ReadOnly`
+/// - `running_batches`: Batch files being run, so a batch cannot run itself
 pub struct Database {
     /// In-memory vector of all rows currently in the database
     rows: Vec<Row>,
     /// Index mapping row IDs to their positions in the rows vector
     index: IdIndex,
-    /// Storage backend for persisting operations to disk
-    storage: Storage,
+    /// Storage backend for persisting operations, `None` when in-memory
+    storage: Option<Box<dyn StorageBackend>>,
+    /// Maximum accepted name length in characters
+    max_name_len: usize,
+    /// Inclusive range of accepted ages, `None` to accept any age
+    age_range: Option<RangeInclusive<u8>>,
+    /// Write a snapshot and truncate the log on shutdown
+    snapshot_on_shutdown: bool,
+    /// Maximum number of rows, `None` for no limit
+    capacity: Option<usize>,
+    /// What an insert does once the database holds `capacity` rows
+    eviction_policy: EvictionPolicy,
+    /// Compare names by their lowercased form in `select_by_name`
+    case_insensitive_names: bool,
+    /// Operations reversing each write, most recent last
+    undo_stack: Vec<UndoOp>,
+    /// Reject every write, set by `open_readonly`
+    read_only: bool,
+    /// Canonical paths of the batch files currently running, outermost first
+    running_batches: Vec<PathBuf>,
+}
+
+/// The inverse of a single write, applied by [`Database::undo`].
+enum UndoOp {
+    /// Reverses an insert by deleting the row with this ID
+    Delete(u32),
+    /// Reverses a delete by inserting the removed row again
+    Reinsert(Row),
+    /// Reverses an update by restoring the row as it was before
+    Restore(Row),
+    /// Reverses a rekey by moving the row from `id` back to `to`
+    Rekey { id: u32, to: u32 },
+}
+
+/// A summary of the database's size, as returned by [`Database::stats`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DbStats {
+    /// Number of live rows
+    pub row_count: usize,
+    /// Size of the log on disk in bytes, rolled segments included
+    pub log_bytes: u64,
+    /// Number of entries (inserts, updates and deletes) in the log
+    pub entry_count: usize,
+    /// Whether a snapshot exists
+    pub snapshot_exists: bool,
+}
+
+/// How a batch run handles a line that fails to parse or execute.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BatchMode {
+    /// Stop at the first failing line
+    Strict,
+    /// Skip failing lines and carry on with the rest of the batch
+    #[default]
+    Lenient,
+}
+
+/// What an insert into a database at its capacity does.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EvictionPolicy {
+    /// Fail with `DbError::CapacityExceeded`
+    #[default]
+    Reject,
+    /// Delete the oldest row (lowest `created_at`) to make room
+    EvictOldest,
+}
+
+/// Summary of a batch run by [`Database::exec_batch`].
+#[derive(Debug, Default)]
+pub struct BatchReport {
+    /// Lines whose command ran successfully
+    pub executed: usize,
+    /// Lines that failed to parse or execute
+    pub failed: usize,
+    /// In line order, the output of every query and each failing line's
+    /// error as a `BatchLineError`
+    pub outputs: Vec<CommandOutput>,
+}
+
+/// Which row wins when a merged row's ID already exists, see
+/// [`Database::merge_from_with`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MergePolicy {
+    /// Keep this database's row and skip the other one
+    #[default]
+    KeepPrimary,
+    /// Take the other database's row if its `updated_at` is newer
+    PreferNewer,
+}
+
+/// Summary of a merge run by [`Database::merge_from`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct MergeReport {
+    /// Rows whose ID was new and were inserted
+    pub inserted: usize,
+    /// Rows whose ID already existed and were left as this database's version
+    pub skipped: usize,
+    /// Rows whose ID already existed and replaced this database's version
+    pub replaced: usize,
 }
 
+/// A batch line's 1-based number and the output or error of its command.
+type BatchLine = (usize, Result<Option<CommandOutput>, DbError>);
+
+/// Summary of an import run by [`Database::import_jsonl`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ImportReport {
+    /// Entries that were read and applied
+    pub applied: usize,
+    /// Lines that could not be read and were skipped
+    pub skipped: usize,
+}
+
+/// A lazy view over the rows of a [`Database`], returned by [`Database::scan`].
+///
+/// Yields `&Row` in `select_all` order without cloning or collecting, so
+/// adapters such as `filter` and `take` only visit the rows they need.
+pub struct RowView<'a> {
+    rows: std::slice::Iter<'a, Row>,
+}
+
+impl<'a> Iterator for RowView<'a> {
+    type Item = &'a Row;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.rows.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.rows.size_hint()
+    }
+}
+
+impl ExactSizeIterator for RowView<'_> {}
+
+/// A shareable handle to a [`Database`] guarded by a read-write lock.
+///
+/// Cloning a handle is cheap: clones share the same database and cache,
+/// so a clone can be moved into each thread that needs access.
+#[derive(Clone)]
 pub struct DatabaseHandle {
     inner: Arc<RwLock<Database>>,
+    /// Rows cloned by the last `select_all_shared`, cleared by every write
+    rows_cache: Arc<Mutex<Option<Arc<Vec<Row>>>>>,
+    /// The background flush thread started by `start_auto_flush`, if running
+    auto_flush: Arc<Mutex<Option<AutoFlush>>>,
+}
+
+/// A running background flush thread and the channel that stops it.
+struct AutoFlush {
+    stop: Sender<()>,
+    thread: JoinHandle<()>,
 }
 
 impl DatabaseHandle {
     pub fn new(path: impl AsRef<Path>) -> Result<Self, DbError> {
-        let db = Database::new(&path)?;
-        Ok(Self {
-            inner: Arc::new(RwLock::new(db))
-        })
+        Ok(Self::from_database(Database::new(&path)?))
+    }
+
+    /// Opens a database replaying its log under `policy`, see [`Database::open_with_policy`].
+    pub fn open_with_policy(path: impl AsRef<Path>, policy: ReplayPolicy) -> Result<Self, DbError> {
+        Ok(Self::from_database(Database::open_with_policy(path, policy)?))
+    }
+
+    /// Opens an existing database for reading only, see [`Database::open_readonly`].
+    pub fn open_readonly(path: impl AsRef<Path>) -> Result<Self, DbError> {
+        Ok(Self::from_database(Database::open_readonly(path)?))
+    }
+
+    /// Opens a database resolved under `<base>/data`, see [`Database::with_base`].
+    pub fn with_base(base: impl AsRef<Path>, path: impl AsRef<Path>) -> Result<Self, DbError> {
+        Ok(Self::from_database(Database::with_base(base, path)?))
+    }
+
+    /// Creates a handle to a database that never touches disk.
+    ///
+    /// See [`Database::in_memory`].
+    pub fn in_memory() -> Self {
+        Self::from_database(Database::in_memory())
+    }
+
+    /// Wraps a database in a new handle.
+    fn from_database(db: Database) -> Self {
+        Self {
+            inner: Arc::new(RwLock::new(db)),
+            rows_cache: Arc::new(Mutex::new(None)),
+            auto_flush: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Starts a background thread that flushes the database every `interval`.
+    ///
+    /// Each flush takes the write lock only for the duration of the sync.
+    /// A thread started earlier is stopped first. Flush errors are reported
+    /// on stderr and the thread keeps running; it exits on its own once
+    /// every handle to the database has been dropped.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::time::Duration;
+    /// use mini_db::engine::DatabaseHandle;
+    ///
+    /// let db = DatabaseHandle::new("mini_db.log")?;
+    /// db.start_auto_flush(Duration::from_secs(1));
+    /// // ... serve requests ...
+    /// db.stop_auto_flush();
+    /// # Ok::<(), mini_db::errors::DbError>(())
+    /// ```
+    pub fn start_auto_flush(&self, interval: Duration) {
+        self.stop_auto_flush();
+
+        // A weak reference so the thread doesn't keep a dropped database alive
+        let inner: Weak<RwLock<Database>> = Arc::downgrade(&self.inner);
+        let (stop, stopped) = mpsc::channel();
+
+        let thread = thread::spawn(move || {
+            while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(interval) {
+                let Some(inner) = inner.upgrade() else {
+                    return;
+                };
+                let mut db = inner.write();
+                if let Err(e) = db.flush() {
+                    db.warn(format!("auto-flush failed: {}", e));
+                }
+            }
+        });
+
+        *self.auto_flush.lock() = Some(AutoFlush { stop, thread });
+    }
+
+    /// Stops the background flush thread, if running, and waits for it to exit.
+    pub fn stop_auto_flush(&self) {
+        let Some(auto_flush) = self.auto_flush.lock().take() else {
+            return;
+        };
+
+        // The thread also exits if the send fails because it already stopped
+        let _ = auto_flush.stop.send(());
+        let _ = auto_flush.thread.join();
+    }
+
+    /// Takes the write lock and invalidates the cached rows.
+    ///
+    /// The cache is cleared while the write lock is held, so no reader can
+    /// repopulate it with rows from before this write.
+    fn write(&self) -> RwLockWriteGuard<'_, Database> {
+        let db = self.inner.write();
+        *self.rows_cache.lock() = None;
+        db
     }
 
     pub fn insert(&self, id: u32, name: String, age: u8) -> Result<(), DbError> {
-        let mut db = self.inner.write();
+        let mut db = self.write();
         db.insert(id, name, age)
     }
 
+    pub fn insert_row(&self, row: Row) -> Result<(), DbError> {
+        let mut db = self.write();
+        db.insert_row(row)
+    }
+
+    pub fn insert_many(&self, rows: impl IntoIterator<Item = (u32, String, u8)>) -> Result<usize, DbError> {
+        let mut db = self.write();
+        db.insert_many(rows)
+    }
+
+    pub fn update(&self, id: u32, name: String, age: u8) -> Result<bool, DbError> {
+        let mut db = self.write();
+        db.update(id, name, age)
+    }
+
+    pub fn patch_by_id(&self, id: u32, name: Option<String>, age: Option<u8>) -> Result<bool, DbError> {
+        let mut db = self.write();
+        db.patch_by_id(id, name, age)
+    }
+
+    pub fn rename_by_id(&self, id: u32, name: String) -> Result<bool, DbError> {
+        let mut db = self.write();
+        db.rename_by_id(id, name)
+    }
+
+    pub fn upsert(&self, id: u32, name: String, age: u8) -> Result<bool, DbError> {
+        let mut db = self.write();
+        db.upsert(id, name, age)
+    }
+
+    pub fn insert_if_absent(&self, id: u32, name: String, age: u8) -> Result<bool, DbError> {
+        let mut db = self.write();
+        db.insert_if_absent(id, name, age)
+    }
+
     pub fn delete_by_id(&self, id: u32) -> Result<bool, DbError> {
-        let mut db = self.inner.write();
+        let mut db = self.write();
         db.delete_by_id(id)
     }
 
-    pub fn compact(&self) -> Result<(), DbError> {
-        let mut db = self.inner.write();
+    pub fn rekey(&self, old_id: u32, new_id: u32) -> Result<(), DbError> {
+        let mut db = self.write();
+        db.rekey(old_id, new_id)
+    }
+
+    pub fn delete_and_return(&self, id: u32) -> Result<Option<Row>, DbError> {
+        let mut db = self.write();
+        db.delete_and_return(id)
+    }
+
+    pub fn delete_by_ids(&self, ids: &[u32]) -> Result<usize, DbError> {
+        let mut db = self.write();
+        db.delete_by_ids(ids)
+    }
+
+    pub fn delete_where_age(&self, op: CompareOp, value: u8) -> Result<usize, DbError> {
+        let mut db = self.write();
+        db.delete_where_age(op, value)
+    }
+
+    pub fn enforce_retention(&self, max_age_secs: i64) -> Result<usize, DbError> {
+        let mut db = self.write();
+        db.enforce_retention(max_age_secs)
+    }
+
+    pub fn vacuum(&self) -> Result<usize, DbError> {
+        let mut db = self.write();
+        db.vacuum()
+    }
+
+    pub fn compact(&self) -> Result<usize, DbError> {
+        let mut db = self.write();
         db.compact()
     }
 
-    pub fn select_by_id(&self, id: u32) -> Result<Option<Row>, DbError> {
-        let db = self.inner.read();
-        db.select_by_id(id)
+    pub fn undo(&self) -> Result<bool, DbError> {
+        let mut db = self.write();
+        db.undo()
+    }
+
+    pub fn backup_to(&self, path: impl AsRef<Path>) -> Result<(), DbError> {
+        let db = self.inner.read();
+        db.backup_to(path)
+    }
+
+    pub fn restore_from(&self, path: impl AsRef<Path>) -> Result<usize, DbError> {
+        let mut db = self.write();
+        db.restore_from(path)
+    }
+
+    pub fn import_jsonl(&self, path: impl AsRef<Path>) -> Result<ImportReport, DbError> {
+        let mut db = self.write();
+        db.import_jsonl(path)
+    }
+
+    pub fn merge_from(&self, other_path: impl AsRef<Path>) -> Result<MergeReport, DbError> {
+        let mut db = self.write();
+        db.merge_from(other_path)
+    }
+
+    pub fn merge_from_with(&self, other_path: impl AsRef<Path>, policy: MergePolicy) -> Result<MergeReport, DbError> {
+        let mut db = self.write();
+        db.merge_from_with(other_path, policy)
+    }
+
+    pub fn stats(&self) -> Result<DbStats, DbError> {
+        let db = self.inner.read();
+        db.stats()
+    }
+
+    pub fn schema(&self) -> Vec<(String, String)> {
+        let db = self.inner.read();
+        db.schema()
+    }
+
+    pub fn explain(&self, cmd: &Command) -> String {
+        let db = self.inner.read();
+        db.explain(cmd)
+    }
+
+    pub fn check_integrity(&self) -> Result<(), DbError> {
+        let db = self.inner.read();
+        db.check_integrity()
+    }
+
+    pub fn select_by_id(&self, id: u32) -> Result<Option<Row>, DbError> {
+        let db = self.inner.read();
+        db.select_by_id(id)
+    }
+
+    pub fn contains_id(&self, id: u32) -> bool {
+        let db = self.inner.read();
+        db.contains_id(id)
+    }
+
+    pub fn all_ids(&self) -> Vec<u32> {
+        let db = self.inner.read();
+        db.all_ids()
+    }
+
+    pub fn select_after_id(&self, after: u32, limit: usize) -> Vec<Row> {
+        let db = self.inner.read();
+        db.select_after_id(after, limit)
+    }
+
+    /// Returns every row matching `pred`, see [`Database::select_where_fn`].
+    ///
+    /// The read lock is held for the whole scan, so `pred` must not use
+    /// this handle to write.
+    pub fn select_where_fn<F: Fn(&Row) -> bool>(&self, pred: F) -> Vec<Row> {
+        let db = self.inner.read();
+        db.select_where_fn(pred)
+    }
+
+    pub fn query(&self, cmd: &Command) -> Result<CommandOutcome, DbError> {
+        let db = self.inner.read();
+        db.query(cmd)
+    }
+
+    /// Returns a copy of every row.
+    ///
+    /// Returns a `Result` like the other selects, so scans that can fail
+    /// may be added without changing the signature.
+    pub fn select_all(&self) -> Result<Vec<Row>, DbError> {
+        let db = self.inner.read();
+        Ok(db.select_all().clone())
+    }
+
+    /// Passes every row to `f` under the read lock, without cloning, and
+    /// returns its result.
+    ///
+    /// Writers are blocked until `f` returns, so keep it cheap.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use mini_db::engine::DatabaseHandle;
+    /// # let db = DatabaseHandle::new("mini_db.log")?;
+    /// let adults = db.select_all_ref(|rows| rows.iter().filter(|r| r.age >= 18).count());
+    /// # Ok::<(), mini_db::errors::DbError>(())
+    /// ```
+    pub fn select_all_ref<R>(&self, f: impl FnOnce(&[Row]) -> R) -> R {
+        let db = self.inner.read();
+        f(db.select_all())
+    }
+
+    pub fn select_all_sorted(&self) -> Vec<Row> {
+        let db = self.inner.read();
+        db.select_all_sorted()
+    }
+
+    /// Returns all rows as a shared vector, cloning them at most once per write.
+    ///
+    /// Repeated calls between writes return the same `Arc` without touching
+    /// the database lock. On a cache miss the rows are cloned under the read
+    /// lock, so writers are only blocked for that single clone.
+    pub fn select_all_shared(&self) -> Arc<Vec<Row>> {
+        if let Some(rows) = self.rows_cache.lock().as_ref() {
+            return Arc::clone(rows);
+        }
+
+        // Lock order is always database lock -> cache lock, as in `write`
+        let db = self.inner.read();
+        let rows = Arc::new(db.select_all().clone());
+        *self.rows_cache.lock() = Some(Arc::clone(&rows));
+
+        rows
+    }
+
+    /// Applies `f` to every row under the read lock, without cloning.
+    ///
+    /// Writers are blocked until `f` has visited every row, so keep it cheap.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use mini_db::engine::DatabaseHandle;
+    /// # let db = DatabaseHandle::new("mini_db.log")?;
+    /// let mut count = 0;
+    /// db.for_each_row(|_| count += 1);
+    /// # Ok::<(), mini_db::errors::DbError>(())
+    /// ```
+    pub fn for_each_row<F: FnMut(&Row)>(&self, f: F) {
+        let db = self.inner.read();
+        db.iter_rows().for_each(f);
+    }
+
+    /// Passes a lazy [`RowView`] over every row to `f` under the read lock,
+    /// see [`Database::scan`], and returns its result.
+    ///
+    /// Writers are blocked until `f` returns, so keep it cheap.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use mini_db::engine::DatabaseHandle;
+    /// # let db = DatabaseHandle::new("mini_db.log")?;
+    /// let names: Vec<String> = db.scan(|rows| {
+    ///     rows.filter(|r| r.age >= 18).take(3).map(|r| r.name.clone()).collect()
+    /// });
+    /// # Ok::<(), mini_db::errors::DbError>(())
+    /// ```
+    pub fn scan<R>(&self, f: impl FnOnce(RowView<'_>) -> R) -> R {
+        let db = self.inner.read();
+        f(db.scan())
+    }
+
+    /// Executes a batch file while holding the write lock.
+    ///
+    /// The lock is taken once for the whole batch, so other operations see
+    /// either none or all of it. The batch runs directly on the locked
+    /// [`Database`] and never goes back through a handle, so it cannot
+    /// deadlock on its own lock.
+    pub fn exec_batch(&self, path: PathBuf) -> Result<BatchReport, DbError> {
+        let mut db = self.write();
+        db.exec_batch(path)
+    } 
+
+    pub fn exec_batch_with(&self, path: impl AsRef<Path>, mode: BatchMode) -> Result<Vec<(usize, DbError)>, DbError> {
+        let mut db = self.write();
+        db.exec_batch_with(path, mode)
+    }
+
+    pub fn exec_batch_dry_run(&self, path: impl AsRef<Path>) -> Result<Vec<(usize, Command)>, DbError> {
+        let db = self.inner.read();
+        db.exec_batch_dry_run(path)
+    }
+
+    pub fn flush(&self) -> Result<(), DbError> {
+        let mut db = self.write();
+        db.flush()
+    }
+
+    pub fn shutdown(&self) -> Result<(), DbError> {
+        let mut db = self.write();
+        db.shutdown()
+    }
+
+    pub fn reset_db(&self) -> Result<(), DbError> {
+        let mut db = self.write();
+        db.reset_db()
+    }
+}
+
+impl Database {
+    /// Creates a new database instance or loads an existing one from the given path.
+    ///
+    /// A relative path is resolved under the `data` directory of the current
+    /// working directory; use [`with_base`](Self::with_base) to pin it.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The file path where the database log is stored
+    ///
+    /// # Returns
+    ///
+    /// Returns a `Result` containing the database instance or a `DbError` if:
+    /// - The file cannot be created or opened
+    /// - The existing log file is corrupted
+    /// - There are I/O errors during log replay
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use mini_db::engine::Database;
+    ///
+    /// let db = Database::new("mini_db.log")?;
+    /// # Ok::<(), mini_db::errors::DbError>(())
+    /// ```
+    pub fn new(path: impl AsRef<Path>) -> Result<Self, DbError> {
+        let storage = Storage::new(path.as_ref())?;
+        Self::from_storage(storage)
+    }
+
+    /// Creates or loads a database whose relative `path` is resolved under
+    /// `<base>/data` instead of the current working directory.
+    ///
+    /// See [`Storage::with_base`].
+    ///
+    /// # Arguments
+    ///
+    /// * `base` - The directory holding the `data` directory
+    /// * `path` - The file path where the database log is stored
+    ///
+    /// # Returns
+    ///
+    /// Returns the database, or a `DbError` if the data directory cannot be
+    /// created or the log cannot be opened or replayed.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use mini_db::engine::Database;
+    ///
+    /// let db = Database::with_base("/srv/app", "mini_db.log")?;
+    /// # Ok::<(), mini_db::errors::DbError>(())
+    /// ```
+    pub fn with_base(base: impl AsRef<Path>, path: impl AsRef<Path>) -> Result<Self, DbError> {
+        let storage = Storage::with_base(base, path)?;
+        Self::from_storage(storage)
+    }
+
+    /// Creates or loads a database, replaying its log under `policy`.
+    ///
+    /// Like [`new`](Self::new), but with `ReplayPolicy::Strict` a log line
+    /// that cannot be read fails the open instead of being skipped.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The file path where the database log is stored
+    /// * `policy` - Whether unreadable log lines are skipped or fail the open
+    ///
+    /// # Returns
+    ///
+    /// Returns the database, or a `DbError` as for [`new`](Self::new), or
+    /// `CorruptLog` naming the file and line of the first unreadable line
+    /// under the strict policy.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use mini_db::engine::Database;
+    /// use mini_db::storage::ReplayPolicy;
+    ///
+    /// let db = Database::open_with_policy("mini_db.log", ReplayPolicy::Strict)?;
+    /// # Ok::<(), mini_db::errors::DbError>(())
+    /// ```
+    pub fn open_with_policy(path: impl AsRef<Path>, policy: ReplayPolicy) -> Result<Self, DbError> {
+        let mut storage = Storage::new(path.as_ref())?;
+        storage.replay_policy = policy;
+        Self::from_storage(storage)
+    }
+
+    /// Loads an existing database for inspection without allowing writes.
+    ///
+    /// The log is opened without write access (see [`Storage::open_readonly`]).
+    /// Selects work as usual, while `insert`, `update`, the deletes,
+    /// `reset_db`, `compact` and `vacuum` return `DbError::ReadOnly` and
+    /// leave the files untouched.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The file path where the database log is stored
+    ///
+    /// # Returns
+    ///
+    /// Returns the database, or a `DbError` if the log does not exist or
+    /// cannot be read.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use mini_db::engine::Database;
+    ///
+    /// let mut db = Database::open_readonly("mini_db.log")?;
+    /// println!("{} rows", db.select_all().len());
+    /// assert!(db.insert(1, "Alice".to_string(), 30).is_err());
+    /// # Ok::<(), mini_db::errors::DbError>(())
+    /// ```
+    pub fn open_readonly(path: impl AsRef<Path>) -> Result<Self, DbError> {
+        let storage = Storage::open_readonly(path)?;
+        let mut db = Self::from_storage(storage)?;
+        db.read_only = true;
+
+        Ok(db)
+    }
+
+    /// Creates a database from an already opened storage.
+    ///
+    /// This lets callers configure the storage (for example its
+    /// [`ReplayOrder`](crate::storage::ReplayOrder)) before the log is replayed.
+    ///
+    /// # Arguments
+    ///
+    /// * `storage` - The storage to load rows from and append new entries to
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use mini_db::engine::Database;
+    /// use mini_db::storage::{ReplayOrder, Storage};
+    ///
+    /// let mut storage = Storage::new("mini_db.log")?;
+    /// storage.replay_order = ReplayOrder::Timestamp;
+    /// let db = Database::from_storage(storage)?;
+    /// # Ok::<(), mini_db::errors::DbError>(())
+    /// ```
+    pub fn from_storage(storage: Storage) -> Result<Self, DbError> {
+        Self::from_backend(Box::new(storage))
+    }
+
+    /// Creates a database on top of any storage backend.
+    ///
+    /// The backend's snapshot (if any) is loaded and its log replayed on top.
+    ///
+    /// # Arguments
+    ///
+    /// * `backend` - Where rows are loaded from and new entries appended to
+    ///
+    /// # Returns
+    ///
+    /// Returns the loaded database or a `DbError` if the backend fails to
+    /// read its snapshot or log.
+    pub fn from_backend(backend: Box<dyn StorageBackend>) -> Result<Self, DbError> {
+        let snapshot_rows = backend.snapshot_read()?.unwrap_or_default();
+
+        // Entries logged after the snapshot apply on top of its rows
+        let rows = backend.load_onto(snapshot_rows)?;
+
+        Ok(Self::with_rows(rows, Some(backend)))
+    }
+
+    /// Creates a database that never touches disk.
+    ///
+    /// Writes are kept in memory only and are lost when the database is
+    /// dropped; `compact`, `vacuum` and `shutdown` succeed without doing anything.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use mini_db::engine::Database;
+    ///
+    /// let mut db = Database::in_memory();
+    /// db.insert(1, "Alice".to_string(), 30)?;
+    /// assert_eq!(db.select_all().len(), 1);
+    /// # Ok::<(), mini_db::errors::DbError>(())
+    /// ```
+    pub fn in_memory() -> Self {
+        Self::with_rows(Vec::new(), None)
+    }
+
+    /// Builds a database around already loaded rows with default settings.
+    fn with_rows(rows: Vec<Row>, storage: Option<Box<dyn StorageBackend>>) -> Self {
+        let index = IdIndex::rebuild(&rows);
+
+        Self {
+            rows,
+            index,
+            storage,
+            max_name_len: DEFAULT_MAX_NAME_LEN,
+            age_range: None,
+            snapshot_on_shutdown: false,
+            capacity: None,
+            eviction_policy: EvictionPolicy::default(),
+            case_insensitive_names: false,
+            undo_stack: Vec::new(),
+            read_only: false,
+            running_batches: Vec::new(),
+        }
+    }
+
+    /// Loads a database from the snapshot next to its log, then replays the
+    /// log's entries on top of the snapshot's rows.
+    ///
+    /// # Arguments
+    ///
+    /// * `storage` - The opened storage whose snapshot and log should be read
+    ///
+    /// # Returns
+    ///
+    /// Returns the loaded database or a `DbError` if the log cannot be read
+    /// or either file has an unsupported format version. An otherwise
+    /// unreadable snapshot is treated as empty.
+    pub fn load_from_disk(storage: Storage) -> Result<Self, DbError> {
+        Self::from_backend(Box::new(storage))
+    }
+
+    /// Upgrades the log at `path` (and its snapshot) from format version 0
+    /// to the current format.
+    ///
+    /// The originals are kept with a `.bak` suffix. Running this on files
+    /// that are already current does nothing. See [`Storage::migrate`].
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The file path where the database log is stored
+    ///
+    /// # Returns
+    ///
+    /// Returns `Ok(())` on success or a `DbError` if the files cannot be
+    /// read or rewritten, or were written by a newer format version.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use mini_db::engine::Database;
+    ///
+    /// Database::migrate("mini_db.log")?;
+    /// let db = Database::new("mini_db.log")?;
+    /// # Ok::<(), mini_db::errors::DbError>(())
+    /// ```
+    pub fn migrate(path: impl AsRef<Path>) -> Result<(), DbError> {
+        let mut storage = Storage::new(path.as_ref())?;
+        storage.migrate()?;
+        storage.flush()
+    }
+
+    /// Rebuilds a clean log and snapshot for the database stored at `path`
+    /// after corruption.
+    ///
+    /// Corrupt log lines are dropped and everything readable is folded into
+    /// a fresh snapshot; the originals are kept with a `.bak` suffix. See
+    /// [`Storage::repair`].
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The file path where the database log is stored
+    ///
+    /// # Returns
+    ///
+    /// Returns a report of the entries kept and dropped, or a `DbError` if
+    /// the files cannot be read or rewritten.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use mini_db::engine::Database;
+    ///
+    /// let report = Database::repair("mini_db.log")?;
+    /// println!("Kept {}, dropped {}", report.entries_kept, report.entries_dropped);
+    /// let db = Database::new("mini_db.log")?;
+    /// # Ok::<(), mini_db::errors::DbError>(())
+    /// ```
+    pub fn repair(path: impl AsRef<Path>) -> Result<RepairReport, DbError> {
+        let mut storage = Storage::new(path.as_ref())?;
+        let report = storage.repair()?;
+        storage.flush()?;
+
+        Ok(report)
+    }
+
+    /// Inserts a new row into the database.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - Unique identifier for the row (must not already exist)
+    /// * `name` - Name field for the row
+    /// * `age` - Age field for the row
+    ///
+    /// # Returns
+    ///
+    /// Returns `Ok(())` on success or a `DbError` if:
+    /// - The ID already exists (`DuplicateIdError`)
+    /// - The name is longer than the maximum name length (`NameTooLong`)
+    /// - The age is outside the valid age range (`AgeOutOfRange`)
+    /// - The database is full and rejects inserts (`CapacityExceeded`)
+    /// - There are I/O errors writing to the log
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use mini_db::engine::Database;
+    /// # let mut db = Database::new("mini_db.log")?;
+    /// db.insert(1, "Alice".to_string(), 30)?;
+    /// # Ok::<(), mini_db::errors::DbError>(())
+    /// ```
+    pub fn insert(&mut self, id: u32, name: String, age: u8) -> Result<(), DbError> {
+        self.insert_row(Row::new(id, name, age))
+    }
+
+    /// Inserts an already built row, keeping its extra fields.
+    ///
+    /// Validation and logging are the same as for [`insert`](Self::insert).
+    ///
+    /// # Arguments
+    ///
+    /// * `row` - The row to insert (its ID must not already exist)
+    ///
+    /// # Returns
+    ///
+    /// Returns `Ok(())` on success or a `DbError` if:
+    /// - The ID already exists (`DuplicateIdError`)
+    /// - The name is longer than the maximum name length (`NameTooLong`)
+    /// - The age is outside the valid age range (`AgeOutOfRange`)
+    /// - The database is full and rejects inserts (`CapacityExceeded`)
+    /// - There are I/O errors writing to the log
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use mini_db::engine::Database;
+    /// use mini_db::model::Row;
+    /// # let mut db = Database::new("mini_db.log")?;
+    /// let mut row = Row::new(1, "Alice".to_string(), 30);
+    /// row.set_extra("city", "Paris".into());
+    /// db.insert_row(row)?;
+    /// # Ok::<(), mini_db::errors::DbError>(())
+    /// ```
+    pub fn insert_row(&mut self, row: Row) -> Result<(), DbError> {
+        self.check_writable()?;
+        let id = row.id;
+
+        // Check for duplicate IDs to maintain uniqueness constraint
+        if self.index.get(id).is_some() {
+            return Err(DbError::DuplicateIdError(id));
+        }
+
+        self.validate_name(&row.name)?;
+        self.validate_age(row.age)?;
+        self.make_room()?;
+
+        if let Some(storage) = &mut self.storage {
+            storage.append_entry(&row)?;
+        }
+        self.rows.push(row);
+        self.index.insert(id, self.rows.len() - 1)?;
+        self.undo_stack.push(UndoOp::Delete(id));
+
+        Ok(())
+    }
+
+    /// Inserts many rows at once, without going through the parser.
+    ///
+    /// The whole batch is validated before anything is written: if any ID
+    /// collides with an existing row or with another row of the batch, or
+    /// any name or age is invalid, nothing is inserted. Once validated, all log
+    /// entries are appended and, if the batch pushed the row count past a
+    /// compaction threshold (see [`should_compact`](Self::should_compact)),
+    /// the database is compacted once at the end. Without a capacity, the
+    /// entries are appended in one piece through
+    /// [`StorageBackend::append_entries`], which the file log writes
+    /// all-or-nothing (see [`Storage::append_batch`]).
+    ///
+    /// # Arguments
+    ///
+    /// * `rows` - The `(id, name, age)` tuples to insert
+    ///
+    /// # Returns
+    ///
+    /// Returns the number of rows inserted, or a `DbError` if:
+    /// - An ID collides (`DuplicateIdError` naming the first such ID)
+    /// - A name is longer than the maximum name length (`NameTooLong`)
+    /// - An age is outside the valid age range (`AgeOutOfRange`)
+    /// - The batch does not fit and the database rejects inserts past its
+    ///   capacity (`CapacityExceeded`)
+    /// - There are I/O errors writing to the log, in which case the rows
+    ///   appended before the failure remain inserted (none, when the batch
+    ///   is appended in one piece)
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use mini_db::engine::Database;
+    /// # let mut db = Database::new("mini_db.log")?;
+    /// let rows = (1..=3).map(|id| (id, format!("user{id}"), 30));
+    /// assert_eq!(db.insert_many(rows)?, 3);
+    /// # Ok::<(), mini_db::errors::DbError>(())
+    /// ```
+    pub fn insert_many(&mut self, rows: impl IntoIterator<Item = (u32, String, u8)>) -> Result<usize, DbError> {
+        self.check_writable()?;
+        let rows: Vec<(u32, String, u8)> = rows.into_iter().collect();
+
+        let mut batch_ids = HashSet::with_capacity(rows.len());
+        for (id, name, age) in &rows {
+            if self.index.get(*id).is_some() || !batch_ids.insert(*id) {
+                return Err(DbError::DuplicateIdError(*id));
+            }
+            self.validate_name(name)?;
+            self.validate_age(*age)?;
+        }
+
+        if let Some(capacity) = self.capacity
+            && self.eviction_policy == EvictionPolicy::Reject
+            && self.rows.len() + rows.len() > capacity
+        {
+            return Err(DbError::CapacityExceeded { capacity });
+        }
+
+        let count_before = self.rows.len();
+        let inserted = rows.len();
+        if self.capacity.is_none() {
+            // Nothing gets evicted, so the batch goes to the log in one piece
+            let rows: Vec<Row> = rows.into_iter()
+                .map(|(id, name, age)| Row::new(id, name, age))
+                .collect();
+            if let Some(storage) = &mut self.storage {
+                storage.append_entries(&rows)?;
+            }
+            for row in rows {
+                let id = row.id;
+                self.rows.push(row);
+                self.index.insert(id, self.rows.len() - 1)?;
+                self.undo_stack.push(UndoOp::Delete(id));
+            }
+        } else {
+            for (id, name, age) in rows {
+                self.make_room()?;
+                let row = Row::new(id, name, age);
+                if let Some(storage) = &mut self.storage {
+                    storage.append_entry(&row)?;
+                }
+                self.rows.push(row);
+                self.index.insert(id, self.rows.len() - 1)?;
+                self.undo_stack.push(UndoOp::Delete(id));
+            }
+        }
+
+        if count_before / COMPACT_EVERY != self.rows.len() / COMPACT_EVERY {
+            self.compact()?;
+        }
+
+        Ok(inserted)
+    }
+
+    /// Replaces the name and age of an existing row.
+    ///
+    /// The row keeps its `created_at` while `updated_at` is set to the
+    /// current time. An update entry is appended to the log.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The ID of the row to update
+    /// * `name` - The new name
+    /// * `age` - The new age
+    ///
+    /// # Returns
+    ///
+    /// Returns `Ok(true)` if the row was updated, `Ok(false)` if no row has
+    /// the ID, or a `DbError` if:
+    /// - The name is longer than the maximum name length (`NameTooLong`)
+    /// - The age is outside the valid age range (`AgeOutOfRange`)
+    /// - There are I/O errors writing to the log
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use mini_db::engine::Database;
+    /// # let mut db = Database::new("mini_db.log")?;
+    /// # db.insert(1, "Alice".to_string(), 30)?;
+    /// let updated = db.update(1, "Alice".to_string(), 31)?;
+    /// assert!(updated);
+    /// # Ok::<(), mini_db::errors::DbError>(())
+    /// ```
+    pub fn update(&mut self, id: u32, name: String, age: u8) -> Result<bool, DbError> {
+        self.check_writable()?;
+        let Some(pos) = self.index.get(id) else {
+            return Ok(false);
+        };
+
+        self.validate_name(&name)?;
+        self.validate_age(age)?;
+
+        let updated_row = Row {
+            name,
+            age,
+            updated_at: unix_now(),
+            ..self.rows[pos].clone()
+        };
+        self.replace_row(pos, updated_row)?;
+
+        Ok(true)
+    }
+
+    /// Logs `row` as an update and puts it in place of the row at `pos`.
+    fn replace_row(&mut self, pos: usize, row: Row) -> Result<(), DbError> {
+        if let Some(storage) = &mut self.storage {
+            storage.append_update(&row)?;
+        }
+        let previous = std::mem::replace(&mut self.rows[pos], row);
+        self.undo_stack.push(UndoOp::Restore(previous));
+
+        Ok(())
+    }
+
+    /// Changes only the given fields of an existing row.
+    ///
+    /// Fields passed as `None` keep their current value. The resulting full
+    /// row is logged as an update entry, as with [`update`](Self::update).
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The ID of the row to patch
+    /// * `name` - The new name, or `None` to keep it
+    /// * `age` - The new age, or `None` to keep it
+    ///
+    /// # Returns
+    ///
+    /// Returns `Ok(true)` if the row was updated, `Ok(false)` if no row has
+    /// the ID, or a `DbError` if:
+    /// - The name is longer than the maximum name length (`NameTooLong`)
+    /// - The age is outside the valid age range (`AgeOutOfRange`)
+    /// - There are I/O errors writing to the log
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use mini_db::engine::Database;
+    /// # let mut db = Database::new("mini_db.log")?;
+    /// # db.insert(1, "Alice".to_string(), 30)?;
+    /// // Birthday: only the age changes
+    /// db.patch_by_id(1, None, Some(31))?;
+    /// # Ok::<(), mini_db::errors::DbError>(())
+    /// ```
+    pub fn patch_by_id(&mut self, id: u32, name: Option<String>, age: Option<u8>) -> Result<bool, DbError> {
+        self.check_writable()?;
+        let Some(pos) = self.index.get(id) else {
+            return Ok(false);
+        };
+
+        let current = &self.rows[pos];
+        let name = name.unwrap_or_else(|| current.name.clone());
+        let age = age.unwrap_or(current.age);
+
+        self.update(id, name, age)
+    }
+
+    /// Changes only the name of an existing row, logging an update entry.
+    ///
+    /// Shorthand for [`patch_by_id`](Self::patch_by_id) with just a name.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The ID of the row to rename
+    /// * `name` - The new name
+    ///
+    /// # Returns
+    ///
+    /// Returns `Ok(true)` if the row was renamed, `Ok(false)` if no row has
+    /// the ID, or a `DbError` if:
+    /// - The name is longer than the maximum name length (`NameTooLong`)
+    /// - There are I/O errors writing to the log
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use mini_db::engine::Database;
+    /// # let mut db = Database::new("mini_db.log")?;
+    /// # db.insert(5, "alice".to_string(), 30)?;
+    /// assert!(db.rename_by_id(5, "Alice Smith".to_string())?);
+    /// # Ok::<(), mini_db::errors::DbError>(())
+    /// ```
+    pub fn rename_by_id(&mut self, id: u32, name: String) -> Result<bool, DbError> {
+        self.patch_by_id(id, Some(name), None)
+    }
+
+    /// Changes the ID of an existing row.
+    ///
+    /// The row keeps its other fields, `created_at` included, while
+    /// `updated_at` is set to the current time. A delete entry for the old
+    /// ID and an insert entry for the new one are appended to the log, and
+    /// the row moves to the end of `select_all` order, as it will be after
+    /// replaying the log.
+    ///
+    /// # Arguments
+    ///
+    /// * `old_id` - The current ID of the row
+    /// * `new_id` - The ID the row should have
+    ///
+    /// # Returns
+    ///
+    /// Returns `Ok(())` if the row was re-keyed, or a `DbError` if:
+    /// - No row has `old_id` (`RowNotFound`)
+    /// - A row already has `new_id` (`DuplicateIdError`)
+    /// - There are I/O errors writing to the log
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mini_db::engine::Database;
+    /// let mut db = Database::in_memory();
+    /// db.insert(5, "Alice".to_string(), 30)?;
+    /// db.rekey(5, 9)?;
+    /// assert!(db.contains_id(9) && !db.contains_id(5));
+    /// # Ok::<(), mini_db::errors::DbError>(())
+    /// ```
+    pub fn rekey(&mut self, old_id: u32, new_id: u32) -> Result<(), DbError> {
+        self.check_writable()?;
+        let Some(pos) = self.index.get(old_id) else {
+            return Err(DbError::RowNotFound(old_id));
+        };
+        if self.index.get(new_id).is_some() {
+            return Err(DbError::DuplicateIdError(new_id));
+        }
+
+        let row = Row {
+            id: new_id,
+            updated_at: unix_now(),
+            ..self.rows[pos].clone()
+        };
+        if let Some(storage) = &mut self.storage {
+            storage.append_delete(old_id)?;
+            storage.append_entry(&row)?;
+        }
+        self.rows.remove(pos);
+        self.rows.push(row);
+
+        // Rebuild index since positions have shifted after removal
+        self.index = IdIndex::rebuild(&self.rows);
+        self.undo_stack.push(UndoOp::Rekey { id: new_id, to: old_id });
+
+        Ok(())
+    }
+
+    /// Inserts a row, or updates the existing row with the same ID.
+    ///
+    /// Appends an insert entry for a new ID and an update entry otherwise,
+    /// so re-running a seed script does not fail on `DuplicateIdError`.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The ID of the row to insert or update
+    /// * `name` - The (new) name
+    /// * `age` - The (new) age
+    ///
+    /// # Returns
+    ///
+    /// Returns `Ok(true)` if a new row was inserted, `Ok(false)` if an
+    /// existing row was updated, or a `DbError` if:
+    /// - The name is longer than the maximum name length (`NameTooLong`)
+    /// - The age is outside the valid age range (`AgeOutOfRange`)
+    /// - There are I/O errors writing to the log
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use mini_db::engine::Database;
+    /// # let mut db = Database::new("mini_db.log")?;
+    /// assert!(db.upsert(1, "Alice".to_string(), 30)?);
+    /// assert!(!db.upsert(1, "Alice".to_string(), 31)?);
+    /// # Ok::<(), mini_db::errors::DbError>(())
+    /// ```
+    pub fn upsert(&mut self, id: u32, name: String, age: u8) -> Result<bool, DbError> {
+        if self.index.get(id).is_some() {
+            self.update(id, name, age)?;
+            return Ok(false);
+        }
+
+        self.insert(id, name, age)?;
+        Ok(true)
+    }
+
+    /// Inserts a row unless a row with the same ID already exists.
+    ///
+    /// Unlike [`upsert`](Self::upsert), an existing row is left unchanged
+    /// and nothing is logged, so re-running a seed script does not clobber
+    /// later edits.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The ID of the row to insert
+    /// * `name` - The name of the new row
+    /// * `age` - The age of the new row
+    ///
+    /// # Returns
+    ///
+    /// Returns `Ok(true)` if the row was inserted, `Ok(false)` if the ID
+    /// already exists, or a `DbError` if the insert itself fails (see
+    /// [`insert`](Self::insert)).
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use mini_db::engine::Database;
+    /// # let mut db = Database::new("mini_db.log")?;
+    /// assert!(db.insert_if_absent(1, "Alice".to_string(), 30)?);
+    /// assert!(!db.insert_if_absent(1, "Alicia".to_string(), 31)?);
+    /// # Ok::<(), mini_db::errors::DbError>(())
+    /// ```
+    pub fn insert_if_absent(&mut self, id: u32, name: String, age: u8) -> Result<bool, DbError> {
+        if self.contains_id(id) {
+            return Ok(false);
+        }
+
+        self.insert(id, name, age)?;
+        Ok(true)
+    }
+
+    /// Sets the maximum accepted name length, in characters.
+    ///
+    /// Defaults to [`DEFAULT_MAX_NAME_LEN`]. Existing rows are not re-validated.
+    pub fn set_max_name_len(&mut self, max: usize) {
+        self.max_name_len = max;
+    }
+
+    /// Returns the maximum accepted name length, in characters.
+    pub fn max_name_len(&self) -> usize {
+        self.max_name_len
+    }
+
+    /// Returns `true` if the database was opened with [`open_readonly`](Self::open_readonly).
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    /// Fails with `ReadOnly` if the database rejects writes.
+    fn check_writable(&self) -> Result<(), DbError> {
+        if self.read_only {
+            return Err(DbError::ReadOnly);
+        }
+
+        Ok(())
+    }
+
+    /// Restricts the ages accepted by `insert` and `update` to `range`, or
+    /// lifts the restriction with `None`.
+    ///
+    /// Defaults to `None`. Existing rows are not re-validated.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use mini_db::engine::Database;
+    /// # let mut db = Database::new("mini_db.log")?;
+    /// db.set_age_range(Some(0..=150));
+    /// assert!(db.insert(1, "Alice".to_string(), 200).is_err());
+    /// # Ok::<(), mini_db::errors::DbError>(())
+    /// ```
+    pub fn set_age_range(&mut self, range: Option<RangeInclusive<u8>>) {
+        self.age_range = range;
+    }
+
+    /// Returns the range of accepted ages, if restricted.
+    pub fn age_range(&self) -> Option<RangeInclusive<u8>> {
+        self.age_range.clone()
+    }
+
+    /// Enables or disables writing a snapshot on shutdown.
+    ///
+    /// When enabled, [`shutdown`](Self::shutdown) compacts the log into a fresh
+    /// snapshot so the next open loads from the snapshot alone. Defaults to `false`.
+    pub fn set_snapshot_on_shutdown(&mut self, enabled: bool) {
+        self.snapshot_on_shutdown = enabled;
+    }
+
+    /// Returns whether `shutdown` writes a snapshot.
+    pub fn snapshot_on_shutdown(&self) -> bool {
+        self.snapshot_on_shutdown
+    }
+
+    /// Limits the database to `capacity` rows.
+    ///
+    /// What an insert does once the limit is reached is decided by the
+    /// [`EvictionPolicy`], which defaults to rejecting the insert. Rows
+    /// already present beyond the limit are kept until the next insert,
+    /// which then fails under `Reject`, or under `EvictOldest` first evicts
+    /// as many of the oldest rows as it takes to get back under the limit.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use mini_db::engine::{Database, EvictionPolicy};
+    ///
+    /// let mut db = Database::in_memory().with_capacity(2);
+    /// db.set_eviction_policy(EvictionPolicy::EvictOldest);
+    /// for id in 1..=3 {
+    ///     db.insert(id, format!("user{id}"), 30)?;
+    /// }
+    /// assert_eq!(db.ids(), vec![2, 3]);
+    /// # Ok::<(), mini_db::errors::DbError>(())
+    /// ```
+    pub fn with_capacity(mut self, capacity: usize) -> Self {
+        self.capacity = Some(capacity);
+        self
+    }
+
+    /// Returns the maximum number of rows, if the database is bounded.
+    pub fn capacity(&self) -> Option<usize> {
+        self.capacity
+    }
+
+    /// Sets what an insert does once the database is at its capacity.
+    pub fn set_eviction_policy(&mut self, policy: EvictionPolicy) {
+        self.eviction_policy = policy;
+    }
+
+    /// Returns what an insert does once the database is at its capacity.
+    pub fn eviction_policy(&self) -> EvictionPolicy {
+        self.eviction_policy
+    }
+
+    /// Enables or disables case-insensitive name lookups.
+    ///
+    /// When enabled, [`select_by_name`](Self::select_by_name) compares
+    /// lowercased names, so `alice` finds `Alice`; rows keep the case they
+    /// were stored with. Defaults to `false`.
+    pub fn set_case_insensitive_names(&mut self, enabled: bool) {
+        self.case_insensitive_names = enabled;
+    }
+
+    /// Returns whether name lookups ignore case.
+    pub fn case_insensitive_names(&self) -> bool {
+        self.case_insensitive_names
+    }
+
+    /// Frees a slot for one more row if the database is at its capacity,
+    /// by rejecting the insert or deleting (and logging) the oldest rows.
+    fn make_room(&mut self) -> Result<(), DbError> {
+        let Some(capacity) = self.capacity else {
+            return Ok(());
+        };
+
+        while self.rows.len() >= capacity {
+            // The first row with the lowest created_at is the earliest inserted
+            let oldest = self.rows.iter().min_by_key(|r| r.created_at).map(|r| r.id);
+            match (self.eviction_policy, oldest) {
+                (EvictionPolicy::EvictOldest, Some(id)) => {
+                    self.delete_and_return(id)?;
+                },
+                _ => return Err(DbError::CapacityExceeded { capacity }),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Checks that `name` fits within the maximum name length.
+    fn validate_name(&self, name: &str) -> Result<(), DbError> {
+        let len = name.chars().count();
+
+        if len > self.max_name_len {
+            return Err(DbError::NameTooLong { len, max: self.max_name_len });
+        }
+
+        Ok(())
+    }
+
+    /// Checks that `age` lies within the valid age range, if one is set.
+    fn validate_age(&self, age: u8) -> Result<(), DbError> {
+        if let Some(range) = &self.age_range
+            && !range.contains(&age)
+        {
+            return Err(DbError::AgeOutOfRange { value: age, min: *range.start(), max: *range.end() });
+        }
+
+        Ok(())
+    }
+
+    /// Executes a batch of commands from a text file.
+    ///
+    /// Each line in the file should contain a valid database command, which
+    /// is applied to this database. Blank lines and lines starting with `#`
+    /// are skipped, so batch files can be annotated. A line that fails to
+    /// parse or execute is recorded with its line number and the batch
+    /// carries on with the next line. Nothing is printed: query results and
+    /// failures are returned in the report's `outputs`.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path to the file containing batch commands
+    ///
+    /// # Returns
+    ///
+    /// Returns how many lines ran and how many failed, so a file in which
+    /// nothing succeeded can be told apart from a clean run, along with the
+    /// lines' outputs, or a `DbError` if:
+    /// - The file does not exist
+    /// - There are I/O errors reading the file
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use mini_db::engine::Database;
+    /// # let mut db = Database::new("mini_db.log")?;
+    /// let report = db.exec_batch("seed.txt".into())?;
+    /// println!("{} ran, {} failed", report.executed, report.failed);
+    /// # Ok::<(), mini_db::errors::DbError>(())
+    /// ```
+    pub fn exec_batch(&mut self, path: PathBuf) -> Result<BatchReport, DbError> {
+        let mut report = BatchReport::default();
+
+        for (line, result) in self.run_batch(&path, BatchMode::Lenient)? {
+            match result {
+                Ok(output) => {
+                    report.executed += 1;
+                    report.outputs.extend(output);
+                },
+                Err(e) => {
+                    report.failed += 1;
+                    report.outputs.push(CommandOutput::Error(DbError::BatchLineError { line, source: Box::new(e) }));
+                },
+            }
+        }
+
+        Ok(report)
+    }
+
+    /// Executes a batch of commands from a text file, returning only the
+    /// lines that failed.
+    ///
+    /// Blank lines and `#` comments are skipped as in [`exec_batch`](Self::exec_batch).
+    /// Commands before a failing line stay applied in either mode. Query
+    /// output is discarded; use [`exec_batch`](Self::exec_batch) to keep it.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path to the file containing batch commands
+    /// * `mode` - Whether to stop at the first failing line or carry on
+    ///
+    /// # Returns
+    ///
+    /// Returns every failing line's 1-based number and error (always empty
+    /// in strict mode), or a `DbError` if:
+    /// - The file does not exist or cannot be read
+    /// - In strict mode, a line fails (`BatchLineError` naming the line)
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use mini_db::engine::{BatchMode, Database};
+    /// # let mut db = Database::new("mini_db.log")?;
+    /// for (line, e) in db.exec_batch_with("seed.txt", BatchMode::Lenient)? {
+    ///     println!("line {}: {}", line, e);
+    /// }
+    /// # Ok::<(), mini_db::errors::DbError>(())
+    /// ```
+    pub fn exec_batch_with(&mut self, path: impl AsRef<Path>, mode: BatchMode) -> Result<Vec<(usize, DbError)>, DbError> {
+        let results = self.run_batch(path.as_ref(), mode)?;
+        Ok(results.into_iter().filter_map(|(line, result)| result.err().map(|e| (line, e))).collect())
+    }
+
+    /// Runs a batch file, returning every line's number and result, see
+    /// [`exec_batch_with`](Self::exec_batch_with).
+    ///
+    /// A batch that runs, directly or through other batches, a file that is
+    /// already running fails with `DbError::RecursiveBatch`.
+    fn run_batch(&mut self, path: &Path, mode: BatchMode) -> Result<Vec<BatchLine>, DbError> {
+        let lines = Self::batch_lines(path)?;
+        let canonical = fs::canonicalize(path)?;
+        if self.running_batches.contains(&canonical) {
+            return Err(DbError::RecursiveBatch { path: path.to_path_buf() });
+        }
+
+        self.running_batches.push(canonical);
+        let results = self.run_batch_lines(lines, mode);
+        self.running_batches.pop();
+        results
+    }
+
+    /// Runs the lines of a batch file, see [`run_batch`](Self::run_batch).
+    fn run_batch_lines(&mut self, lines: Vec<(usize, String)>, mode: BatchMode) -> Result<Vec<BatchLine>, DbError> {
+        let mut results = Vec::new();
+
+        for (line_num, line) in lines {
+            let result = parser::parse_command(&line).and_then(|cmd| self.execute(cmd));
+            match (result, mode) {
+                (Err(e), BatchMode::Strict) => {
+                    return Err(DbError::BatchLineError { line: line_num, source: Box::new(e) });
+                },
+                (result, _) => results.push((line_num, result)),
+            }
+        }
+
+        Ok(results)
+    }
+
+    /// Parses a batch file without applying any of its commands.
+    ///
+    /// Blank lines and `#` comments are skipped as in [`exec_batch`](Self::exec_batch).
+    /// Neither the rows nor the log are touched.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path to the file containing batch commands
+    ///
+    /// # Returns
+    ///
+    /// Returns every parsed command with its 1-based line number, or a `DbError` if:
+    /// - The file does not exist or cannot be read
+    /// - A line does not parse (`ParseError` naming the first such line)
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use mini_db::engine::Database;
+    /// # let db = Database::new("mini_db.log")?;
+    /// let commands = db.exec_batch_dry_run("seed.txt")?;
+    /// println!("{} command(s) would run", commands.len());
+    /// # Ok::<(), mini_db::errors::DbError>(())
+    /// ```
+    pub fn exec_batch_dry_run(&self, path: impl AsRef<Path>) -> Result<Vec<(usize, Command)>, DbError> {
+        Self::batch_lines(path.as_ref())?
+            .into_iter()
+            .map(|(line_num, line)| {
+                parser::parse_command(&line)
+                    .map(|cmd| (line_num, cmd))
+                    .map_err(|e| DbError::ParseError(format!("line {}: {}", line_num, e)))
+            })
+            .collect()
+    }
+
+    /// Reads the command lines of a batch file with their 1-based line numbers,
+    /// skipping blank separator lines and `#` comments.
+    fn batch_lines(path: &Path) -> Result<Vec<(usize, String)>, DbError> {
+        if !path.exists() {
+            return Err(DbError::IoError(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("Batch file not found: {}", path.display())
+            )));
+        }
+
+        let file = fs::File::open(path)?;
+        let reader = BufReader::new(file);
+        let mut lines = Vec::new();
+
+        for (line_num, line) in reader.lines().enumerate() {
+            let line = line?;
+            let line = line.trim();
+
+            // Skip separators and comments
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            lines.push((line_num + 1, line.to_string()));
+        }
+
+        Ok(lines)
+    }
+
+    /// Applies a single batch command to the database.
+    ///
+    /// Queries, `STATS`, `DESCRIBE`, `EXPLAIN` and nested batches return
+    /// their output for the caller to show; writes return `None`. Shell-only
+    /// commands such as `EXIT` or `HISTORY` are rejected with
+    /// `InvalidCommandError`, as is `RESET`, which needs the shell's
+    /// confirmation token.
+    fn execute(&mut self, cmd: Command) -> Result<Option<CommandOutput>, DbError> {
+        match cmd {
+            Command::Insert { id, name, age } => self.insert(id, name, age).map(|_| None),
+            Command::Upsert { id, name, age } => self.upsert(id, name, age).map(|_| None),
+            Command::InsertIfAbsent { id, name, age } => self.insert_if_absent(id, name, age).map(|_| None),
+            Command::Update { id, name, age } => self.patch_by_id(id, name, age).map(|_| None),
+            Command::Rename { id, name } => self.rename_by_id(id, name).map(|_| None),
+            Command::Rekey { old_id, new_id } => self.rekey(old_id, new_id).map(|_| None),
+            Command::DeleteById { id } => self.delete_by_id(id).map(|_| None),
+            Command::DeleteByIds { ids } => self.delete_by_ids(&ids).map(|_| None),
+            Command::DeleteByAge { op, value } => self.delete_where_age(op, value).map(|_| None),
+            Command::ExecBatch { path } => self.exec_batch(path).map(|report| Some(parser::batch_output(report))),
+            Command::ExecBatchDryRun { path } => self.exec_batch_dry_run(path).map(|_| None),
+            Command::Flush => self.flush().map(|_| None),
+            Command::Undo => self.undo().map(|_| None),
+            Command::Stats => Ok(Some(parser::stats_output(&self.stats()?))),
+            Command::Describe => Ok(Some(parser::schema_output(&self.schema()))),
+            Command::Explain { inner } => Ok(Some(CommandOutput::Message(self.explain(&inner)))),
+            Command::Compact => self.compact().map(|_| None),
+            Command::Select
+            | Command::SelectById { .. }
+            | Command::SelectByIds { .. }
+            | Command::SelectWhere { .. }
+            | Command::SelectByNameLike { .. }
+            | Command::SelectKeys { .. }
+            | Command::SelectFields { .. }
+            | Command::SelectDistinct { .. }
+            | Command::Aggregate { .. }
+            | Command::CountGroupByAge
+            | Command::CountGroupByName
+            | Command::SelectTopByAge { .. }
+            | Command::SelectIds
+            | Command::Sample { .. }
+            | Command::Exists { .. } => self.query(&cmd).map(|outcome| Some(outcome.into())),
+            Command::Exit
+            | Command::Help
+            | Command::History
+            | Command::HistoryReplay { .. }
+            | Command::Format { .. }
+            | Command::DefineAlias { .. }
+            | Command::Prompt { .. }
+            | Command::Reset
+            | Command::ResetConfirm { .. } => Err(DbError::InvalidCommandError),
+        }
+    }
+
+    /// Returns a reference to all rows in the database.
+    ///
+    /// # Returns
+    ///
+    /// A reference to the vector containing all rows in insertion order
+    /// (accounting for deletions). This order is an implementation detail;
+    /// use [`select_all_sorted`](Self::select_all_sorted) for a stable order.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use mini_db::engine::Database;
+    /// # let db = Database::new("mini_db.log")?;
+    /// let all_rows = db.select_all();
+    /// println!("Total rows: {}", all_rows.len());
+    /// # Ok::<(), mini_db::errors::DbError>(())
+    /// ```
+    pub fn select_all(&self) -> &Vec<Row> {
+        &self.rows
+    }
+
+    /// Returns a copy of all rows in ascending ID order.
+    ///
+    /// Unlike `select_all`, the order does not depend on how rows were
+    /// inserted or deleted, so it is safe to compare against in tests.
+    /// This is the order in which `SELECT` lists rows.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use mini_db::engine::Database;
+    /// # let db = Database::new("mini_db.log")?;
+    /// for row in db.select_all_sorted() {
+    ///     println!("{:?}", row);
+    /// }
+    /// # Ok::<(), mini_db::errors::DbError>(())
+    /// ```
+    pub fn select_all_sorted(&self) -> Vec<Row> {
+        let mut rows = self.rows.clone();
+        rows.sort_unstable_by_key(|r| r.id);
+        rows
+    }
+
+    /// Reconstructs the database as it was at `cutoff` by replaying only the
+    /// log entries made up to then.
+    ///
+    /// The returned database is a detached, in-memory view: it has no
+    /// storage, so nothing done to it touches this database or its log.
+    /// Rows folded into a snapshot by compaction are not part of the view.
+    ///
+    /// # Arguments
+    ///
+    /// * `cutoff` - The Unix timestamp to reconstruct the database at
+    ///
+    /// # Returns
+    ///
+    /// Returns the view, or a `DbError` if the log cannot be read. An
+    /// in-memory database, or a backend that does not record entry times
+    /// (including a [`Storage`] with `record_timestamps` off), fails with an
+    /// `Unsupported` I/O error.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use mini_db::engine::Database;
+    /// # let db = Database::new("mini_db.log")?;
+    /// let yesterday = db.snapshot_at(1_700_000_000)?;
+    /// println!("{:?}", yesterday.select_by_id(1)?);
+    /// # Ok::<(), mini_db::errors::DbError>(())
+    /// ```
+    pub fn snapshot_at(&self, cutoff: i64) -> Result<Database, DbError> {
+        let rows = match &self.storage {
+            Some(storage) => storage.load_until(cutoff)?,
+            None => return Err(io::Error::new(io::ErrorKind::Unsupported, "in-memory databases keep no log").into()),
+        };
+
+        Ok(Self::with_rows(rows, None))
+    }
+
+    /// Returns an iterator over all rows, in `select_all` order.
+    ///
+    /// Unlike cloning the rows, this allocates nothing, which suits callers
+    /// that only count, filter or aggregate.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use mini_db::engine::Database;
+    /// # let db = Database::new("mini_db.log")?;
+    /// let adults = db.iter_rows().filter(|row| row.age >= 18).count();
+    /// # Ok::<(), mini_db::errors::DbError>(())
+    /// ```
+    pub fn iter_rows(&self) -> impl Iterator<Item = &Row> {
+        self.rows.iter()
+    }
+
+    /// Returns a lazy [`RowView`] over all rows, in `select_all` order.
+    ///
+    /// Filters and limits composed on the view run row by row as it is
+    /// consumed; nothing is cloned or collected unless the caller does so.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mini_db::engine::Database;
+    /// let mut db = Database::in_memory();
+    /// db.insert(1, "Alice".to_string(), 30)?;
+    /// db.insert(2, "Bob".to_string(), 17)?;
+    /// let adults: Vec<u32> = db.scan().filter(|r| r.age >= 18).take(10).map(|r| r.id).collect();
+    /// assert_eq!(adults, vec![1]);
+    /// # Ok::<(), mini_db::errors::DbError>(())
+    /// ```
+    pub fn scan(&self) -> RowView<'_> {
+        RowView { rows: self.rows.iter() }
+    }
+
+    /// Returns every row satisfying the predicate, in `select_all` order.
+    ///
+    /// # Arguments
+    ///
+    /// * `pred` - The predicate each row is evaluated against
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use mini_db::engine::Database;
+    /// use mini_db::query::{CompareOp, Field, Predicate, Value};
+    /// # let db = Database::new("mini_db.log")?;
+    /// let adults = Predicate::Compare { field: Field::Age, op: CompareOp::Ge, value: Value::Number(18) };
+    /// let rows = db.select_where(&adults);
+    /// # Ok::<(), mini_db::errors::DbError>(())
+    /// ```
+    pub fn select_where(&self, pred: &Predicate) -> Vec<Row> {
+        self.rows.iter()
+            .filter(|r| pred.matches(r))
+            .cloned()
+            .collect()
+    }
+
+    /// Returns the rows with the given IDs, using one index lookup per ID.
+    ///
+    /// Rows come back in the order their IDs are listed; IDs with no row are
+    /// skipped and repeated IDs only yield their row once.
+    ///
+    /// # Arguments
+    ///
+    /// * `ids` - The IDs to look up
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use mini_db::engine::Database;
+    /// # let db = Database::new("mini_db.log")?;
+    /// let rows = db.select_by_ids(&[5, 9, 12]);
+    /// # Ok::<(), mini_db::errors::DbError>(())
+    /// ```
+    pub fn select_by_ids(&self, ids: &[u32]) -> Vec<Row> {
+        let mut seen = HashSet::with_capacity(ids.len());
+
+        ids.iter()
+            .filter(|id| seen.insert(**id))
+            .filter_map(|id| self.index.get(*id))
+            .map(|pos| self.rows[pos].clone())
+            .collect()
+    }
+
+    /// Returns every row whose name matches a `LIKE` pattern, in `select_all` order.
+    ///
+    /// `%` matches any sequence of characters and `_` any single character;
+    /// escape either with `\` to match it literally. Matching is
+    /// case-insensitive, see [`like_matches`].
+    ///
+    /// # Arguments
+    ///
+    /// * `pattern` - The pattern names are matched against
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use mini_db::engine::Database;
+    /// # let db = Database::new("mini_db.log")?;
+    /// let rows = db.select_by_name_like("Al%");
+    /// # Ok::<(), mini_db::errors::DbError>(())
+    /// ```
+    pub fn select_by_name_like(&self, pattern: &str) -> Vec<Row> {
+        self.rows.iter()
+            .filter(|r| like_matches(pattern, &r.name))
+            .cloned()
+            .collect()
+    }
+
+    /// Returns every row with the given name, in `select_all` order.
+    ///
+    /// Names must match exactly unless case-insensitive names are enabled
+    /// (see [`set_case_insensitive_names`](Self::set_case_insensitive_names)).
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name to look for
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mini_db::engine::Database;
+    /// let mut db = Database::in_memory();
+    /// db.insert(1, "Alice".to_string(), 30)?;
+    /// assert!(db.select_by_name("alice").is_empty());
+    ///
+    /// db.set_case_insensitive_names(true);
+    /// assert_eq!(db.select_by_name("alice")[0].name, "Alice");
+    /// # Ok::<(), mini_db::errors::DbError>(())
+    /// ```
+    pub fn select_by_name(&self, name: &str) -> Vec<Row> {
+        if !self.case_insensitive_names {
+            return self.rows.iter().filter(|r| r.name == name).cloned().collect();
+        }
+
+        let key = name.to_lowercase();
+        self.rows.iter()
+            .filter(|r| r.name.to_lowercase() == key)
+            .cloned()
+            .collect()
+    }
+
+    /// Returns every row for which `pred` returns `true`, in `select_all` order.
+    ///
+    /// # Arguments
+    ///
+    /// * `pred` - Decides whether a row is returned
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mini_db::engine::Database;
+    /// let mut db = Database::in_memory();
+    /// db.insert(1, "Alice".to_string(), 30)?;
+    /// db.insert(2, "Bob".to_string(), 25)?;
+    /// assert_eq!(db.select_where_fn(|r| r.age % 2 == 0)[0].id, 1);
+    /// # Ok::<(), mini_db::errors::DbError>(())
+    /// ```
+    pub fn select_where_fn<F: Fn(&Row) -> bool>(&self, pred: F) -> Vec<Row> {
+        self.rows.iter()
+            .filter(|r| pred(r))
+            .cloned()
+            .collect()
+    }
+
+    /// Returns every row created between `lo` and `hi`, both inclusive, in
+    /// `select_all` order.
+    ///
+    /// An empty range (`lo > hi`) matches nothing.
+    ///
+    /// # Arguments
+    ///
+    /// * `lo` - The earliest `created_at` Unix timestamp to include
+    /// * `hi` - The latest `created_at` Unix timestamp to include
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use mini_db::engine::Database;
+    /// # let db = Database::new("mini_db.log")?;
+    /// let rows = db.select_created_between(1_700_000_000, 1_700_086_399);
+    /// # Ok::<(), mini_db::errors::DbError>(())
+    /// ```
+    pub fn select_created_between(&self, lo: i64, hi: i64) -> Vec<Row> {
+        self.rows.iter()
+            .filter(|r| (lo..=hi).contains(&r.created_at))
+            .cloned()
+            .collect()
+    }
+
+    /// Returns every row reduced to the requested fields, in ascending ID order.
+    ///
+    /// Each row becomes a JSON object holding only `fields`, keyed by their
+    /// column names (`id`, `name`, `age`).
+    ///
+    /// # Arguments
+    ///
+    /// * `fields` - The fields to keep
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use mini_db::engine::Database;
+    /// use mini_db::query::Field;
+    /// # let db = Database::new("mini_db.log")?;
+    /// for value in db.select_projection(&[Field::Id, Field::Name]) {
+    ///     println!("{}", value);
+    /// }
+    /// # Ok::<(), mini_db::errors::DbError>(())
+    /// ```
+    pub fn select_projection(&self, fields: &[Field]) -> Vec<serde_json::Value> {
+        let mut rows: Vec<&Row> = self.rows.iter().collect();
+        rows.sort_unstable_by_key(|r| r.id);
+
+        rows.into_iter()
+            .map(|row| {
+                let object: serde_json::Map<String, serde_json::Value> = fields.iter()
+                    .map(|field| (field.name().to_string(), field.json_value(row)))
+                    .collect();
+                serde_json::Value::Object(object)
+            })
+            .collect()
+    }
+
+    /// Computes an aggregate function over the ages of all rows.
+    ///
+    /// `MIN` and `MAX` return the exact age; `AVG` returns the mean.
+    ///
+    /// # Arguments
+    ///
+    /// * `func` - The aggregate function to compute
+    ///
+    /// # Returns
+    ///
+    /// Returns `None` if the database is empty.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use mini_db::engine::Database;
+    /// use mini_db::query::AggregateFunc;
+    /// # let db = Database::new("mini_db.log")?;
+    /// if let Some(avg) = db.aggregate_age(AggregateFunc::Avg) {
+    ///     println!("Average age: {}", avg);
+    /// }
+    /// # Ok::<(), mini_db::errors::DbError>(())
+    /// ```
+    pub fn aggregate_age(&self, func: AggregateFunc) -> Option<f64> {
+        let ages = self.rows.iter().map(|r| r.age);
+
+        match func {
+            AggregateFunc::Min => ages.min().map(f64::from),
+            AggregateFunc::Max => ages.max().map(f64::from),
+            AggregateFunc::Avg => {
+                if self.rows.is_empty() {
+                    return None;
+                }
+                let total: u64 = ages.map(u64::from).sum();
+                Some(total as f64 / self.rows.len() as f64)
+            },
+        }
+    }
+
+    /// Counts the rows of each distinct age, in ascending order of age.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use mini_db::engine::Database;
+    /// # let db = Database::new("mini_db.log")?;
+    /// for (age, count) in db.count_by_age() {
+    ///     println!("{}: {}", age, count);
+    /// }
+    /// # Ok::<(), mini_db::errors::DbError>(())
+    /// ```
+    pub fn count_by_age(&self) -> BTreeMap<u8, usize> {
+        let mut counts = BTreeMap::new();
+        for row in &self.rows {
+            *counts.entry(row.age).or_insert(0) += 1;
+        }
+
+        counts
+    }
+
+    /// Counts the rows of each distinct name.
+    pub fn count_by_name(&self) -> HashMap<String, usize> {
+        let mut counts = HashMap::new();
+        for row in &self.rows {
+            *counts.entry(row.name.clone()).or_insert(0) += 1;
+        }
+
+        counts
+    }
+
+    /// Returns the `n` youngest (`ascending`) or oldest rows, youngest or
+    /// oldest first. Rows of equal age are ordered by ID.
+    ///
+    /// Only a heap of `n` rows is kept while scanning, so the whole table is
+    /// never sorted. If `n` exceeds the row count, every row is returned.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use mini_db::engine::Database;
+    /// # let db = Database::new("mini_db.log")?;
+    /// for row in db.top_n_by_age(5, false) {
+    ///     println!("{} ({})", row.name, row.age);
+    /// }
+    /// # Ok::<(), mini_db::errors::DbError>(())
+    /// ```
+    pub fn top_n_by_age(&self, n: usize, ascending: bool) -> Vec<Row> {
+        if n == 0 {
+            return Vec::new();
+        }
+
+        // Negating the age for descending order lets one max-heap serve
+        // both directions: its top is always the worst of the kept rows
+        let key = |row: &Row| {
+            let age = i16::from(row.age);
+            (if ascending { age } else { -age }, row.id)
+        };
+
+        let mut heap = BinaryHeap::with_capacity(n.min(self.rows.len()) + 1);
+        for (pos, row) in self.rows.iter().enumerate() {
+            heap.push((key(row), pos));
+            if heap.len() > n {
+                heap.pop();
+            }
+        }
+
+        heap.into_sorted_vec()
+            .into_iter()
+            .map(|(_, pos)| self.rows[pos].clone())
+            .collect()
+    }
+
+    /// Returns `n` rows picked at random, in `select_all` order.
+    ///
+    /// Every row is returned if the table has fewer than `n` rows. Use
+    /// [`sample_seeded`](Self::sample_seeded) for a repeatable sample.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use mini_db::engine::Database;
+    /// # let db = Database::new("mini_db.log")?;
+    /// for row in db.sample(5) {
+    ///     println!("{:?}", row);
+    /// }
+    /// # Ok::<(), mini_db::errors::DbError>(())
+    /// ```
+    pub fn sample(&self, n: usize) -> Vec<Row> {
+        self.sample_with(n, &mut StdRng::from_os_rng())
+    }
+
+    /// Like [`sample`](Self::sample), but picks the same rows every time for
+    /// the same `seed` and table.
+    pub fn sample_seeded(&self, n: usize, seed: u64) -> Vec<Row> {
+        self.sample_with(n, &mut StdRng::seed_from_u64(seed))
+    }
+
+    /// Picks `n` distinct row positions with `rng` and returns their rows.
+    fn sample_with(&self, n: usize, rng: &mut impl Rng) -> Vec<Row> {
+        let mut positions = rand::seq::index::sample(rng, self.rows.len(), n.min(self.rows.len())).into_vec();
+        positions.sort_unstable();
+
+        positions.into_iter().map(|pos| self.rows[pos].clone()).collect()
+    }
+
+    /// Returns the distinct ages present in the database, in ascending order.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use mini_db::engine::Database;
+    /// # let db = Database::new("mini_db.log")?;
+    /// println!("Ages: {:?}", db.distinct_ages());
+    /// # Ok::<(), mini_db::errors::DbError>(())
+    /// ```
+    pub fn distinct_ages(&self) -> Vec<u8> {
+        let ages: BTreeSet<u8> = self.rows.iter().map(|r| r.age).collect();
+        ages.into_iter().collect()
+    }
+
+    /// Returns the distinct names present in the database, in ascending order.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use mini_db::engine::Database;
+    /// # let db = Database::new("mini_db.log")?;
+    /// println!("Names: {:?}", db.distinct_names());
+    /// # Ok::<(), mini_db::errors::DbError>(())
+    /// ```
+    pub fn distinct_names(&self) -> Vec<String> {
+        let names: BTreeSet<&str> = self.rows.iter().map(|r| r.name.as_str()).collect();
+        names.into_iter().map(String::from).collect()
+    }
+
+    /// Returns the IDs of all rows, in the same order as `select_all`.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use mini_db::engine::Database;
+    /// # let db = Database::new("mini_db.log")?;
+    /// let ids = db.ids();
+    /// println!("Known ids: {:?}", ids);
+    /// # Ok::<(), mini_db::errors::DbError>(())
+    /// ```
+    pub fn ids(&self) -> Vec<u32> {
+        self.rows.iter().map(|r| r.id).collect()
+    }
+
+    /// Returns the IDs of all rows in ascending order, read from the index
+    /// without touching the rows.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mini_db::engine::Database;
+    /// let mut db = Database::in_memory();
+    /// db.insert(9, "Bob".to_string(), 40)?;
+    /// db.insert(2, "Alice".to_string(), 30)?;
+    /// assert_eq!(db.all_ids(), vec![2, 9]);
+    /// # Ok::<(), mini_db::errors::DbError>(())
+    /// ```
+    pub fn all_ids(&self) -> Vec<u32> {
+        self.index.iter().map(|(id, _)| id).collect()
+    }
+
+    /// Returns up to `limit` rows with an ID greater than `after`, in
+    /// ascending ID order.
+    ///
+    /// Pass `0` to start at the beginning, then the ID of the last row of
+    /// each page to fetch the next one. Unlike an offset, the cursor does
+    /// not skip or repeat rows when rows before it are inserted or deleted
+    /// between pages, and the rows are found through the index without
+    /// walking the ones before the cursor.
+    ///
+    /// # Arguments
+    ///
+    /// * `after` - The ID of the last row already seen, `0` for none
+    /// * `limit` - The largest number of rows to return
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mini_db::engine::Database;
+    /// let mut db = Database::in_memory();
+    /// for id in 1..=5 {
+    ///     db.insert(id, format!("user{id}"), 30)?;
+    /// }
+    /// let page = db.select_after_id(2, 2);
+    /// assert_eq!(page.iter().map(|r| r.id).collect::<Vec<_>>(), vec![3, 4]);
+    /// # Ok::<(), mini_db::errors::DbError>(())
+    /// ```
+    pub fn select_after_id(&self, after: u32, limit: usize) -> Vec<Row> {
+        self.index.iter_after(after)
+            .take(limit)
+            .map(|(_, pos)| self.rows[pos].clone())
+            .collect()
+    }
+
+    /// Returns the row count along with the size of the log, to help decide
+    /// when to compact.
+    ///
+    /// An in-memory database reports an empty log and no snapshot.
+    ///
+    /// # Returns
+    ///
+    /// Returns the statistics, or a `DbError` if the log cannot be read.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use mini_db::engine::Database;
+    /// # let db = Database::new("mini_db.log")?;
+    /// let stats = db.stats()?;
+    /// println!("{} rows, {} log entries", stats.row_count, stats.entry_count);
+    /// # Ok::<(), mini_db::errors::DbError>(())
+    /// ```
+    pub fn stats(&self) -> Result<DbStats, DbError> {
+        let log = match &self.storage {
+            Some(storage) => storage.log_stats()?,
+            None => LogStats::default(),
+        };
+
+        Ok(DbStats {
+            row_count: self.rows.len(),
+            log_bytes: log.log_bytes,
+            entry_count: log.entry_count,
+            snapshot_exists: log.snapshot_exists,
+        })
+    }
+
+    /// Returns the columns rows have, as `(name, type)` pairs.
+    ///
+    /// The fixed columns of [`Row`] come first, in declaration order,
+    /// followed by every extra field set on any row, sorted by name and
+    /// typed `json`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mini_db::engine::Database;
+    /// let db = Database::in_memory();
+    /// assert_eq!(db.schema()[0], ("id".to_string(), "u32".to_string()));
+    /// ```
+    pub fn schema(&self) -> Vec<(String, String)> {
+        let extra: BTreeSet<&String> = self.rows.iter()
+            .flat_map(|r| r.extra.keys())
+            .collect();
+
+        Row::COLUMNS.iter()
+            .map(|(name, ty)| (name.to_string(), ty.to_string()))
+            .chain(extra.into_iter().map(|name| (name.clone(), "json".to_string())))
+            .collect()
+    }
+
+    /// Describes how a command finds the rows it reads or changes, without
+    /// running it.
+    ///
+    /// Commands that go through the ID index are described as
+    /// `IndexLookup(...)` (or `IndexScan` for walking it in order), the
+    /// others as `FullScan` followed by what is done with the rows, e.g.
+    /// `FullScan + filter(age>30)`. Commands that touch no rows are
+    /// described as `NoRowAccess`.
+    ///
+    /// # Arguments
+    ///
+    /// * `cmd` - The command to describe
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mini_db::engine::Database;
+    /// use mini_db::parser::parse_command;
+    ///
+    /// let db = Database::in_memory();
+    /// assert_eq!(db.explain(&parse_command("SELECT WHERE ID=5")?), "IndexLookup(id=5)");
+    /// assert_eq!(db.explain(&parse_command("SELECT WHERE AGE>30")?), "FullScan + filter(age>30)");
+    /// # Ok::<(), mini_db::errors::DbError>(())
+    /// ```
+    pub fn explain(&self, cmd: &Command) -> String {
+        let id_list = |ids: &[u32]| {
+            let ids: Vec<String> = ids.iter().map(u32::to_string).collect();
+            format!("IndexLookup(id IN ({}))", ids.join(", "))
+        };
+        let full_scan = |step: String| format!("FullScan + {}", step);
+
+        match cmd {
+            Command::SelectById { id }
+            | Command::Exists { id }
+            | Command::DeleteById { id }
+            | Command::Update { id, .. }
+            | Command::Rename { id, .. }
+            | Command::Insert { id, .. }
+            | Command::Upsert { id, .. }
+            | Command::InsertIfAbsent { id, .. }
+            | Command::Rekey { old_id: id, .. } => format!("IndexLookup(id={})", id),
+            Command::SelectByIds { ids } | Command::DeleteByIds { ids } => id_list(ids),
+            Command::SelectIds => "IndexScan".to_string(),
+            Command::Select => full_scan("sort(id)".to_string()),
+            Command::SelectKeys { filter: None } => "FullScan".to_string(),
+            Command::SelectWhere { predicate }
+            | Command::SelectKeys { filter: Some(predicate) } => full_scan(format!("filter({})", predicate)),
+            Command::SelectByNameLike { pattern } => full_scan(format!("filter(name LIKE '{}')", pattern)),
+            Command::DeleteByAge { op, value } => full_scan(format!("filter(age{}{})", op.symbol(), value)),
+            Command::SelectFields { fields } => {
+                let names: Vec<&str> = fields.iter().map(Field::name).collect();
+                full_scan(format!("project({})", names.join(", ")))
+            },
+            Command::SelectDistinct { field } => full_scan(format!("distinct({})", field.name())),
+            Command::Aggregate { func, field } => {
+                full_scan(format!("{}({})", format!("{:?}", func).to_lowercase(), field.name()))
+            },
+            Command::CountGroupByAge => full_scan("group(age)".to_string()),
+            Command::CountGroupByName => full_scan("group(name)".to_string()),
+            Command::SelectTopByAge { n, ascending } => {
+                full_scan(format!("top({}, age {})", n, if *ascending { "asc" } else { "desc" }))
+            },
+            Command::Sample { n } => full_scan(format!("sample({})", n)),
+            Command::Explain { inner } => self.explain(inner),
+            _ => "NoRowAccess".to_string(),
+        }
+    }
+
+    /// Runs a read-only query command without modifying the database.
+    ///
+    /// # Arguments
+    ///
+    /// * `cmd` - The query to run (`Select`, `SelectById`, `SelectByIds`, `SelectWhere`,
+    ///   `SelectByNameLike`, `SelectKeys`, `SelectFields`, `SelectDistinct`,
+    ///   `Aggregate` over `AGE`, `CountGroupByAge`, `CountGroupByName`,
+    ///   `SelectTopByAge`, `SelectIds`, `Sample` or `Exists`)
+    ///
+    /// # Returns
+    ///
+    /// Returns the query's `CommandOutcome`, or `InvalidCommandError` if the
+    /// command is not a query.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use mini_db::engine::Database;
+    /// use mini_db::parser::{parse_command, CommandOutcome};
+    /// # let db = Database::new("mini_db.log")?;
+    /// let cmd = parse_command("SELECT KEYS WHERE AGE>30")?;
+    /// if let CommandOutcome::Keys(ids) = db.query(&cmd)? {
+    ///     println!("{:?}", ids);
+    /// }
+    /// # Ok::<(), mini_db::errors::DbError>(())
+    /// ```
+    pub fn query(&self, cmd: &Command) -> Result<CommandOutcome, DbError> {
+        match cmd {
+            Command::Select => Ok(CommandOutcome::Rows(self.select_all_sorted())),
+            Command::SelectById { id } => {
+                Ok(CommandOutcome::Rows(self.select_by_id(*id)?.into_iter().collect()))
+            },
+            Command::SelectByIds { ids } => Ok(CommandOutcome::Rows(self.select_by_ids(ids))),
+            Command::SelectWhere { predicate } => {
+                Ok(CommandOutcome::Rows(self.select_where(predicate)))
+            },
+            Command::SelectByNameLike { pattern } => {
+                Ok(CommandOutcome::Rows(self.select_by_name_like(pattern)))
+            },
+            Command::SelectKeys { filter: None } => Ok(CommandOutcome::Keys(self.ids())),
+            Command::SelectKeys { filter: Some(predicate) } => {
+                let ids = self.rows.iter()
+                    .filter(|r| predicate.matches(r))
+                    .map(|r| r.id)
+                    .collect();
+                Ok(CommandOutcome::Keys(ids))
+            },
+            Command::SelectFields { fields } => {
+                Ok(CommandOutcome::Projected(self.select_projection(fields)))
+            },
+            Command::SelectDistinct { field } => {
+                let values = match field {
+                    Field::Id => self.select_all_sorted().iter().map(|r| r.id.to_string()).collect(),
+                    Field::Name => self.distinct_names(),
+                    Field::Age => self.distinct_ages().iter().map(u8::to_string).collect(),
+                    Field::CreatedAt | Field::UpdatedAt => {
+                        let values: BTreeSet<i64> = self.rows.iter()
+                            .filter_map(|r| field.json_value(r).as_i64())
+                            .collect();
+                        values.iter().map(i64::to_string).collect()
+                    },
+                };
+                Ok(CommandOutcome::Values(values))
+            },
+            Command::Aggregate { func, field: Field::Age } => {
+                let value = self.aggregate_age(*func);
+                Ok(CommandOutcome::Values(value.iter().map(f64::to_string).collect()))
+            },
+            Command::CountGroupByAge => {
+                let counts = self.count_by_age().into_iter()
+                    .map(|(age, count)| format!("{}: {}", age, count))
+                    .collect();
+                Ok(CommandOutcome::Values(counts))
+            },
+            Command::CountGroupByName => {
+                let mut counts: Vec<(String, usize)> = self.count_by_name().into_iter().collect();
+                counts.sort();
+                let counts = counts.into_iter()
+                    .map(|(name, count)| format!("{}: {}", name, count))
+                    .collect();
+                Ok(CommandOutcome::Values(counts))
+            },
+            Command::SelectTopByAge { n, ascending } => {
+                Ok(CommandOutcome::Rows(self.top_n_by_age(*n, *ascending)))
+            },
+            Command::SelectIds => Ok(CommandOutcome::Keys(self.all_ids())),
+            Command::Sample { n } => Ok(CommandOutcome::Rows(self.sample(*n))),
+            Command::Exists { id } => {
+                Ok(CommandOutcome::Values(vec![self.contains_id(*id).to_string()]))
+            },
+            _ => Err(DbError::InvalidCommandError),
+        }
+    }
+
+    /// Reverses the most recent insert, update or delete that has not been
+    /// undone yet.
+    ///
+    /// An insert is undone by deleting the row, a delete by inserting the
+    /// removed row again and an update by restoring the row's previous
+    /// fields. The compensating entry is appended to the log like any other
+    /// write. Bulk deletes are undone one row at a time. The history only
+    /// reaches back to the last compaction or reset.
+    ///
+    /// # Returns
+    ///
+    /// Returns `Ok(true)` if a write was undone, `Ok(false)` if there was
+    /// nothing to undo, or a `DbError` if there are I/O errors writing to the
+    /// log, in which case the write stays undoable.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use mini_db::engine::Database;
+    /// # let mut db = Database::new("mini_db.log")?;
+    /// db.insert(1, "Alice".to_string(), 30)?;
+    /// assert!(db.undo()?);
+    /// assert_eq!(db.select_by_id(1)?, None);
+    /// # Ok::<(), mini_db::errors::DbError>(())
+    /// ```
+    pub fn undo(&mut self) -> Result<bool, DbError> {
+        let Some(op) = self.undo_stack.pop() else {
+            return Ok(false);
+        };
+        let depth = self.undo_stack.len();
+
+        let result = match &op {
+            UndoOp::Delete(id) => self.delete_and_return(*id).map(|_| ()),
+            UndoOp::Reinsert(row) => self.insert_row(row.clone()),
+            UndoOp::Restore(row) => match self.index.get(row.id) {
+                Some(pos) => self.replace_row(pos, row.clone()),
+                None => Ok(()),
+            },
+            UndoOp::Rekey { id, to } => self.rekey(*id, *to),
+        };
+
+        // The compensating write must not become undoable itself
+        self.undo_stack.truncate(depth);
+        if let Err(e) = result {
+            self.undo_stack.push(op);
+            return Err(e);
+        }
+
+        Ok(true)
+    }
+
+    /// Resets the database by clearing all data, truncating the log file and
+    /// deleting its segments and snapshot.
+    ///
+    /// **Warning**: This operation is irreversible and will delete all data.
+    ///
+    /// # Returns
+    ///
+    /// Returns `Ok(())` on success or a `DbError` if there are I/O errors.
+    pub fn reset_db(&mut self) -> Result<(), DbError> {
+        self.check_writable()?;
+        self.rows.clear();
+        self.index.clear();
+        self.undo_stack.clear();
+
+        // A stale snapshot would bring the old rows back on restart, so it
+        // is cleared along with the log
+        if let Some(storage) = &mut self.storage {
+            storage.reset()?;
+        }
+
+        Ok(())
+    }
+
+    /// Flushes all pending writes to disk without shutting down.
+    ///
+    /// # Returns
+    ///
+    /// Returns `Ok(())` on success or a `DbError` if syncing the log fails.
+    pub fn flush(&mut self) -> Result<(), DbError> {
+        if let Some(storage) = &mut self.storage {
+            storage.flush()?;
+        }
+
+        Ok(())
+    }
+
+    /// Returns the storage's log sink, or stderr for an in-memory database.
+    fn log_sink(&self) -> &dyn LogSink {
+        match &self.storage {
+            Some(storage) => storage.log_sink(),
+            None => &storage::StderrSink,
+        }
+    }
+
+    /// Reports a warning not tied to a log line to the log sink.
+    fn warn(&self, message: String) {
+        self.log_sink().warn(&LogWarning { line: 0, message });
+    }
+
+    /// Safely shuts down the database by flushing all pending writes to disk.
+    ///
+    /// This ensures data durability by syncing the log file before the database
+    /// is dropped or the program exits. If `snapshot_on_shutdown` is enabled,
+    /// the rows are also written to a fresh snapshot and the log is truncated.
+    ///
+    /// # Returns
+    ///
+    /// Returns `Ok(())` on success or a `DbError` if the flush or snapshot fails.
+    pub fn shutdown(&mut self) -> Result<(), DbError> {
+        self.flush()?;
+
+        if self.snapshot_on_shutdown && !self.read_only {
+            self.compact()?;
+        }
+
+        Ok(())
+    }
+
+    /// Deletes a row by its ID.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The ID of the row to delete
+    ///
+    /// # Returns
+    ///
+    /// Returns `Ok(true)` if the row was found and deleted, `Ok(false)` if the
+    /// row was not found, or a `DbError` if there are I/O errors.
+    ///
+    /// # Note
+    ///
+    /// After deletion, the index is rebuilt to maintain consistency of row positions.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use mini_db::engine::Database;
+    /// # let mut db = Database::new("mini_db.log")?;
+    /// # db.insert(1, "Alice".to_string(), 30)?;
+    /// let deleted = db.delete_by_id(1)?;
+    /// assert!(deleted);
+    /// # Ok::<(), mini_db::errors::DbError>(())
+    /// ```
+    pub fn delete_by_id(&mut self, id: u32) -> Result<bool, DbError> {
+        Ok(self.delete_and_return(id)?.is_some())
+    }
+
+    /// Deletes a row by its ID and returns the removed row.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The ID of the row to delete
+    ///
+    /// # Returns
+    ///
+    /// Returns `Ok(Some(Row))` with the removed row, `Ok(None)` if no row
+    /// has the ID, or a `DbError` if there are I/O errors.
+    ///
+    /// # Note
+    ///
+    /// After deletion, the index is rebuilt to maintain consistency of row positions.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use mini_db::engine::Database;
+    /// # let mut db = Database::new("mini_db.log")?;
+    /// # db.insert(1, "Alice".to_string(), 30)?;
+    /// if let Some(row) = db.delete_and_return(1)? {
+    ///     println!("Deleted {}", row.name);
+    /// }
+    /// # Ok::<(), mini_db::errors::DbError>(())
+    /// ```
+    pub fn delete_and_return(&mut self, id: u32) -> Result<Option<Row>, DbError> {
+        self.check_writable()?;
+        let Some(pos) = self.index.get(id) else {
+            return Ok(None);
+        };
+
+        if let Some(storage) = &mut self.storage {
+            storage.append_delete(id)?;
+        }
+        let row = self.rows.remove(pos);
+
+        // Rebuild index since positions have shifted after removal
+        self.index = IdIndex::rebuild(&self.rows);
+        self.undo_stack.push(UndoOp::Reinsert(row.clone()));
+
+        Ok(Some(row))
+    }
+
+    /// Deletes every row whose ID is in `ids`.
+    ///
+    /// A delete entry is appended to the log for each removed row; IDs with
+    /// no row are skipped.
+    ///
+    /// # Arguments
+    ///
+    /// * `ids` - The IDs of the rows to delete
+    ///
+    /// # Returns
+    ///
+    /// Returns the number of rows actually deleted, or a `DbError` if there
+    /// are I/O errors.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use mini_db::engine::Database;
+    /// # let mut db = Database::new("mini_db.log")?;
+    /// let removed = db.delete_by_ids(&[1, 2, 3])?;
+    /// println!("Removed {} rows", removed);
+    /// # Ok::<(), mini_db::errors::DbError>(())
+    /// ```
+    pub fn delete_by_ids(&mut self, ids: &[u32]) -> Result<usize, DbError> {
+        let ids: HashSet<u32> = ids.iter().copied().collect();
+        self.delete_where(|r| ids.contains(&r.id))
+    }
+
+    /// Deletes every row whose age satisfies `age <op> value`.
+    ///
+    /// # Arguments
+    ///
+    /// * `op` - The comparison to apply to each row's age
+    /// * `value` - The age to compare against
+    ///
+    /// # Returns
+    ///
+    /// Returns the number of rows deleted, or a `DbError` if there are I/O errors.
+    ///
+    /// # Note
+    ///
+    /// A delete entry is appended to the log for every removed row, so the
+    /// purge is replayed on restart.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use mini_db::engine::Database;
+    /// use mini_db::query::CompareOp;
+    /// # let mut db = Database::new("mini_db.log")?;
+    /// let removed = db.delete_where_age(CompareOp::Lt, 18)?;
+    /// println!("Removed {} minors", removed);
+    /// # Ok::<(), mini_db::errors::DbError>(())
+    /// ```
+    pub fn delete_where_age(&mut self, op: CompareOp, value: u8) -> Result<usize, DbError> {
+        self.delete_where(|r| op.compare(r.age, value))
+    }
+
+    /// Deletes every row whose insert is older than `max_age_secs`.
+    ///
+    /// Insert times are taken from the log's entry timestamps. Rows that
+    /// only exist in the snapshot have no recorded insert time and are kept.
+    /// An in-memory database uses each row's `created_at` instead. With
+    /// [`Storage::record_timestamps`] turned off no insert time is known, so
+    /// retention is a no-op and deletes nothing.
+    /// This is intended to be called periodically to enforce a retention window.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_age_secs` - How long, in seconds, a row may be kept after insertion
+    ///
+    /// # Returns
+    ///
+    /// Returns the number of rows deleted, or a `DbError` if the log cannot
+    /// be read or written.
+    pub fn enforce_retention(&mut self, max_age_secs: i64) -> Result<usize, DbError> {
+        let cutoff = unix_now() - max_age_secs;
+        let inserted_at = match &self.storage {
+            Some(storage) => storage.insert_timestamps()?,
+            None => self.rows.iter().map(|r| (r.id, r.created_at)).collect(),
+        };
+
+        self.delete_where(|r| inserted_at.get(&r.id).is_some_and(|&ts| ts < cutoff))
     }
 
-    pub fn select_all(&self) -> Vec<Row>{
-        let db = self.inner.read();
-        db.select_all().clone()
-    }
+    /// Deletes every row matching `pred`, logging one delete per row.
+    fn delete_where(&mut self, pred: impl Fn(&Row) -> bool) -> Result<usize, DbError> {
+        self.check_writable()?;
+        let ids: Vec<u32> = self.rows.iter()
+            .filter(|r| pred(r))
+            .map(|r| r.id)
+            .collect();
 
-    pub fn exec_batch(&self, path: PathBuf) -> Result<(), DbError> {
-        let db = self.inner.write();
-        db.exec_batch(path)
-    } 
+        if let Some(storage) = &mut self.storage {
+            for &id in &ids {
+                storage.append_delete(id)?;
+            }
+        }
 
-    pub fn shutdown(&self) -> Result<(), DbError> {
-        let mut db = self.inner.write();
-        db.shutdown()
-    }
+        let (removed, kept) = std::mem::take(&mut self.rows)
+            .into_iter()
+            .partition(|r| pred(r));
+        self.rows = kept;
 
-    pub fn reset_db(&self) -> Result<(), DbError> {
-        let mut db = self.inner.write();
-        db.reset_db()
+        // Rebuild index since positions have shifted after removal
+        self.index = IdIndex::rebuild(&self.rows);
+        self.undo_stack.extend(removed.into_iter().map(UndoOp::Reinsert));
+
+        Ok(ids.len())
     }
-}
 
-impl Database {
-    /// Creates a new database instance or loads an existing one from the given path.
+    /// Retrieves a row by its ID.
     ///
     /// # Arguments
     ///
-    /// * `path` - The file path where the database log is stored
+    /// * `id` - The ID of the row to retrieve
     ///
     /// # Returns
     ///
-    /// Returns a `Result` containing the database instance or a `DbError` if:
-    /// - The file cannot be created or opened
-    /// - The existing log file is corrupted
-    /// - There are I/O errors during log replay
+    /// Returns `Ok(Some(Row))` if found, `Ok(None)` if not found,
+    /// or a `DbError` if there are errors.
     ///
     /// # Examples
     ///
     /// ```no_run
-    /// use mini_db::engine::Database;
-    ///
-    /// let db = Database::new("data/mini_db.log")?;
+    /// # use mini_db::engine::Database;
+    /// # let db = Database::new("mini_db.log")?;
+    /// if let Some(row) = db.select_by_id(1)? {
+    ///     println!("Found: {} (age {})", row.name, row.age);
+    /// }
     /// # Ok::<(), mini_db::errors::DbError>(())
     /// ```
-     pub fn new(path: impl AsRef<Path>) -> Result<Self, DbError> {
-        let dir_path = PathBuf::from("data");
-        let snapshot_path = dir_path.join(path.as_ref());
-
-        if snapshot_path.exists() {
-            return Self::load_from_disk(&snapshot_path);
+    pub fn select_by_id(&self, id: u32) -> Result<Option<Row>, DbError> {
+        if let Some(pos) = self.index.get(id) {
+            Ok(Some(self.rows[pos].clone()))
+        } else {
+            Ok(None)
         }
+    }
 
-        let storage = Storage::new(path.as_ref())?;
-        let rows = storage.load_all()?;
-        let index = IdIndex::rebuild(&rows);
-
-        Ok(Self {
-            rows,
-            index,
-            storage,
-        })
-    } 
-
-   pub fn load_from_disk(path: impl AsRef<Path>) -> Result<Self, DbError> {
-        let snapshot_path = path.as_ref();
-        let storage_path = "mini_db.log";
-
-        let storage = Storage::new(storage_path)?;
-        let mut rows = match storage.snapshot_read(&snapshot_path) {
-            Ok(rows) => rows,
-            Err(_) => Vec::new(),
-        };
-
-        let mut log_rows = storage.load_all()?;
-        rows.append(&mut log_rows);
-
-        let index = IdIndex::rebuild(&rows);
-
-        Ok(Self {
-            rows,
-            index,
-            storage,
-        })    
-   }
-
-    /// Inserts a new row into the database.
+    /// Deletes a row by its ID, treating a missing row as an error.
+    ///
+    /// This is the strict counterpart of [`delete_by_id`](Self::delete_by_id)
+    /// for callers that want to propagate a missing row with `?`.
     ///
     /// # Arguments
     ///
-    /// * `id` - Unique identifier for the row (must not already exist)
-    /// * `name` - Name field for the row
-    /// * `age` - Age field for the row
+    /// * `id` - The ID of the row to delete
     ///
     /// # Returns
     ///
-    /// Returns `Ok(())` on success or a `DbError` if:
-    /// - The ID already exists (`DuplicateIdError`)
-    /// - There are I/O errors writing to the log
+    /// Returns `Ok(())` if the row was deleted, `RowNotFound` if no row has
+    /// the ID, or a `DbError` if there are I/O errors.
+    pub fn delete_by_id_strict(&mut self, id: u32) -> Result<(), DbError> {
+        if self.delete_by_id(id)? {
+            Ok(())
+        } else {
+            Err(DbError::RowNotFound(id))
+        }
+    }
+
+    /// Returns `true` if a row with the given ID exists.
+    ///
+    /// Only the index is consulted, so unlike [`select_by_id`](Self::select_by_id)
+    /// no row is cloned.
     ///
     /// # Examples
     ///
     /// ```no_run
     /// # use mini_db::engine::Database;
     /// # let mut db = Database::new("mini_db.log")?;
-    /// db.insert(1, "Alice".to_string(), 30)?;
+    /// if !db.contains_id(7) {
+    ///     db.insert(7, "Alice".to_string(), 30)?;
+    /// }
     /// # Ok::<(), mini_db::errors::DbError>(())
     /// ```
-    pub fn insert(&mut self, id: u32, name: String, age: u8) -> Result<(), DbError> {
-        // Check for duplicate IDs to maintain uniqueness constraint
-        if self.rows.iter().any(|r| r.id == id) {
-            return Err(DbError::DuplicateIdError(id));
-        }
-
-        let newly_created_row = Row::new(id, name, age);
-        self.storage.append_entry(&newly_created_row)?;
-        self.rows.push(newly_created_row);
-        self.index.insert(id, self.rows.len() - 1)?;
+    pub fn contains_id(&self, id: u32) -> bool {
+        self.index.get(id).is_some()
+    }
 
-        Ok(())
+    /// Retrieves a row by its ID, treating a missing row as an error.
+    ///
+    /// This is the strict counterpart of [`select_by_id`](Self::select_by_id).
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The ID of the row to retrieve
+    ///
+    /// # Returns
+    ///
+    /// Returns the row, or `RowNotFound` if no row has the ID.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use mini_db::engine::Database;
+    /// # let db = Database::new("mini_db.log")?;
+    /// let row = db.select_by_id_strict(1)?;
+    /// println!("Found: {}", row.name);
+    /// # Ok::<(), mini_db::errors::DbError>(())
+    /// ```
+    pub fn select_by_id_strict(&self, id: u32) -> Result<Row, DbError> {
+        self.select_by_id(id)?.ok_or(DbError::RowNotFound(id))
     }
 
-    /// Executes a batch of commands from a text file.
+    /// Gets the internal index position for a given ID.
     ///
-    /// Each line in the file should contain a valid database command.
+    /// This method is primarily used for testing to verify index correctness.
     ///
     /// # Arguments
     ///
-    /// * `path` - Path to the file containing batch commands
+    /// * `id` - The ID to look up in the index
     ///
     /// # Returns
     ///
-    /// Returns `Ok(())` on success or a `DbError` if:
-    /// - The file does not exist
-    /// - There are I/O errors reading the file
-    /// - Any command in the batch fails
-    pub fn exec_batch(&self, path: PathBuf) -> Result<(), DbError> {
-        if !path.exists() {
-            return Err(DbError::IoError(std::io::Error::new(
-                std::io::ErrorKind::NotFound,
-                format!("Batch file not found: {}", path.display())
-            )));
-        }
+    /// Returns `Some(position)` if the ID exists in the index, `None` otherwise.
+    pub fn get_index_position(&self, id: u32) -> Option<usize> {
+        self.index.get(id)
+    }
 
-        let file = fs::File::open(&path)?;
-        let reader = BufReader::new(file);
+    /// Verifies that the ID index agrees with the rows.
+    ///
+    /// Every row's ID must be unique and map to the row's position, and
+    /// every index entry must point at a row with that ID.
+    ///
+    /// # Returns
+    ///
+    /// Returns `Ok(())` if the index is consistent, or an `IntegrityError`
+    /// describing the first inconsistency found.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use mini_db::engine::Database;
+    /// let mut db = Database::in_memory();
+    /// db.insert(1, "Alice".to_string(), 30)?;
+    /// db.check_integrity()?;
+    /// # Ok::<(), mini_db::errors::DbError>(())
+    /// ```
+    pub fn check_integrity(&self) -> Result<(), DbError> {
+        let mut seen: HashMap<u32, usize> = HashMap::with_capacity(self.rows.len());
 
-        let path = "mini_db.snapshot";
-        
-        for line in reader.lines() {
-            let line = line?;
-            parser::handle_command(&line, &DatabaseHandle::new(path)?);
+        for (pos, row) in self.rows.iter().enumerate() {
+            if let Some(first) = seen.insert(row.id, pos) {
+                return Err(DbError::IntegrityError(format!(
+                    "id {} appears at positions {} and {}", row.id, first, pos
+                )));
+            }
+
+            match self.index.get(row.id) {
+                Some(indexed) if indexed == pos => {},
+                Some(indexed) => return Err(DbError::IntegrityError(format!(
+                    "index maps id {} to position {}, but the row is at position {}", row.id, indexed, pos
+                ))),
+                None => return Err(DbError::IntegrityError(format!(
+                    "row {} at position {} is missing from the index", row.id, pos
+                ))),
+            }
+        }
+
+        // Every row is indexed correctly, so any extra entry is dangling
+        if self.index.len() != self.rows.len()
+            && let Some((id, pos)) = self.index.iter().find(|(id, _)| !seen.contains_key(id))
+        {
+            return Err(DbError::IntegrityError(format!(
+                "index entry for id {} points to position {}, which holds no such row", id, pos
+            )));
         }
 
         Ok(())
     }
 
-    /// Returns a reference to all rows in the database.
+    pub fn should_compact(&self) -> bool {
+        // Compacts every 50k rows 
+        self.rows.len() >= COMPACT_EVERY && self.rows.len().is_multiple_of(COMPACT_EVERY)
+    }
+
+    /// Rewrites the log with only the final state of each row.
+    ///
+    /// A lighter alternative to [`compact`](Self::compact) that writes no
+    /// snapshot; see [`Storage::vacuum`]. The in-memory rows are unchanged.
     ///
     /// # Returns
     ///
-    /// A reference to the vector containing all rows in insertion order
-    /// (accounting for deletions).
+    /// Returns the number of superseded log entries removed, or a `DbError`
+    /// if the log cannot be rewritten.
     ///
     /// # Examples
     ///
     /// ```no_run
     /// # use mini_db::engine::Database;
-    /// # let db = Database::new("mini_db.log")?;
-    /// let all_rows = db.select_all();
-    /// println!("Total rows: {}", all_rows.len());
+    /// # let mut db = Database::new("mini_db.log")?;
+    /// let removed = db.vacuum()?;
+    /// println!("Dropped {} superseded entries", removed);
     /// # Ok::<(), mini_db::errors::DbError>(())
     /// ```
-    pub fn select_all(&self) -> &Vec<Row> {
-        &self.rows
+    pub fn vacuum(&mut self) -> Result<usize, DbError> {
+        self.check_writable()?;
+        match &mut self.storage {
+            Some(storage) => storage.vacuum(),
+            None => Ok(0),
+        }
     }
 
-    /// Resets the database by clearing all data and truncating the log file.
+    /// Writes every row to a single portable backup file.
     ///
-    /// **Warning**: This operation is irreversible and will delete all data.
+    /// Unlike the log and snapshot, the backup is a point-in-time dump that
+    /// can be moved elsewhere and loaded with [`restore_from`](Self::restore_from).
+    /// See [`storage::write_backup`](crate::storage::write_backup).
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Where to write the backup
     ///
     /// # Returns
     ///
-    /// Returns `Ok(())` on success or a `DbError` if there are I/O errors.
-    pub fn reset_db(&mut self) -> Result<(), DbError> {
-        self.rows.clear();
-        self.index.clear();
-
-        let path = &self.storage.path;
-        // Truncate the file by recreating it
-        File::create(path)?;
-
-        Ok(())
+    /// Returns `Ok(())` on success or a `DbError` if the file cannot be written.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use mini_db::engine::Database;
+    /// # let db = Database::new("mini_db.log")?;
+    /// db.backup_to("backups/2024-01-01.json")?;
+    /// # Ok::<(), mini_db::errors::DbError>(())
+    /// ```
+    pub fn backup_to(&self, path: impl AsRef<Path>) -> Result<(), DbError> {
+        storage::write_backup(path.as_ref(), &self.rows)
     }
 
-    /// Safely shuts down the database by flushing all pending writes to disk.
+    /// Replaces every row with the rows of a backup written by
+    /// [`backup_to`](Self::backup_to).
     ///
-    /// This ensures data durability by syncing the log file before the database
-    /// is dropped or the program exits.
+    /// The backup is read and checked before anything is changed. The log,
+    /// its segments and the snapshot are then cleared and the restored rows
+    /// written to a fresh snapshot. The undo history is cleared.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The backup file to load
     ///
     /// # Returns
     ///
-    /// Returns `Ok(())` on success or a `DbError` if the flush operation fails.
-    pub fn shutdown(&mut self) -> Result<(), DbError> {
-        self.storage.flush()?;
+    /// Returns the number of restored rows, or a `DbError` if:
+    /// - The backup cannot be read or has an unsupported format
+    /// - The backup holds the same ID twice (`DuplicateIdError`)
+    /// - There are I/O errors writing the snapshot
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use mini_db::engine::Database;
+    /// let mut db = Database::new("restored.log")?;
+    /// let rows = db.restore_from("backups/2024-01-01.json")?;
+    /// println!("Restored {} rows", rows);
+    /// # Ok::<(), mini_db::errors::DbError>(())
+    /// ```
+    pub fn restore_from(&mut self, path: impl AsRef<Path>) -> Result<usize, DbError> {
+        self.check_writable()?;
+        let rows = storage::read_backup(path.as_ref())?;
 
-        Ok(())
+        let mut index = IdIndex::new();
+        for (pos, row) in rows.iter().enumerate() {
+            index.insert(row.id, pos)?;
+        }
+
+        if let Some(storage) = &mut self.storage {
+            storage.reset()?;
+            storage.snapshot_write(&rows)?;
+        }
+        self.rows = rows;
+        self.index = index;
+        self.undo_stack.clear();
+
+        Ok(self.rows.len())
     }
 
-    /// Deletes a row by its ID.
+    /// Applies the entries of a JSON Lines file in the log's format, such as
+    /// another instance's log, to this database.
+    ///
+    /// Entries are applied in file order with the same rules as log replay:
+    /// an insert or update of an ID that has a row replaces that row, an
+    /// insert or update of a new ID inserts it and a delete removes the row
+    /// if there is one. Each applied entry is logged like a regular write.
+    /// Lines that cannot be parsed are skipped, counted and reported to the
+    /// storage's [`LogSink`](storage::LogSink) (stderr for an in-memory
+    /// database).
     ///
     /// # Arguments
     ///
-    /// * `id` - The ID of the row to delete
+    /// * `path` - The JSON Lines file to import
     ///
     /// # Returns
     ///
-    /// Returns `Ok(true)` if the row was found and deleted, `Ok(false)` if the
-    /// row was not found, or a `DbError` if there are I/O errors.
-    ///
-    /// # Note
-    ///
-    /// After deletion, the index is rebuilt to maintain consistency of row positions.
+    /// Returns the number of entries applied and lines skipped, or a `DbError` if:
+    /// - The file cannot be read or has an unsupported format version
+    /// - A row's name or age is invalid (`NameTooLong`, `AgeOutOfRange`) or
+    ///   the database is full (`CapacityExceeded`), in which case the
+    ///   entries before it remain applied
+    /// - There are I/O errors writing to the log
     ///
     /// # Examples
     ///
     /// ```no_run
     /// # use mini_db::engine::Database;
     /// # let mut db = Database::new("mini_db.log")?;
-    /// # db.insert(1, "Alice".to_string(), 30)?;
-    /// let deleted = db.delete_by_id(1)?;
-    /// assert!(deleted);
+    /// let report = db.import_jsonl("data/other_instance.log")?;
+    /// println!("Imported {} entries, skipped {} lines", report.applied, report.skipped);
     /// # Ok::<(), mini_db::errors::DbError>(())
     /// ```
-    pub fn delete_by_id(&mut self, id: u32) -> Result<bool, DbError> {
-        if let Some(pos) = self.index.get(id) {
-            self.index.remove(id);
-            self.storage.append_delete(id)?;
-            self.rows.remove(pos);
+    pub fn import_jsonl(&mut self, path: impl AsRef<Path>) -> Result<ImportReport, DbError> {
+        self.check_writable()?;
+        let (entries, skipped) = storage::read_entry_file(path.as_ref(), self.log_sink())?;
 
-            // Rebuild index since positions have shifted after removal
-            let index = IdIndex::rebuild(&self.rows);
-            self.index = index;
-
-            return Ok(true);
+        let report = ImportReport { applied: entries.len(), skipped };
+        for entry in entries {
+            match entry {
+                LogEntry::Insert { row, .. } | LogEntry::Update { row, .. } => match self.index.get(row.id) {
+                    Some(pos) => {
+                        self.validate_name(&row.name)?;
+                        self.validate_age(row.age)?;
+                        self.replace_row(pos, row)?;
+                    },
+                    None => self.insert_row(row)?,
+                },
+                LogEntry::Delete { id, .. } => {
+                    self.delete_by_id(id)?;
+                },
+            }
         }
-        Ok(false)
+
+        Ok(report)
     }
 
-    /// Retrieves a row by its ID.
+    /// Merges the rows of another database file into this one, keeping this
+    /// database's row wherever both have the same ID.
+    ///
+    /// See [`merge_from_with`](Self::merge_from_with) to let the newer row
+    /// win a conflict instead.
     ///
     /// # Arguments
     ///
-    /// * `id` - The ID of the row to retrieve
+    /// * `other_path` - The log of the database to merge in
     ///
     /// # Returns
     ///
-    /// Returns `Ok(Some(Row))` if found, `Ok(None)` if not found,
-    /// or a `DbError` if there are errors.
+    /// Returns how many rows were inserted and skipped, or a `DbError` as
+    /// for [`merge_from_with`](Self::merge_from_with).
     ///
     /// # Examples
     ///
     /// ```no_run
     /// # use mini_db::engine::Database;
-    /// # let db = Database::new("mini_db.log")?;
-    /// if let Some(row) = db.select_by_id(1)? {
-    ///     println!("Found: {} (age {})", row.name, row.age);
-    /// }
+    /// # let mut db = Database::new("mini_db.log")?;
+    /// let report = db.merge_from("shard_2.log")?;
+    /// println!("{} inserted, {} skipped", report.inserted, report.skipped);
     /// # Ok::<(), mini_db::errors::DbError>(())
     /// ```
-    pub fn select_by_id(&self, id: u32) -> Result<Option<Row>, DbError> {
-        if let Some(pos) = self.index.get(id) {
-            Ok(Some(self.rows[pos].clone()))
-        } else {
-            Ok(None)
-        }
+    pub fn merge_from(&mut self, other_path: impl AsRef<Path>) -> Result<MergeReport, DbError> {
+        self.merge_from_with(other_path, MergePolicy::KeepPrimary)
     }
 
-    /// Gets the internal index position for a given ID.
+    /// Merges the rows of another database file into this one.
     ///
-    /// This method is primarily used for testing to verify index correctness.
+    /// The other database is opened read-only, so it may still be open
+    /// elsewhere. Rows with a new ID are inserted; a row whose ID already
+    /// exists is resolved by `policy`. Rows merged before a failing one
+    /// stay applied.
     ///
     /// # Arguments
     ///
-    /// * `id` - The ID to look up in the index
+    /// * `other_path` - The log of the database to merge in
+    /// * `policy` - Which row wins when both databases have the same ID
     ///
     /// # Returns
     ///
-    /// Returns `Some(position)` if the ID exists in the index, `None` otherwise.
-    pub fn get_index_position(&self, id: u32) -> Option<usize> {
-        self.index.get(id)
-    }
+    /// Returns how many rows were inserted, skipped and replaced, or a
+    /// `DbError` if:
+    /// - The database is read-only (`ReadOnly`)
+    /// - The other database does not exist or cannot be read
+    /// - A merged row has an invalid name or age, or does not fit
+    /// - There are I/O errors writing to the log
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use mini_db::engine::{Database, MergePolicy};
+    /// # let mut db = Database::new("mini_db.log")?;
+    /// let report = db.merge_from_with("shard_2.log", MergePolicy::PreferNewer)?;
+    /// println!("{} replaced", report.replaced);
+    /// # Ok::<(), mini_db::errors::DbError>(())
+    /// ```
+    pub fn merge_from_with(&mut self, other_path: impl AsRef<Path>, policy: MergePolicy) -> Result<MergeReport, DbError> {
+        self.check_writable()?;
+        let mut other = Database::open_readonly(other_path)?;
 
-    pub fn should_compact(&self) -> bool {
-        // Compacts every 50k rows 
-        self.rows.len() >= 50_000 && self.rows.len() % 50_000 == 0
+        let mut report = MergeReport::default();
+        for row in std::mem::take(&mut other.rows) {
+            match self.index.get(row.id) {
+                None => {
+                    self.insert_row(row)?;
+                    report.inserted += 1;
+                },
+                Some(pos) if policy == MergePolicy::PreferNewer && row.updated_at > self.rows[pos].updated_at => {
+                    self.validate_name(&row.name)?;
+                    self.validate_age(row.age)?;
+                    self.replace_row(pos, row)?;
+                    report.replaced += 1;
+                },
+                Some(_) => report.skipped += 1,
+            }
+        }
+
+        Ok(report)
     }
 
-    pub fn compact(&mut self) -> Result<(), DbError> {
-        let data_dir = PathBuf::from("data");
+    /// Writes every row to a fresh snapshot and truncates the log, along
+    /// with its rolled segments. The undo history is cleared.
+    ///
+    /// # Returns
+    ///
+    /// Returns the number of rows written to the snapshot (`0` for an
+    /// in-memory database), or a `DbError` if the snapshot cannot be written
+    /// or the log cannot be truncated.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use mini_db::engine::Database;
+    /// # let mut db = Database::new("mini_db.log")?;
+    /// let rows = db.compact()?;
+    /// println!("Snapshotted {} rows", rows);
+    /// # Ok::<(), mini_db::errors::DbError>(())
+    /// ```
+    pub fn compact(&mut self) -> Result<usize, DbError> {
+        self.check_writable()?;
+        let Some(storage) = &mut self.storage else {
+            return Ok(0);
+        };
 
-        self.storage.snapshot_write(&self.rows, &data_dir)?;
+        // The snapshot covers the whole log, rolled segments included
+        storage.snapshot_write(&self.rows)?;
+        storage.log_truncate()?;
+        self.undo_stack.clear();
 
-        let log_path = PathBuf::from("data/mini_db.log");
-        self.storage.log_truncate(&log_path)?;
+        Ok(self.rows.len())
+    }
+}
 
-        Ok(())
+impl Drop for Database {
+    /// Flushes the storage on a best-effort basis, so a database dropped
+    /// without [`shutdown`](Database::shutdown) keeps its writes. Errors are
+    /// reported to the storage's log sink.
+    fn drop(&mut self) {
+        if let Err(e) = self.flush() {
+            self.warn(format!("could not flush data: {}", e));
+        }
     }
-}
\ No newline at end of file
+}
@@ -11,6 +11,17 @@ use thiserror::Error;
 /// This enum covers all failure modes including:
 /// - Command parsing errors
 /// - Constraint violations (duplicate IDs)
+/// - Missing rows (for the strict lookup methods)
+/// - Field validation failures (overlong names, out-of-range ages)
+/// - Inserts past a database's row capacity
+/// - Files written in an unsupported format version
+/// - Missing data directories that cannot be created
+/// - Writes to a database opened read-only
+/// - Databases already opened by another process
+/// - An in-memory index out of step with the rows
+/// - Corrupt log lines read under a strict replay policy
+/// - Failing lines of a batch file run in strict mode
+/// - Batch files that run themselves
 /// - I/O failures
 /// - Serialization/deserialization errors
 #[derive(Error, Debug)]
@@ -23,6 +34,101 @@ pub enum DbError {
     #[error("Duplicate id {0}")]
     DuplicateIdError(u32),
 
+    /// Returned by strict lookups when no row has the requested ID
+    #[error("Row with id {0} not found")]
+    RowNotFound(u32),
+
+    /// Returned when a name exceeds the database's maximum name length
+    #[error("Name is {len} characters long, the maximum is {max}")]
+    NameTooLong {
+        /// Length of the rejected name in characters
+        len: usize,
+        /// The configured maximum length
+        max: usize,
+    },
+
+    /// Returned when an age falls outside the database's valid age range
+    #[error("Age {value} is outside the valid range {min}-{max}")]
+    AgeOutOfRange {
+        /// The rejected age
+        value: u8,
+        /// The smallest accepted age
+        min: u8,
+        /// The largest accepted age
+        max: u8,
+    },
+
+    /// Returned when an insert would grow the database past its capacity
+    /// under `EvictionPolicy::Reject`
+    #[error("Database is full: capacity of {capacity} rows reached")]
+    CapacityExceeded {
+        /// The configured maximum number of rows
+        capacity: usize,
+    },
+
+    /// Returned when a log or snapshot was written in a format version this
+    /// build cannot read
+    #[error("Unsupported file format version {found}, expected at most {expected}")]
+    UnsupportedVersion {
+        /// Version found in the file's header
+        found: u32,
+        /// The newest version this build understands
+        expected: u32,
+    },
+
+    /// Returned when the directory holding the database files cannot be created
+    #[error("Could not create data directory {}: {source}", .path.display())]
+    DataDirError {
+        /// The directory that could not be created
+        path: std::path::PathBuf,
+        /// The underlying I/O error
+        source: std::io::Error,
+    },
+
+    /// Returned when a write is attempted on a database opened read-only
+    #[error("Database is opened read-only")]
+    ReadOnly,
+
+    /// Returned when opening a log whose lock file is held by another open
+    /// storage, usually in another process
+    #[error("Database {} is already opened by another process", .path.display())]
+    AlreadyLocked {
+        /// The log that is locked
+        path: std::path::PathBuf,
+    },
+
+    /// Returned by integrity checks when the index disagrees with the rows
+    #[error("Integrity check failed: {0}")]
+    IntegrityError(String),
+
+    /// Returned when a log line cannot be read and the storage's replay
+    /// policy is `ReplayPolicy::Strict`
+    #[error("Corrupt log line {line}: {detail}")]
+    CorruptLog {
+        /// Number of the offending line within its file, starting at 1
+        line: usize,
+        /// The file holding the line, then why the line could not be read
+        detail: String,
+    },
+
+    /// Returned by a strict batch run for the first line that fails, and
+    /// listed for every failing line in a lenient run's report
+    #[error("line {line}: {source}")]
+    BatchLineError {
+        /// The 1-based line number in the batch file
+        line: usize,
+        /// The parse or execution error of that line
+        source: Box<DbError>,
+    },
+
+    /// Returned when a batch file runs, directly or through nested batches,
+    /// a batch file that is already running
+    #[error("Batch file {} is already running", .path.display())]
+    RecursiveBatch {
+        /// The batch file that would be re-entered
+        path: std::path::PathBuf,
+    },
+
     /// Returned when parsing input data fails
     #[error("Failed to parse input: {0}")]
     ParseError(String),
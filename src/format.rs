@@ -0,0 +1,98 @@
+//! Rendering of query results in the interactive shell.
+//!
+//! The shell prints rows in one of several formats, chosen with
+//! `FORMAT TABLE|JSON|DEBUG`:
+//!
+//! - `DEBUG` (the default): one row per line using its `Debug` form
+//! - `TABLE`: aligned `id`, `name` and `age` columns under a header
+//! - `JSON`: a single JSON array of the rows
+
+use crate::model::Row;
+
+/// How the shell renders the rows returned by a query.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutputFormat {
+    /// Aligned columns under a header
+    Table,
+    /// A JSON array of rows
+    Json,
+    /// One `Debug`-formatted row per line
+    #[default]
+    Debug,
+}
+
+impl OutputFormat {
+    /// Looks up a format by its (case-insensitive) name.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "table" => Some(OutputFormat::Table),
+            "json" => Some(OutputFormat::Json),
+            "debug" => Some(OutputFormat::Debug),
+            _ => None,
+        }
+    }
+}
+
+/// Renders rows in the given format, without a trailing newline.
+///
+/// # Examples
+///
+/// ```
+/// use mini_db::format::{format_rows, OutputFormat};
+/// use mini_db::model::Row;
+///
+/// let rows = vec![Row::new(1, "Alice".into(), 30)];
+/// let table = format_rows(&rows, OutputFormat::Table);
+/// assert_eq!(table, "id | name  | age\n---+-------+----\n1  | Alice | 30");
+/// ```
+pub fn format_rows(rows: &[Row], format: OutputFormat) -> String {
+    match format {
+        OutputFormat::Table => format_table(rows),
+        OutputFormat::Json => format_json(rows),
+        OutputFormat::Debug => rows.iter()
+            .map(|row| format!("{:?}", row))
+            .collect::<Vec<_>>()
+            .join("\n"),
+    }
+}
+
+/// Renders rows as a JSON array of objects.
+fn format_json(rows: &[Row]) -> String {
+    let values: Vec<serde_json::Value> = rows.iter()
+        .map(|row| serde_json::json!({ "id": row.id, "name": row.name, "age": row.age }))
+        .collect();
+
+    serde_json::Value::Array(values).to_string()
+}
+
+/// Renders rows as left-aligned columns padded to their widest cell.
+fn format_table(rows: &[Row]) -> String {
+    let header = ["id", "name", "age"];
+    let cells: Vec<[String; 3]> = rows.iter()
+        .map(|row| [row.id.to_string(), row.name.clone(), row.age.to_string()])
+        .collect();
+
+    let mut widths = header.map(str::len);
+    for row in &cells {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let render = |row: [&str; 3]| -> String {
+        let padded: Vec<String> = row.iter()
+            .zip(widths)
+            .map(|(cell, width)| format!("{:<width$}", cell))
+            .collect();
+        padded.join(" | ").trim_end().to_string()
+    };
+
+    let separator: Vec<String> = widths.iter().map(|width| "-".repeat(*width)).collect();
+
+    let mut lines = vec![render(header), separator.join("-+-")];
+    for row in &cells {
+        lines.push(render([row[0].as_str(), row[1].as_str(), row[2].as_str()]));
+    }
+
+    lines.join("\n")
+}
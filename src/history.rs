@@ -0,0 +1,93 @@
+//! Command history for the interactive shell.
+//!
+//! This module implements a fixed-capacity ring buffer of previously entered
+//! commands, backing the `HISTORY` and `HISTORY REPLAY <n>` commands.
+//!
+//! Commands are numbered from 1 in the order they were entered. Numbers keep
+//! counting up once the buffer wraps around, so `HISTORY REPLAY <n>` always
+//! refers to the same command until it falls out of the buffer.
+
+use std::collections::VecDeque;
+
+/// Default number of commands remembered by the shell.
+pub const DEFAULT_HISTORY_CAPACITY: usize = 100;
+
+/// A ring buffer of the most recently entered commands.
+///
+/// # Examples
+///
+/// ```
+/// use mini_db::history::History;
+///
+/// let mut history = History::new(2);
+/// history.push("INSERT 1 Alice 30");
+/// history.push("SELECT");
+/// history.push("SELECT KEYS");
+///
+/// // The oldest command was evicted, but numbering is unchanged
+/// assert_eq!(history.get(1), None);
+/// assert_eq!(history.get(3), Some("SELECT KEYS"));
+/// ```
+pub struct History {
+    /// Remembered commands, oldest first
+    entries: VecDeque<String>,
+    /// Maximum number of remembered commands
+    capacity: usize,
+    /// Number of commands evicted so far, i.e. the number of the oldest entry minus one
+    evicted: usize,
+}
+
+impl History {
+    /// Creates an empty history remembering at most `capacity` commands.
+    pub fn new(capacity: usize) -> Self {
+        History {
+            entries: VecDeque::with_capacity(capacity),
+            capacity,
+            evicted: 0,
+        }
+    }
+
+    /// Records a command, evicting the oldest one if the buffer is full.
+    pub fn push(&mut self, command: impl Into<String>) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+            self.evicted += 1;
+        }
+
+        self.entries.push_back(command.into());
+    }
+
+    /// Returns the command with number `n`, if it is still remembered.
+    pub fn get(&self, n: usize) -> Option<&str> {
+        let pos = n.checked_sub(self.evicted + 1)?;
+        self.entries.get(pos).map(String::as_str)
+    }
+
+    /// Returns the remembered commands with their numbers, oldest first.
+    pub fn iter(&self) -> impl Iterator<Item = (usize, &str)> {
+        self.entries
+            .iter()
+            .enumerate()
+            .map(|(pos, command)| (self.evicted + pos + 1, command.as_str()))
+    }
+
+    /// Returns the number of remembered commands.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns `true` if no commands are remembered.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self::new(DEFAULT_HISTORY_CAPACITY)
+    }
+}
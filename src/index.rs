@@ -1,31 +1,54 @@
 //! Index module providing fast ID-based lookups.
 //!
-//! This module implements a hash-based index that maps row IDs to their
-//! positions in the database's row vector, enabling O(1) lookups by ID.
+//! This module implements an ordered index that maps row IDs to their
+//! positions in the database's row vector, enabling O(log n) lookups by ID
+//! and walks over IDs in ascending order.
+//!
+//! The index is generic over its key type, which defaults to the `u32` used
+//! for row IDs; any `Ord + Copy` type such as `u64` or `i64` works.
 
-use std::collections::HashMap;
+use std::collections::BTreeMap;
+use std::collections::btree_map::Entry;
+use std::ops::Bound;
 use crate::model::Row;
 use crate::errors::DbError;
 
-/// A hash-based index mapping row IDs to their positions in the database.
+/// An ordered index mapping row IDs to their positions in the database.
 ///
-/// The index provides O(1) lookups for retrieving rows by their unique ID.
+/// The index provides O(log n) lookups for retrieving rows by their unique
+/// ID, and keeps IDs sorted so ranges of them can be walked in order.
 /// It must be kept in sync with the actual row storage, and is typically
 /// rebuilt after operations that change row positions (like deletions).
-pub struct IdIndex {
+///
+/// # Examples
+///
+/// ```
+/// use mini_db::index::IdIndex;
+///
+/// let mut index: IdIndex<u64> = IdIndex::new();
+/// assert!(index.try_insert(5_000_000_000, 0));
+/// assert_eq!(index.get(5_000_000_000), Some(0));
+/// ```
+pub struct IdIndex<K = u32> {
     /// Maps row ID -> position in the rows vector
-    row_map: HashMap<u32, usize>
+    row_map: BTreeMap<K, usize>
 }
 
-impl IdIndex {
+impl<K: Ord + Copy> Default for IdIndex<K> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: Ord + Copy> IdIndex<K> {
     /// Creates a new, empty index.
     pub fn new() -> Self {
         IdIndex {
-            row_map: HashMap::new()
+            row_map: BTreeMap::new()
         }
     }
 
-    /// Inserts a new ID-to-position mapping into the index.
+    /// Inserts a new ID-to-position mapping unless the ID is already indexed.
     ///
     /// # Arguments
     ///
@@ -34,13 +57,15 @@ impl IdIndex {
     ///
     /// # Returns
     ///
-    /// Returns `Ok(())` on success or `DuplicateIdError` if the ID already exists.
-    pub fn insert(&mut self, id: u32, position: usize) -> Result<(), DbError> {
-        if self.row_map.contains_key(&id) {
-            return Err(DbError::DuplicateIdError(id));
-        } else {
-            self.row_map.insert(id, position);
-            return Ok(());
+    /// Returns `true` if the mapping was added, or `false` (leaving the
+    /// index unchanged) if the ID already exists.
+    pub fn try_insert(&mut self, id: K, position: usize) -> bool {
+        match self.row_map.entry(id) {
+            Entry::Occupied(_) => false,
+            Entry::Vacant(entry) => {
+                entry.insert(position);
+                true
+            }
         }
     }
 
@@ -52,7 +77,7 @@ impl IdIndex {
     /// # Arguments
     ///
     /// * `id` - The ID to remove from the index
-    pub fn remove(&mut self, id: u32)  {
+    pub fn remove(&mut self, id: K)  {
         self.row_map.remove(&id);
     }
 
@@ -65,16 +90,73 @@ impl IdIndex {
     /// # Returns
     ///
     /// Returns `Some(position)` if the ID exists, `None` otherwise.
-    pub fn get(&self, id: u32) -> Option<usize> {
+    pub fn get(&self, id: K) -> Option<usize> {
         self.row_map.get(&id).copied()
     }
 
+    /// Returns every ID-to-position mapping, in ascending ID order.
+    pub fn iter(&self) -> impl Iterator<Item = (K, usize)> + '_ {
+        self.row_map.iter().map(|(&id, &position)| (id, position))
+    }
+
+    /// Returns the ID-to-position mappings of every ID greater than `after`,
+    /// in ascending ID order.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use mini_db::index::IdIndex;
+    ///
+    /// let mut index = IdIndex::new();
+    /// for (position, id) in [7, 3, 5].into_iter().enumerate() {
+    ///     index.insert(id, position)?;
+    /// }
+    /// let ids: Vec<u32> = index.iter_after(3).map(|(id, _)| id).collect();
+    /// assert_eq!(ids, vec![5, 7]);
+    /// # Ok::<(), mini_db::errors::DbError>(())
+    /// ```
+    pub fn iter_after(&self, after: K) -> impl Iterator<Item = (K, usize)> + '_ {
+        self.row_map
+            .range((Bound::Excluded(after), Bound::Unbounded))
+            .map(|(&id, &position)| (id, position))
+    }
+
+    /// Returns the number of indexed IDs.
+    pub fn len(&self) -> usize {
+        self.row_map.len()
+    }
+
+    /// Returns `true` if no IDs are indexed.
+    pub fn is_empty(&self) -> bool {
+        self.row_map.is_empty()
+    }
+
     /// Clears all entries from the index.
     ///
     /// This is typically used when resetting the database.
     pub fn clear(&mut self) {
         self.row_map.clear();
     }
+}
+
+impl IdIndex<u32> {
+    /// Inserts a new ID-to-position mapping into the index.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The unique row ID
+    /// * `position` - The position of the row in the storage vector
+    ///
+    /// # Returns
+    ///
+    /// Returns `Ok(())` on success or `DuplicateIdError` if the ID already exists.
+    pub fn insert(&mut self, id: u32, position: usize) -> Result<(), DbError> {
+        if self.try_insert(id, position) {
+            Ok(())
+        } else {
+            Err(DbError::DuplicateIdError(id))
+        }
+    }
 
     /// Rebuilds the index from a vector of rows.
     ///
@@ -89,8 +171,8 @@ impl IdIndex {
     /// # Returns
     ///
     /// A new `IdIndex` containing mappings for all rows.
-    pub fn rebuild(rows: &Vec<Row>) -> Self {
-        let mut row_map: HashMap<u32, usize> = HashMap::new();
+    pub fn rebuild(rows: &[Row]) -> Self {
+        let mut row_map: BTreeMap<u32, usize> = BTreeMap::new();
 
         for (index, row) in rows.iter().enumerate() {
             row_map.insert(row.id, index);
@@ -100,6 +182,4 @@ impl IdIndex {
             row_map
         }
     }
-    
-     
 }
\ No newline at end of file
@@ -19,10 +19,16 @@
 //! - `parser`: Command parsing and execution
 //! - `storage`: Persistence layer with append-only log
 //! - `index`: In-memory indexing for fast lookups
+//! - `query`: Comparison operators used by filtering commands
+//! - `history`: Ring buffer of commands entered in the shell
+//! - `format`: Rendering of rows as a table, JSON or debug output
 
 pub mod model;
 pub mod engine;
 pub mod errors;
 pub mod parser;
 pub mod storage;
-pub mod index;
\ No newline at end of file
+pub mod index;
+pub mod query;
+pub mod history;
+pub mod format;
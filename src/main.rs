@@ -1,25 +1,26 @@
-use std::io::{self, Write};
+use std::io::{self, IsTerminal};
 use mini_db::engine::{DatabaseHandle};
-use mini_db::parser::handle_command;
+use mini_db::parser::run_repl;
 
 fn main() {
 
-    let path = "mini_db.snapshot";
+    let path = "mini_db.log";
     let db = DatabaseHandle::new(path).expect("Failed to initialize db.");
-      
 
-    loop {
-        print!("mini_db> ");
-        io::stdout().flush().unwrap();
-    
-        let mut input = String::new();
-        std::io::stdin().read_line(&mut input).unwrap();
-        
-        if !handle_command(&input, &db) {
-            break;
+    // Flush to disk on Ctrl-C instead of dying with unsynced writes
+    let signal_db = db.clone();
+    ctrlc::set_handler(move || {
+        if let Err(e) = signal_db.shutdown() {
+            eprintln!("Warning: could not flush data: {}", e);
         }
-    }
-}
-
+        println!("\nExiting mini_db... Goodbye!");
+        std::process::exit(0);
+    }).expect("Failed to install Ctrl-C handler.");
 
+    // Piped scripts run without prompts
+    let interactive = io::stdin().is_terminal();
 
+    if let Err(e) = run_repl(io::stdin().lock(), io::stdout(), &db, interactive) {
+        eprintln!("Error: {}", e);
+    }
+}
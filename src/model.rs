@@ -3,14 +3,40 @@
 //! This module defines the core data structures stored in the database.
 //! All models implement Serialize/Deserialize for JSON persistence.
 
+use std::collections::HashMap;
 use serde::{Serialize, Deserialize};
 
+/// Returns the current time as Unix seconds.
+#[cfg(feature = "timestamps")]
+pub(crate) fn unix_now() -> i64 {
+    chrono::Utc::now().timestamp()
+}
+
+/// Returns the current time as Unix seconds.
+///
+/// Without the `timestamps` feature the standard library's clock is used
+/// instead of `chrono`; a clock set before 1970 reads as `0`.
+#[cfg(not(feature = "timestamps"))]
+pub(crate) fn unix_now() -> i64 {
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs() as i64)
+}
+
 /// Represents a single row in the database.
 ///
 /// Each row contains:
 /// - A unique ID (primary key)
 /// - A name field (string)
 /// - An age field (unsigned 8-bit integer, 0-255)
+/// - Creation and last-update times (Unix seconds)
+/// - Optional extra fields beyond the fixed columns
+///
+/// Rows written before timestamps existed deserialize with both set to `0`,
+/// and rows written before extra fields existed have none.
+/// Equality compares the stored data only and ignores the timestamps.
 ///
 /// # Examples
 ///
@@ -22,7 +48,7 @@ use serde::{Serialize, Deserialize};
 /// assert_eq!(row.name, "Alice");
 /// assert_eq!(row.age, 30);
 /// ```
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Row {
     /// Unique identifier for the row (primary key)
     pub id: u32,
@@ -30,11 +56,32 @@ pub struct Row {
     pub name: String,
     /// Age field (0-255)
     pub age: u8,
+    /// Unix timestamp of when the row was created
+    #[serde(default)]
+    pub created_at: i64,
+    /// Unix timestamp of the row's most recent update
+    #[serde(default)]
+    pub updated_at: i64,
+    /// Additional fields beyond the fixed columns, keyed by name
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub extra: HashMap<String, serde_json::Value>,
 }
 
 impl Row {
+    /// The fixed columns of every row as `(name, type)` pairs, in
+    /// declaration order.
+    pub const COLUMNS: &'static [(&'static str, &'static str)] = &[
+        ("id", "u32"),
+        ("name", "String"),
+        ("age", "u8"),
+        ("created_at", "i64"),
+        ("updated_at", "i64"),
+    ];
+
     /// Creates a new row with the given values.
     ///
+    /// Both `created_at` and `updated_at` are set to the current time.
+    ///
     /// # Arguments
     ///
     /// * `id` - Unique identifier for the row
@@ -53,10 +100,41 @@ impl Row {
     /// let row = Row::new(42, "Bob".to_string(), 25);
     /// ```
     pub fn new(id: u32, name: String, age: u8) -> Self {
+        let now = unix_now();
+
         Self {
             id,
             name,
             age,
+            created_at: now,
+            updated_at: now,
+            extra: HashMap::new(),
         }
     }
+
+    /// Sets an extra field, replacing any previous value under `key`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use mini_db::model::Row;
+    ///
+    /// let mut row = Row::new(1, "Alice".to_string(), 30);
+    /// row.set_extra("city", "Paris".into());
+    /// assert_eq!(row.get_extra("city"), Some(&"Paris".into()));
+    /// ```
+    pub fn set_extra(&mut self, key: impl Into<String>, value: serde_json::Value) {
+        self.extra.insert(key.into(), value);
+    }
+
+    /// Returns the extra field stored under `key`, if any.
+    pub fn get_extra(&self, key: &str) -> Option<&serde_json::Value> {
+        self.extra.get(key)
+    }
+}
+
+impl PartialEq for Row {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id && self.name == other.name && self.age == other.age && self.extra == other.extra
+    }
 }
\ No newline at end of file
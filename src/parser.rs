@@ -6,18 +6,68 @@
 //! ## Supported Commands
 //!
 //! - `INSERT <id> <name> <age>` - Insert a new row
+//! - `UPSERT <id> <name> <age>` - Insert a row or update the existing one
+//! - `INSERT IF ABSENT <id> <name> <age>` - Insert a row unless its ID exists, leaving the existing row unchanged
 //! - `SELECT` - Retrieve all rows
 //! - `SELECT WHERE ID=<id>` - Retrieve a specific row by ID
+//! - `SELECT WHERE ID IN (<id>, <id>...)` - Retrieve the rows with the given IDs, in list order
+//! - `SELECT WHERE <cond> [AND <cond>...]` - Retrieve rows matching every condition; prefix a condition with `NOT` to invert it
+//! - `SELECT WHERE CREATED|UPDATED AFTER|BEFORE <unix>` - Retrieve rows created or last updated in a time range (inclusive)
+//! - `SELECT WHERE NAME LIKE '<pattern>'` - Retrieve rows whose name matches (`%` = any sequence, `_` = any character)
+//! - `SELECT KEYS [WHERE <cond> [AND <cond>...]]` - Retrieve only the IDs of (matching) rows
+//! - `SELECT IDS` - Retrieve every ID in ascending order
+//! - `SELECT <field>[, <field>...]` - Retrieve only the given fields (`ID`, `NAME`, `AGE`) of every row
+//! - `SELECT DISTINCT AGE|NAME` - Retrieve the sorted, de-duplicated ages or names
+//! - `SELECT MIN(AGE)|MAX(AGE)|AVG(AGE)` - Compute an aggregate over all ages
+//! - `SELECT COUNT GROUP BY AGE|NAME` - Count the rows sharing each age or name
+//! - `SELECT YOUNGEST|OLDEST <n>` - Retrieve the `n` youngest or oldest rows
+//! - `SELECT ORDER BY AGE ASC|DESC LIMIT <n>` - Same as `SELECT YOUNGEST|OLDEST <n>`
+//! - `SELECT SAMPLE <n>` - Retrieve `n` rows picked at random
+//! - `EXISTS ID=<id>` - Print `true` if a row has the ID, `false` otherwise
+//! - `UPDATE WHERE ID=<id> SET <field>=<value> [<field>=<value>]` - Change the name and/or age of a row
+//! - `RENAME ID=<id> TO <name>` - Change only the name of a row; quote names containing spaces
+//! - `REKEY <id> TO <id>` - Change the ID of a row
 //! - `DELETE WHERE ID=<id>` - Delete a row by ID
+//! - `DELETE WHERE ID IN (<id>, <id>...)` - Delete several rows by ID
+//! - `DELETE WHERE AGE<op><age>` - Delete every row whose age matches (`<`, `<=`, `>`, `>=`, `=`)
 //! - `EXEC BATCH <path>` - Execute commands from a file
-//! - `RESET` - Clear all data
+//! - `EXEC BATCH <path> DRYRUN` - Validate a batch file without applying it
+//! - `UNDO` - Reverse the most recent insert, update or delete
+//! - `FLUSH` - Sync pending writes to disk
+//! - `STATS` - Show the row count and the size of the log
+//! - `EXPLAIN <command>` - Show how a command finds its rows, without running it
+//! - `DESCRIBE` - Show the columns of the table and their types
+//! - `COMPACT` - Snapshot all rows and truncate the log
+//! - `RESET` - Print a confirmation token in the interactive shell; clears nothing by itself
+//! - `RESET CONFIRM <token>` - Clear all data in the interactive shell, using the token printed by `RESET`
+//! - `HISTORY` - List previously entered commands
+//! - `HISTORY REPLAY <n>` - Re-run command number `n`
+//! - `FORMAT TABLE|JSON|DEBUG` - Choose how the shell prints rows
+//! - `ALIAS <name> = "<command>[; <command>...]"` - Define a shell macro, run by typing its name
+//! - `PROMPT <text>` - Change the shell's prompt; quote it to keep surrounding spaces
 //! - `HELP` - Display help information
 //! - `EXIT` - Shutdown and exit
+//!
+//! A condition compares `ID`, `NAME`, `AGE`, `CREATED_AT` or `UPDATED_AT`
+//! with a value, e.g. `AGE>=30`, `ID!=5` or `NAME=Alice`. Names only support
+//! `=` and `!=`. Timestamps can also be written `CREATED AFTER <unix>` or
+//! `UPDATED BEFORE <unix>`, which include the given second.
+//!
+//! Several commands can be entered on one line, separated by `;`; they run
+//! in order and the rest of the line is skipped after `EXIT`. A `;` inside
+//! quotes, e.g. in `INSERT 1 "A;B" 20`, does not separate commands.
 
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
 use std::path::{PathBuf};
-use crate::engine::{DatabaseHandle};
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use crate::engine::{BatchReport, DatabaseHandle, DbStats};
+use crate::format::{format_rows, OutputFormat};
+use crate::history::History;
 use crate::model::Row;
 use crate::errors::DbError;
+use crate::query::{AggregateFunc, CompareOp, Field, Predicate, Value};
 
 /// Represents a parsed database command.
 ///
@@ -31,10 +81,42 @@ pub enum Command {
         name: String,
         age: u8,
     },
+    /// Insert a row, or replace the name and age of the row with the same ID
+    Upsert {
+        id: u32,
+        name: String,
+        age: u8,
+    },
+    /// Insert a row unless a row with the same ID exists
+    InsertIfAbsent {
+        id: u32,
+        name: String,
+        age: u8,
+    },
+    /// Change only the given fields of a row
+    Update {
+        id: u32,
+        name: Option<String>,
+        age: Option<u8>,
+    },
+    /// Change only the name of a row
+    Rename {
+        id: u32,
+        name: String,
+    },
+    /// Change the ID of a row
+    Rekey {
+        old_id: u32,
+        new_id: u32,
+    },
     /// Execute a batch of commands from a file
     ExecBatch {
         path: PathBuf,
     },
+    /// Parse a batch file without executing any of its commands
+    ExecBatchDryRun {
+        path: PathBuf,
+    },
     /// Select a specific row by its ID
     SelectById {
         id: u32,
@@ -43,21 +125,361 @@ pub enum Command {
     DeleteById {
         id: u32,
     },
+    /// Delete the rows with the given IDs
+    DeleteByIds {
+        ids: Vec<u32>,
+    },
+    /// Delete every row whose age satisfies the comparison
+    DeleteByAge {
+        op: CompareOp,
+        value: u8,
+    },
     /// Select and display all rows
     Select,
+    /// Select the rows with the given IDs, in list order
+    SelectByIds {
+        ids: Vec<u32>,
+    },
+    /// Select rows matching a predicate
+    SelectWhere {
+        predicate: Predicate,
+    },
+    /// Select rows whose name matches a `LIKE` pattern
+    SelectByNameLike {
+        pattern: String,
+    },
+    /// Select only the IDs of rows, optionally filtered by a predicate
+    SelectKeys {
+        filter: Option<Predicate>,
+    },
+    /// Select only the given fields of every row
+    SelectFields {
+        fields: Vec<Field>,
+    },
+    /// Select the distinct values of one field
+    SelectDistinct {
+        field: Field,
+    },
+    /// Compute an aggregate function over one field
+    Aggregate {
+        func: AggregateFunc,
+        field: Field,
+    },
+    /// Count the rows of each distinct age
+    CountGroupByAge,
+    /// Count the rows of each distinct name
+    CountGroupByName,
+    /// Select the `n` youngest (`ascending`) or oldest rows
+    SelectTopByAge {
+        n: usize,
+        ascending: bool,
+    },
+    /// Select every ID, in ascending order
+    SelectIds,
+    /// Select `n` rows picked at random
+    Sample {
+        n: usize,
+    },
+    /// Check whether a row with the given ID exists
+    Exists {
+        id: u32,
+    },
     /// Exit the program
     Exit,
+    /// Reverse the most recent write
+    Undo,
+    /// Sync pending writes to disk without exiting
+    Flush,
+    /// Show row and log statistics
+    Stats,
+    /// Show the columns and their types
+    Describe,
+    /// Show how a command finds its rows, without running it
+    Explain {
+        inner: Box<Command>,
+    },
     /// Compact the database to reduce size
     Compact,
     /// Display help information
     Help,
     /// Reset (clear) the entire database
     Reset,
+    /// Confirm a reset requested in the interactive shell
+    ResetConfirm {
+        token: String,
+    },
+    /// List the commands entered so far
+    History,
+    /// Re-run the command with the given history number
+    HistoryReplay {
+        n: usize,
+    },
+    /// Change how the shell prints rows
+    Format {
+        format: OutputFormat,
+    },
+    /// Define a shell macro expanding to one or more commands
+    DefineAlias {
+        name: String,
+        body: String,
+    },
+    /// Change the shell's prompt
+    Prompt {
+        prompt: String,
+    },
+}
+
+/// The result of running any command through [`run_command`].
+#[derive(Debug)]
+pub enum CommandOutput {
+    /// Full rows returned by a query
+    Rows(Vec<Row>),
+    /// Only the IDs of the rows matched by a query
+    Keys(Vec<u32>),
+    /// Rows reduced to the requested fields, as JSON objects
+    Projected(Vec<serde_json::Value>),
+    /// Single values, such as the distinct values of a field
+    Values(Vec<String>),
+    /// Number of rows changed by a write
+    Affected(usize),
+    /// A human-readable confirmation
+    Message(String),
+    /// The command failed to parse or execute
+    Error(DbError),
+    /// The outputs of a batch's lines, in order, followed by its summary
+    Batch(Vec<CommandOutput>),
+    /// The database was flushed and the shell should exit
+    Exit,
+}
+
+impl From<CommandOutcome> for CommandOutput {
+    fn from(outcome: CommandOutcome) -> Self {
+        match outcome {
+            CommandOutcome::Rows(rows) => CommandOutput::Rows(rows),
+            CommandOutcome::Keys(ids) => CommandOutput::Keys(ids),
+            CommandOutcome::Projected(values) => CommandOutput::Projected(values),
+            CommandOutcome::Values(values) => CommandOutput::Values(values),
+        }
+    }
+}
+
+/// The result of running a query command against the database.
+#[derive(PartialEq, Debug)]
+pub enum CommandOutcome {
+    /// Full rows returned by the query
+    Rows(Vec<Row>),
+    /// Only the IDs of the matching rows
+    Keys(Vec<u32>),
+    /// Rows reduced to the requested fields, as JSON objects
+    Projected(Vec<serde_json::Value>),
+    /// Single values, such as the distinct values of a field
+    Values(Vec<String>),
+}
+
+/// Parses the `<id> <name> <age>` values following `INSERT` or `UPSERT`;
+/// `tokens[0]` is the keyword before them.
+///
+/// The name may be quoted, e.g. to include a `;` that would otherwise end
+/// the statement.
+fn parse_row_values(tokens: &[&str]) -> Result<(u32, String, u8), DbError> {
+    if tokens.len() != 4 {
+        return Err(DbError::InvalidCommandError);
+    }
+
+    let id: u32 = tokens[1].parse().map_err(|_| {
+        DbError::ParseError("ID must be a valid unsigned integer".to_string())
+    })?;
+
+    let name = strip_quotes(tokens[2]).to_string();
+
+    let age: u8 = tokens[3].parse().map_err(|_| {
+        DbError::ParseError("Age must be a valid integer (0-255)".to_string())
+    })?;
+
+    Ok((id, name, age))
+}
+
+/// Parses an `IN (<id>, <id>...)` list; IDs are separated by commas and/or
+/// whitespace.
+fn parse_id_list(list: &str) -> Result<Vec<u32>, DbError> {
+    let inner = list.strip_prefix("in")
+        .map(str::trim_start)
+        .and_then(|rest| rest.strip_prefix('('))
+        .and_then(|rest| rest.strip_suffix(')'))
+        .ok_or(DbError::InvalidCommandError)?;
+
+    let ids: Vec<u32> = inner
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|id| !id.is_empty())
+        .map(|id| id.parse().map_err(|_| {
+            DbError::ParseError("ID must be a valid unsigned integer".to_string())
+        }))
+        .collect::<Result<_, _>>()?;
+
+    if ids.is_empty() {
+        return Err(DbError::InvalidCommandError);
+    }
+
+    Ok(ids)
+}
+
+/// Removes one pair of matching single or double quotes around `value`.
+fn strip_quotes(value: &str) -> &str {
+    for quote in ['\'', '"'] {
+        if let Some(inner) = value.strip_prefix(quote).and_then(|v| v.strip_suffix(quote)) {
+            return inner;
+        }
+    }
+
+    value
+}
+
+/// Parses an aggregate call such as `avg(age)` into its function and field.
+fn parse_aggregate(call: &str) -> Option<(AggregateFunc, Field)> {
+    let (func, rest) = call.split_once('(')?;
+    let field = rest.strip_suffix(')')?;
+
+    Some((AggregateFunc::from_name(func)?, Field::from_name(field)?))
+}
+
+/// Parses the row count of `SELECT YOUNGEST|OLDEST <n>`, `SELECT SAMPLE <n>` and `LIMIT <n>`.
+fn parse_limit(token: &str) -> Result<usize, DbError> {
+    token.parse().map_err(|_| {
+        DbError::ParseError("Limit must be a valid unsigned integer".to_string())
+    })
+}
+
+/// Parses a list of field names separated by commas and/or whitespace.
+fn parse_field_list(tokens: &[&str]) -> Result<Vec<Field>, DbError> {
+    let joined = tokens.join(" ");
+    let fields: Vec<Field> = joined
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|name| !name.is_empty())
+        .map(|name| Field::from_name(name).ok_or(DbError::InvalidCommandError))
+        .collect::<Result<_, _>>()?;
+
+    if fields.is_empty() {
+        return Err(DbError::InvalidCommandError);
+    }
+
+    Ok(fields)
+}
+
+/// Parses the `NAME=<name>` and/or `AGE=<age>` assignments of an `UPDATE`.
+///
+/// Assignments are separated by whitespace or commas; each field may be
+/// set at most once and at least one must be given.
+fn parse_assignments(tokens: &[&str]) -> Result<(Option<String>, Option<u8>), DbError> {
+    let (mut name, mut age) = (None, None);
+
+    let joined = tokens.join(" ");
+    let assignments = joined
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|assignment| !assignment.is_empty());
+
+    for assignment in assignments {
+        let (field, value) = assignment.split_once('=').ok_or(DbError::InvalidCommandError)?;
+
+        match Field::from_name(field) {
+            Some(Field::Name) if name.is_none() && !value.is_empty() => name = Some(value.to_string()),
+            Some(Field::Age) if age.is_none() => {
+                age = Some(value.parse().map_err(|_| {
+                    DbError::ParseError("Age must be a valid integer (0-255)".to_string())
+                })?);
+            },
+            _ => return Err(DbError::InvalidCommandError),
+        }
+    }
+
+    if name.is_none() && age.is_none() {
+        return Err(DbError::InvalidCommandError);
+    }
+
+    Ok((name, age))
+}
+
+/// Parses an age condition such as `AGE<18` or `AGE >= 65` spread over `tokens`.
+fn parse_age_condition(tokens: &[&str]) -> Result<(CompareOp, u8), DbError> {
+    let condition = tokens.concat();
+    let (op, value) = match CompareOp::split_condition(&condition) {
+        Some((field, op, value)) if field.eq_ignore_ascii_case("age") => (op, value),
+        _ => return Err(DbError::InvalidCommandError),
+    };
+
+    let value: u8 = value.parse().map_err(|_| {
+        DbError::ParseError("Age must be a valid integer (0-255)".to_string())
+    })?;
+
+    Ok((op, value))
+}
+
+/// Parses a single condition such as `AGE>=30`, `NAME = Alice` or `NOT ID=5`.
+fn parse_condition(tokens: &[&str]) -> Result<Predicate, DbError> {
+    if let [not, rest @ ..] = tokens
+        && not.eq_ignore_ascii_case("not")
+    {
+        if rest.is_empty() {
+            return Err(DbError::InvalidCommandError);
+        }
+        return Ok(Predicate::Not(Box::new(parse_condition(rest)?)));
+    }
+
+    // CREATED|UPDATED AFTER|BEFORE <unix>, both bounds inclusive
+    if let [field, direction, value] = tokens
+        && let Some(field @ (Field::CreatedAt | Field::UpdatedAt)) = Field::from_name(field)
+    {
+        let op = match direction.to_lowercase().as_str() {
+            "after" => CompareOp::Ge,
+            "before" => CompareOp::Le,
+            _ => return Err(DbError::InvalidCommandError),
+        };
+        let value = value.parse().map_err(|_| {
+            DbError::ParseError("Timestamps must be Unix timestamps in seconds".to_string())
+        })?;
+        return Ok(Predicate::Compare { field, op, value: Value::Number(value) });
+    }
+
+    let condition = tokens.concat();
+    let (field, op, value) = CompareOp::split_condition(&condition)
+        .ok_or(DbError::InvalidCommandError)?;
+    let field = Field::from_name(field).ok_or(DbError::InvalidCommandError)?;
+
+    let value = match field {
+        Field::Name if matches!(op, CompareOp::Eq | CompareOp::Ne) => Value::Text(value.to_string()),
+        Field::Name => {
+            return Err(DbError::ParseError("Names can only be compared with = or !=".to_string()))
+        },
+        Field::Id | Field::Age | Field::CreatedAt | Field::UpdatedAt => Value::Number(value.parse().map_err(|_| {
+            DbError::ParseError(format!("{:?} must be compared with an integer", field))
+        })?),
+    };
+
+    Ok(Predicate::Compare { field, op, value })
+}
+
+/// Parses conditions joined by `AND` into a predicate.
+fn parse_predicate(tokens: &[&str]) -> Result<Predicate, DbError> {
+    let mut conditions = tokens
+        .split(|token| token.eq_ignore_ascii_case("and"))
+        .map(|condition| {
+            if condition.is_empty() {
+                Err(DbError::InvalidCommandError)
+            } else {
+                parse_condition(condition)
+            }
+        });
+
+    // The first condition always exists since `split` yields at least one slice
+    let first = conditions.next().ok_or(DbError::InvalidCommandError)??;
+    conditions.try_fold(first, |left, right| {
+        Ok(Predicate::And(Box::new(left), Box::new(right?)))
+    })
 }
 
 /// Parses a string input into a structured Command.
 ///
-/// Commands are case-insensitive and whitespace-separated.
+/// Keywords are case-insensitive and whitespace-separated; values such as
+/// names and paths keep the casing they were typed with.
 ///
 /// # Arguments
 ///
@@ -80,46 +502,50 @@ pub enum Command {
 /// let cmd = parse_command("DELETE WHERE ID=1").unwrap();
 /// ```
 pub fn parse_command(input: &str) -> Result<Command, DbError> {
-    let line = input.trim().to_lowercase();
+    let line = input.trim();
 
     if line.is_empty() {
         return Err(DbError::InvalidCommandError);
     }
 
-    // Tokenize input by whitespace
-    let tokens: Vec<&str> = line.split_whitespace().collect();
+    // Tokenize input by whitespace; keywords are matched on the lowercased
+    // tokens while values are taken from the original ones
+    let raw_tokens: Vec<&str> = line.split_whitespace().collect();
+    let lowered = line.to_lowercase();
+    let tokens: Vec<&str> = lowered.split_whitespace().collect();
     let cmd = tokens[0];
 
     match cmd {
         "exec" => {
             if tokens.len() == 3 && tokens[1] == "batch" {
-                let path = PathBuf::from(tokens[2]);
+                let path = PathBuf::from(raw_tokens[2]);
                 Ok(Command::ExecBatch { path })
+            } else if tokens.len() == 4 && tokens[1] == "batch" && tokens[3] == "dryrun" {
+                let path = PathBuf::from(raw_tokens[2]);
+                Ok(Command::ExecBatchDryRun { path })
             } else {
                 Err(DbError::InvalidCommandError)
             }
         },
         "insert" => {
-            if tokens.len() == 4 {
-                // Parse: INSERT <id> <name> <age>
-                let id: u32 = tokens[1].parse().map_err(|_| {
-                    DbError::ParseError("ID must be a valid unsigned integer".to_string())
-                })?;
-
-                let name = tokens[2].to_string();
-
-                let age: u8 = tokens[3].parse().map_err(|_| {
-                    DbError::ParseError("Age must be a valid integer (0-255)".to_string())
-                })?;
-
-                Ok(Command::Insert { id, name, age }) 
+            if tokens.len() >= 3 && tokens[1] == "if" && tokens[2] == "absent" {
+                // Parse: INSERT IF ABSENT <id> <name> <age>
+                let (id, name, age) = parse_row_values(&raw_tokens[2..])?;
+                Ok(Command::InsertIfAbsent { id, name, age })
             } else {
-                Err(DbError::InvalidCommandError)
+                // Parse: INSERT <id> <name> <age>
+                let (id, name, age) = parse_row_values(&raw_tokens)?;
+                Ok(Command::Insert { id, name, age })
             }
         },
+        "upsert" => {
+            // Parse: UPSERT <id> <name> <age>
+            let (id, name, age) = parse_row_values(&raw_tokens)?;
+            Ok(Command::Upsert { id, name, age })
+        },
         "select" => {
             if tokens.len() == 1 && tokens[0] == "select" {
-                return Ok(Command::Select);
+                Ok(Command::Select)
             } else if tokens.len() == 3 && tokens[1] == "where" && tokens[2].starts_with("id=") {
                 let id: u32 = match tokens[2].split("=").nth(1) {
                 Some(id) => id.parse().map_err(|_| {
@@ -127,7 +553,116 @@ pub fn parse_command(input: &str) -> Result<Command, DbError> {
                 })?,
                     None => return Err(DbError::ParseError("Id not found".into()))
                 };
-                return Ok(Command::SelectById { id });
+                Ok(Command::SelectById { id })
+            } else if tokens.len() >= 4 && tokens[1] == "where" && tokens[2] == "id" && tokens[3].starts_with("in") {
+                // Parse: SELECT WHERE ID IN (<id>, <id>...)
+                let ids = parse_id_list(&tokens[3..].join(" "))?;
+                Ok(Command::SelectByIds { ids })
+            } else if tokens.len() >= 5 && tokens[1] == "where" && tokens[2] == "name" && tokens[3] == "like" {
+                // Parse: SELECT WHERE NAME LIKE '<pattern>'
+                let pattern = raw_tokens[4..].join(" ");
+                let pattern = strip_quotes(&pattern).to_string();
+                Ok(Command::SelectByNameLike { pattern })
+            } else if tokens.len() >= 3 && tokens[1] == "where" {
+                let predicate = parse_predicate(&raw_tokens[2..])?;
+                Ok(Command::SelectWhere { predicate })
+            } else if tokens.len() == 2 && tokens[1] == "ids" {
+                Ok(Command::SelectIds)
+            } else if tokens.len() == 2 && tokens[1] == "keys" {
+                Ok(Command::SelectKeys { filter: None })
+            } else if tokens.len() >= 4 && tokens[1] == "keys" && tokens[2] == "where" {
+                let filter = parse_predicate(&raw_tokens[3..])?;
+                Ok(Command::SelectKeys { filter: Some(filter) })
+            } else if let Some((func, field)) = parse_aggregate(&tokens[1..].concat()) {
+                // Parse: SELECT MIN(AGE)|MAX(AGE)|AVG(AGE)
+                if field != Field::Age {
+                    return Err(DbError::InvalidCommandError);
+                }
+                Ok(Command::Aggregate { func, field })
+            } else if tokens.len() == 5 && tokens[1..4] == ["count", "group", "by"] {
+                // Parse: SELECT COUNT GROUP BY AGE|NAME
+                match Field::from_name(tokens[4]) {
+                    Some(Field::Age) => Ok(Command::CountGroupByAge),
+                    Some(Field::Name) => Ok(Command::CountGroupByName),
+                    _ => Err(DbError::InvalidCommandError),
+                }
+            } else if tokens.len() == 3 && tokens[1] == "sample" {
+                // Parse: SELECT SAMPLE <n>
+                let n = parse_limit(tokens[2])?;
+                Ok(Command::Sample { n })
+            } else if tokens.len() == 3 && (tokens[1] == "youngest" || tokens[1] == "oldest") {
+                // Parse: SELECT YOUNGEST|OLDEST <n>
+                let n = parse_limit(tokens[2])?;
+                Ok(Command::SelectTopByAge { n, ascending: tokens[1] == "youngest" })
+            } else if tokens.len() == 7 && tokens[1..4] == ["order", "by", "age"] && tokens[5] == "limit" {
+                // Parse: SELECT ORDER BY AGE ASC|DESC LIMIT <n>
+                let ascending = match tokens[4] {
+                    "asc" => true,
+                    "desc" => false,
+                    _ => return Err(DbError::InvalidCommandError),
+                };
+                let n = parse_limit(tokens[6])?;
+                Ok(Command::SelectTopByAge { n, ascending })
+            } else if tokens.len() == 3 && tokens[1] == "distinct" {
+                // Parse: SELECT DISTINCT AGE|NAME
+                match Field::from_name(tokens[2]) {
+                    Some(field @ (Field::Age | Field::Name)) => Ok(Command::SelectDistinct { field }),
+                    _ => Err(DbError::InvalidCommandError),
+                }
+            } else if Field::from_name(tokens[1].trim_end_matches(',')).is_some() {
+                // Parse: SELECT <field>[, <field>...]
+                let fields = parse_field_list(&tokens[1..])?;
+                Ok(Command::SelectFields { fields })
+            } else {
+                Err(DbError::InvalidCommandError)
+            }
+        },
+        "update" => {
+            // Parse: UPDATE WHERE ID=<id> SET <field>=<value> [<field>=<value>]
+            if tokens.len() >= 5 && tokens[1] == "where" && tokens[2].starts_with("id=") && tokens[3] == "set" {
+                let id: u32 = tokens[2]["id=".len()..].parse().map_err(|_| {
+                    DbError::ParseError("ID must be a valid unsigned integer".to_string())
+                })?;
+                let (name, age) = parse_assignments(&raw_tokens[4..])?;
+                Ok(Command::Update { id, name, age })
+            } else {
+                Err(DbError::InvalidCommandError)
+            }
+        },
+        "exists" => {
+            // Parse: EXISTS ID=<id>
+            if tokens.len() == 2 && tokens[1].starts_with("id=") {
+                let id: u32 = tokens[1]["id=".len()..].parse().map_err(|_| {
+                    DbError::ParseError("ID must be a valid unsigned integer".to_string())
+                })?;
+                Ok(Command::Exists { id })
+            } else {
+                Err(DbError::InvalidCommandError)
+            }
+        },
+        "rename" => {
+            // Parse: RENAME ID=<id> TO <name>
+            if tokens.len() >= 4 && tokens[1].starts_with("id=") && tokens[2] == "to" {
+                let id: u32 = tokens[1]["id=".len()..].parse().map_err(|_| {
+                    DbError::ParseError("ID must be a valid unsigned integer".to_string())
+                })?;
+                let name = raw_tokens[3..].join(" ");
+                let name = strip_quotes(&name).to_string();
+                if name.is_empty() {
+                    return Err(DbError::InvalidCommandError);
+                }
+                Ok(Command::Rename { id, name })
+            } else {
+                Err(DbError::InvalidCommandError)
+            }
+        },
+        "rekey" => {
+            // Parse: REKEY <id> TO <id>
+            if tokens.len() == 4 && tokens[2] == "to" {
+                let parse_id = |token: &str| token.parse::<u32>().map_err(|_| {
+                    DbError::ParseError("ID must be a valid unsigned integer".to_string())
+                });
+                Ok(Command::Rekey { old_id: parse_id(tokens[1])?, new_id: parse_id(tokens[3])? })
             } else {
                 Err(DbError::InvalidCommandError)
             }
@@ -140,31 +675,414 @@ pub fn parse_command(input: &str) -> Result<Command, DbError> {
                 })?,
                     None => return Err(DbError::ParseError("Id not found".into()))
                 };
-                return Ok(Command::DeleteById { id });
+                Ok(Command::DeleteById { id })
+            } else if tokens.len() >= 4 && tokens[1] == "where" && tokens[2] == "id" && tokens[3].starts_with("in") {
+                // Parse: DELETE WHERE ID IN (<id>, <id>...)
+                let ids = parse_id_list(&tokens[3..].join(" "))?;
+                Ok(Command::DeleteByIds { ids })
+            } else if tokens.len() >= 3 && tokens[1] == "where" && tokens[2].starts_with("age") {
+                // Parse: DELETE WHERE AGE<op><value>, allowing spaces around the operator
+                let (op, value) = parse_age_condition(&tokens[2..])?;
+                Ok(Command::DeleteByAge { op, value })
             } else {
                 Err(DbError::InvalidCommandError)
             }
         }
         "exit" => Ok(Command::Exit),
+        "undo" => Ok(Command::Undo),
+        "flush" => Ok(Command::Flush),
+        "stats" => Ok(Command::Stats),
+        "describe" => Ok(Command::Describe),
+        "explain" => {
+            // Parse: EXPLAIN <command>
+            if raw_tokens.len() < 2 {
+                return Err(DbError::InvalidCommandError);
+            }
+            let inner = parse_command(&raw_tokens[1..].join(" "))?;
+            Ok(Command::Explain { inner: Box::new(inner) })
+        },
         "compact" => Ok(Command::Compact),
         "help" => Ok(Command::Help),
-        "reset" => Ok(Command::Reset),
+        "reset" => match raw_tokens[1..] {
+            [] => Ok(Command::Reset),
+            // Parse: RESET CONFIRM <token>
+            [confirm, token] if confirm.eq_ignore_ascii_case("confirm") => {
+                Ok(Command::ResetConfirm { token: token.to_string() })
+            },
+            _ => Err(DbError::InvalidCommandError),
+        },
+        "format" => {
+            // Parse: FORMAT TABLE|JSON|DEBUG
+            if tokens.len() != 2 {
+                return Err(DbError::InvalidCommandError);
+            }
+            let format = OutputFormat::from_name(tokens[1]).ok_or(DbError::InvalidCommandError)?;
+            Ok(Command::Format { format })
+        },
+        "alias" => {
+            // Parse: ALIAS <name> = "<command>[; <command>...]"
+            let (name, body) = line["alias".len()..]
+                .split_once('=')
+                .ok_or(DbError::InvalidCommandError)?;
+            let name = name.trim().to_lowercase();
+            let body = strip_quotes(body.trim()).trim().to_string();
+
+            let valid_name = !name.is_empty()
+                && name.chars().all(|c| c.is_alphanumeric() || c == '_');
+            // A command keyword would shadow the command it names
+            if !valid_name || body.is_empty() || parse_command(&name).is_ok() {
+                return Err(DbError::InvalidCommandError);
+            }
+            Ok(Command::DefineAlias { name, body })
+        },
+        "prompt" => {
+            // Parse: PROMPT <text>
+            let prompt = strip_quotes(line["prompt".len()..].trim());
+            if prompt.is_empty() {
+                return Err(DbError::InvalidCommandError);
+            }
+            Ok(Command::Prompt { prompt: prompt.to_string() })
+        },
+        "history" => {
+            if tokens.len() == 1 {
+                Ok(Command::History)
+            } else if tokens.len() == 3 && tokens[1] == "replay" {
+                // Parse: HISTORY REPLAY <n>
+                let n: usize = tokens[2].parse().map_err(|_| {
+                    DbError::ParseError("History number must be a valid unsigned integer".to_string())
+                })?;
+                Ok(Command::HistoryReplay { n })
+            } else {
+                Err(DbError::InvalidCommandError)
+            }
+        },
         _ => Err(DbError::InvalidCommandError)
     }
 
 }
 
-/// Parses and executes a command against the database.
+/// Help text listing every command, shown by `HELP`.
+const HELP_TEXT: &str = concat!(
+    "\n",
+    "Available commands:\n",
+    "EXEC BATCH <FILEPATH.TXT> [DRYRUN]\n",
+    "INSERT <ID> <NAME> <AGE>\n",
+    "UPSERT <ID> <NAME> <AGE>\n",
+    "INSERT IF ABSENT <ID> <NAME> <AGE>\n",
+    "SELECT\n",
+    "SELECT WHERE ID=<ID>\n",
+    "SELECT WHERE ID IN (<ID>, <ID>...)\n",
+    "SELECT WHERE [NOT] <COND> [AND [NOT] <COND>...]\n",
+    "SELECT WHERE NAME LIKE '<PATTERN>'\n",
+    "SELECT WHERE CREATED|UPDATED AFTER|BEFORE <UNIX TIME>\n",
+    "SELECT KEYS [WHERE <COND> [AND <COND>...]]\n",
+    "SELECT IDS\n",
+    "SELECT <FIELD>[, <FIELD>...]\n",
+    "SELECT DISTINCT AGE|NAME\n",
+    "SELECT MIN(AGE)|MAX(AGE)|AVG(AGE)\n",
+    "SELECT COUNT GROUP BY AGE|NAME\n",
+    "SELECT YOUNGEST|OLDEST <N>\n",
+    "SELECT ORDER BY AGE ASC|DESC LIMIT <N>\n",
+    "SELECT SAMPLE <N>\n",
+    "EXISTS ID=<ID>\n",
+    "UPDATE WHERE ID=<ID> SET [NAME=<NAME>] [AGE=<AGE>]\n",
+    "RENAME ID=<ID> TO <NAME>\n",
+    "REKEY <ID> TO <ID>\n",
+    "DELETE WHERE ID=<ID>\n",
+    "DELETE WHERE ID IN (<ID>, <ID>...)\n",
+    "DELETE WHERE AGE<OP><AGE>\n",
+    "UNDO\n",
+    "FLUSH\n",
+    "STATS\n",
+    "DESCRIBE\n",
+    "EXPLAIN <COMMAND>\n",
+    "COMPACT\n",
+    "RESET\n",
+    "RESET CONFIRM <TOKEN>\n",
+    "HISTORY\n",
+    "HISTORY REPLAY <N>\n",
+    "FORMAT TABLE|JSON|DEBUG\n",
+    "PROMPT <TEXT>\n",
+    "ALIAS <NAME> = \"<COMMAND>[; <COMMAND>...]\"\n",
+    "EXIT\n",
+);
+
+/// Parses and executes a command against the database without printing.
 ///
-/// This is the main entry point for command execution. It:
-/// 1. Parses the input string into a Command
-/// 2. Executes the command against the database
-/// 3. Prints results or error messages
+/// This is the entry point for embedding the engine: the result of the
+/// command is returned as a [`CommandOutput`] for the caller to present.
+/// `RESET` clears nothing here; use [`DatabaseHandle::reset_db`] or the
+/// interactive shell's confirmation token.
+///
+/// # Arguments
+///
+/// * `input` - The raw command string
+/// * `db` - A handle to the shared database
+///
+/// # Returns
+///
+/// The command's output. Parse and execution failures are returned as
+/// `CommandOutput::Error`; `EXIT` flushes the database and returns
+/// `CommandOutput::Exit`, or the flush error if it fails.
+///
+/// # Examples
+///
+/// ```no_run
+/// use mini_db::engine::DatabaseHandle;
+/// use mini_db::parser::{run_command, CommandOutput};
+///
+/// let db = DatabaseHandle::new("mini_db.log")?;
+/// run_command("INSERT 1 Alice 30", &db);
+/// if let CommandOutput::Rows(rows) = run_command("SELECT WHERE ID=1", &db) {
+///     assert_eq!(rows[0].name, "Alice");
+/// }
+/// # Ok::<(), mini_db::errors::DbError>(())
+/// ```
+pub fn run_command(input: &str, db: &DatabaseHandle) -> CommandOutput {
+    let cmd = match parse_command(input) {
+        Ok(cmd) => cmd,
+        Err(e) => return CommandOutput::Error(e),
+    };
+
+    let result = match cmd {
+        Command::Insert { id, name, age } => db
+            .insert(id, name, age)
+            .map(|()| CommandOutput::Message(format!("Inserted row with id {id}."))),
+
+        Command::InsertIfAbsent { id, name, age } => db
+            .insert_if_absent(id, name, age)
+            .map(|inserted| {
+                let message = if inserted {
+                    format!("Inserted row with id {id}.")
+                } else {
+                    format!("Row with id {id} already exists; left unchanged.")
+                };
+                CommandOutput::Message(message)
+            }),
+
+        Command::Upsert { id, name, age } => db
+            .upsert(id, name, age)
+            .map(|inserted| {
+                let verb = if inserted { "Inserted" } else { "Updated" };
+                CommandOutput::Message(format!("{verb} row with id {id}."))
+            }),
+
+        Command::Update { id, name, age } => db
+            .patch_by_id(id, name, age)
+            .map(|updated| CommandOutput::Affected(usize::from(updated))),
+
+        Command::Rename { id, name } => db
+            .rename_by_id(id, name)
+            .map(|renamed| CommandOutput::Affected(usize::from(renamed))),
+
+        Command::Rekey { old_id, new_id } => db
+            .rekey(old_id, new_id)
+            .map(|_| CommandOutput::Message(format!("Row {old_id} re-keyed to {new_id}."))),
+
+        Command::ExecBatch { path } => db
+            .exec_batch(path)
+            .map(batch_output),
+
+        Command::ExecBatchDryRun { path } => db
+            .exec_batch_dry_run(path)
+            .map(|commands| {
+                CommandOutput::Message(format!("Batch is valid: {} command(s) would run.", commands.len()))
+            }),
+
+        Command::DeleteById { id } => db
+            .delete_by_id(id)
+            .map(|deleted| CommandOutput::Affected(usize::from(deleted))),
+
+        Command::DeleteByIds { ids } => db
+            .delete_by_ids(&ids)
+            .map(CommandOutput::Affected),
+
+        Command::DeleteByAge { op, value } => db
+            .delete_where_age(op, value)
+            .map(CommandOutput::Affected),
+
+        cmd @ (Command::Select
+            | Command::SelectById { .. }
+            | Command::SelectByIds { .. }
+            | Command::SelectWhere { .. }
+            | Command::SelectByNameLike { .. }
+            | Command::SelectKeys { .. }
+            | Command::SelectFields { .. }
+            | Command::SelectDistinct { .. }
+            | Command::Aggregate { .. }
+            | Command::CountGroupByAge
+            | Command::CountGroupByName
+            | Command::SelectTopByAge { .. }
+            | Command::SelectIds
+            | Command::Sample { .. }
+            | Command::Exists { .. }) => db.query(&cmd).map(CommandOutput::from),
+
+        Command::Exit => db.shutdown().map(|()| CommandOutput::Exit),
+
+        Command::Undo => db.undo().map(|undone| {
+            let message = if undone { "Undid the last change." } else { "Nothing to undo." };
+            CommandOutput::Message(message.to_string())
+        }),
+
+        Command::Flush => db
+            .flush()
+            .map(|()| CommandOutput::Message("Flushed to disk.".to_string())),
+
+        Command::Stats => db.stats().map(|stats| stats_output(&stats)),
+
+        Command::Describe => Ok(schema_output(&db.schema())),
+
+        Command::Explain { inner } => Ok(CommandOutput::Message(db.explain(&inner))),
+
+        Command::Compact => db
+            .compact()
+            .map(|rows| CommandOutput::Message(format!("Compacted {rows} row(s) into the snapshot; log truncated."))),
+
+        Command::Help => Ok(CommandOutput::Message(HELP_TEXT.to_string())),
+
+        Command::Reset => Ok(CommandOutput::Message(
+            "Reset requires confirmation in the interactive shell.".to_string(),
+        )),
+
+        Command::History | Command::HistoryReplay { .. } => Ok(CommandOutput::Message(
+            "History is only available in the interactive shell.".to_string(),
+        )),
+
+        Command::Format { .. } => Ok(CommandOutput::Message(
+            "Output formats are only available in the interactive shell.".to_string(),
+        )),
+
+        Command::DefineAlias { .. } => Ok(CommandOutput::Message(
+            "Aliases are only available in the interactive shell.".to_string(),
+        )),
+
+        Command::Prompt { .. } => Ok(CommandOutput::Message(
+            "Prompts are only available in the interactive shell.".to_string(),
+        )),
+
+        Command::ResetConfirm { .. } => Ok(CommandOutput::Message(
+            "Reset confirmation is only available in the interactive shell.".to_string(),
+        )),
+    };
+
+    result.unwrap_or_else(CommandOutput::Error)
+}
+
+/// Describes a batch run the way the `EXEC BATCH` command shows it: the
+/// outputs of its lines, then a summary. A batch in which every line
+/// failed ends in an error instead of the summary.
+pub fn batch_output(report: BatchReport) -> CommandOutput {
+    let summary = match report {
+        BatchReport { executed: 0, failed, .. } if failed > 0 => CommandOutput::Error(DbError::ParseError(
+            format!("Batch file has no valid commands ({failed} line(s) failed)"),
+        )),
+        BatchReport { executed, failed: 0, .. } => CommandOutput::Message(
+            format!("Batch executed: {executed} command(s) succeeded."),
+        ),
+        BatchReport { executed, failed, .. } => CommandOutput::Message(
+            format!("Batch executed: {executed} command(s) succeeded, {failed} failed."),
+        ),
+    };
+
+    let mut outputs = report.outputs;
+    outputs.push(summary);
+    CommandOutput::Batch(outputs)
+}
+
+/// Describes database statistics the way the `STATS` command shows them.
+pub fn stats_output(stats: &DbStats) -> CommandOutput {
+    CommandOutput::Message(format!(
+        "Rows: {}\nLog entries: {}\nLog size: {} bytes\nSnapshot: {}",
+        stats.row_count,
+        stats.entry_count,
+        stats.log_bytes,
+        if stats.snapshot_exists { "yes" } else { "no" },
+    ))
+}
+
+/// Describes the table's columns the way the `DESCRIBE` command shows them,
+/// one `name: type` line per column.
+pub fn schema_output(schema: &[(String, String)]) -> CommandOutput {
+    let lines: Vec<String> = schema.iter()
+        .map(|(name, ty)| format!("{name}: {ty}"))
+        .collect();
+
+    CommandOutput::Message(lines.join("\n"))
+}
+
+/// Prints a command's output the way the interactive shell shows it,
+/// with rows in the default `DEBUG` format.
+pub fn print_output(output: &CommandOutput) {
+    print_output_as(output, OutputFormat::default());
+}
+
+/// Prints a command's output, rendering rows in the given format.
+///
+/// Errors go to standard error, everything else to standard output.
+pub fn print_output_as(output: &CommandOutput, format: OutputFormat) {
+    match output {
+        CommandOutput::Error(e) => eprintln!("Error: {}", e),
+        CommandOutput::Batch(outputs) => {
+            outputs.iter().for_each(|output| print_output_as(output, format));
+        },
+        output => {
+            // A closed stdout leaves nothing sensible to report to
+            let _ = write_output_as(&mut io::stdout(), output, format);
+        },
+    }
+}
+
+/// Writes a command's output to `out` the way the shell shows it,
+/// rendering rows in the given format. Errors are written as `Error: ...`.
+///
+/// # Examples
+///
+/// ```
+/// use mini_db::format::OutputFormat;
+/// use mini_db::parser::{write_output_as, CommandOutput};
+///
+/// let mut out = Vec::new();
+/// write_output_as(&mut out, &CommandOutput::Affected(2), OutputFormat::default())?;
+/// assert_eq!(out, b"2 row(s) affected.\n");
+/// # Ok::<(), std::io::Error>(())
+/// ```
+pub fn write_output_as(out: &mut dyn Write, output: &CommandOutput, format: OutputFormat) -> io::Result<()> {
+    match output {
+        CommandOutput::Rows(rows) if rows.is_empty() => writeln!(out, "(no rows)"),
+        CommandOutput::Rows(rows) => writeln!(out, "{}", format_rows(rows, format)),
+        CommandOutput::Keys(ids) if ids.is_empty() => writeln!(out, "(no rows)"),
+        CommandOutput::Keys(ids) => {
+            let ids: Vec<String> = ids.iter().map(|id| id.to_string()).collect();
+            writeln!(out, "{}", ids.join(", "))
+        },
+        CommandOutput::Projected(values) if values.is_empty() => writeln!(out, "(no rows)"),
+        CommandOutput::Projected(values) => {
+            values.iter().try_for_each(|value| writeln!(out, "{}", value))
+        },
+        CommandOutput::Values(values) if values.is_empty() => writeln!(out, "(no rows)"),
+        CommandOutput::Values(values) => {
+            values.iter().try_for_each(|value| writeln!(out, "{}", value))
+        },
+        CommandOutput::Affected(count) => writeln!(out, "{} row(s) affected.", count),
+        CommandOutput::Message(message) => writeln!(out, "{}", message),
+        CommandOutput::Error(e) => writeln!(out, "Error: {}", e),
+        CommandOutput::Batch(outputs) => {
+            outputs.iter().try_for_each(|output| write_output_as(out, output, format))
+        },
+        CommandOutput::Exit => writeln!(out, "Exiting mini_db... Goodbye!"),
+    }
+}
+
+/// Parses and executes a line against the database, printing each result.
+///
+/// A thin wrapper around [`run_command`] for the interactive shell. The line
+/// may hold several `;`-separated statements (see [`split_statements`]),
+/// which run in order until one of them is `EXIT`.
 ///
 /// # Arguments
 ///
 /// * `input` - The raw command string from the user
-/// * `db` - A mutable reference to the database
+/// * `db` - A handle to the shared database
 ///
 /// # Returns
 ///
@@ -174,97 +1092,305 @@ pub fn parse_command(input: &str) -> Result<Command, DbError> {
 /// # Examples
 ///
 /// ```no_run
-/// use mini_db::engine::Database;
+/// use mini_db::engine::DatabaseHandle;
 /// use mini_db::parser::handle_command;
 ///
-/// let mut db = Database::new("data.json")?;
-/// let should_continue = handle_command("INSERT 1 Alice 30", &mut db);
+/// let db = DatabaseHandle::new("mini_db.log")?;
+/// let should_continue = handle_command("INSERT 1 Alice 30", &db);
 /// # Ok::<(), mini_db::errors::DbError>(())
 /// ```
 pub fn handle_command(input: &str, db: &DatabaseHandle) -> bool {
-    match parse_command(input) {
-        Ok(Command::Insert {id, name, age}) => {
-            match db.insert(id, name, age) {
-                Ok(()) => println!("Inserted row with id {id}."),
-                Err(e) => eprintln!("Error inserting into db: {}", e),
-            }
-            true
-        },
+    split_statements(input)
+        .into_iter()
+        .all(|statement| {
+            let output = run_command(statement, db);
+            print_output(&output);
+            !matches!(output, CommandOutput::Exit)
+        })
+}
 
-        Ok(Command::ExecBatch { path }) => {
-            match db.exec_batch(path) {
-                Ok(()) => println!("Batch commands executed successfully."),
-                Err(e) => println!("Error executing batch commands: {}", e)
-            }
-            true
-        },
+/// Splits a line into its `;`-separated statements.
+///
+/// Semicolons inside single or double quotes do not split. Statements are
+/// trimmed and empty ones are dropped.
+///
+/// # Examples
+///
+/// ```
+/// use mini_db::parser::split_statements;
+///
+/// let statements = split_statements(r#"INSERT 1 A 20;; ALIAS s = "SELECT; STATS""#);
+/// assert_eq!(statements, vec!["INSERT 1 A 20", r#"ALIAS s = "SELECT; STATS""#]);
+/// ```
+pub fn split_statements(input: &str) -> Vec<&str> {
+    let mut statements = Vec::new();
+    let mut quote: Option<char> = None;
+    let mut start = 0;
 
-        Ok(Command::SelectById { id }) => {
-            match db.select_by_id(id) {
-                Ok(Some(row)) => println!("{:?}", row),
-                Ok(None) => println!("Row with id {} not found.", id),
-                Err(e) => eprintln!("Error fetching row by id: {}", e)
-            }
-            true
-        },
+    for (i, c) in input.char_indices() {
+        match (quote, c) {
+            (Some(open), c) if c == open => quote = None,
+            (Some(_), _) => {},
+            (None, '\'' | '"') => quote = Some(c),
+            (None, ';') => {
+                statements.push(&input[start..i]);
+                start = i + 1;
+            },
+            (None, _) => {},
+        }
+    }
+    statements.push(&input[start..]);
 
-        Ok(Command::DeleteById { id }) => {
-            match db.delete_by_id(id) {
-                Ok(true) => println!("Row with id {} deleted.", id),
-                Ok(false) => println!("No row found with id {}.", id),
-                Err(e)    => eprintln!("Error deleting row: {}", e),
-            }   
-            true
-        },
+    statements.into_iter()
+        .map(str::trim)
+        .filter(|statement| !statement.is_empty())
+        .collect()
+}
 
-        Ok(Command::Select) => {
-            let rows: Vec<Row> = db.select_all();
+/// The prompt the shell starts with.
+pub const DEFAULT_PROMPT: &str = "mini_db> ";
 
-            if rows.len() == 0 {
-                println!("(no rows)");
-                return true;
-            }
+/// State the interactive shell keeps between commands.
+pub struct ReplState {
+    /// Commands entered so far
+    pub history: History,
+    /// How rows are printed
+    pub format: OutputFormat,
+    /// Macros defined with `ALIAS`, by lowercased name
+    pub aliases: HashMap<String, String>,
+    /// Token printed by the last `RESET`, awaiting `RESET CONFIRM <token>`
+    pub pending_reset: Option<String>,
+    /// Written before each line in interactive mode, set with `PROMPT`
+    pub prompt: String,
+}
+
+impl Default for ReplState {
+    fn default() -> Self {
+        ReplState {
+            history: History::default(),
+            format: OutputFormat::default(),
+            aliases: HashMap::new(),
+            pending_reset: None,
+            prompt: DEFAULT_PROMPT.to_string(),
+        }
+    }
+}
+
+impl ReplState {
+    /// Splits a line into statements, replacing each statement that names
+    /// an alias with the statements of its body.
+    ///
+    /// Alias bodies are not expanded again, so an alias cannot recurse.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use mini_db::parser::ReplState;
+    ///
+    /// let mut state = ReplState::default();
+    /// state.aliases.insert("seed".into(), "INSERT 1 Alice 30; INSERT 2 Bob 25".into());
+    /// assert_eq!(state.expand("SEED; SELECT"), vec!["INSERT 1 Alice 30", "INSERT 2 Bob 25", "SELECT"]);
+    /// ```
+    pub fn expand(&self, input: &str) -> Vec<String> {
+        split_statements(input)
+            .into_iter()
+            .flat_map(|statement| match self.aliases.get(&statement.to_lowercase()) {
+                Some(body) => split_statements(body).into_iter().map(str::to_string).collect(),
+                None => vec![statement.to_string()],
+            })
+            .collect()
+    }
+}
+
+/// Parses and executes a line in the interactive shell.
+///
+/// The line is split into `;`-separated statements and aliases are expanded
+/// (see [`ReplState::expand`]); the statements run in order, stopping at
+/// `EXIT`. Shell-only commands are answered from `state`: `HISTORY` and
+/// `HISTORY REPLAY <n>` use its history, `FORMAT` sets its output format,
+/// `PROMPT` its prompt and `ALIAS` defines a macro. `RESET` clears nothing
+/// by itself: it prints a token, and only `RESET CONFIRM <token>` with that
+/// token clears the database. Every other command is recorded in the
+/// history and run through [`run_command`], with rows printed in the
+/// current format. History commands themselves and unparseable input are
+/// not recorded, so a replay can never replay another replay. A replayed
+/// command runs as if typed again, so a replayed `RESET` only prints a new
+/// token.
+///
+/// # Arguments
+///
+/// * `input` - The raw command string from the user
+/// * `db` - A handle to the shared database
+/// * `state` - The shell's history and settings
+///
+/// # Returns
+///
+/// Returns `true` if the program should continue running,
+/// `false` if the user issued an EXIT command.
+///
+/// # Examples
+///
+/// ```no_run
+/// use mini_db::engine::DatabaseHandle;
+/// use mini_db::parser::{handle_shell_command, ReplState};
+///
+/// let db = DatabaseHandle::new("mini_db.log")?;
+/// let mut state = ReplState::default();
+/// handle_shell_command("INSERT 1 Alice 30", &db, &mut state);
+/// handle_shell_command("FORMAT TABLE", &db, &mut state);
+/// handle_shell_command("HISTORY REPLAY 1", &db, &mut state);
+/// # Ok::<(), mini_db::errors::DbError>(())
+/// ```
+pub fn handle_shell_command(input: &str, db: &DatabaseHandle, state: &mut ReplState) -> bool {
+    // A closed stdout ends the shell like EXIT
+    run_shell_line(input, db, state, &mut io::stdout()).unwrap_or(false)
+}
 
-            for row in rows.iter() {
-                println!("{:?}", row)
+/// Runs the shell over the lines of `reader` until `EXIT` or end of input.
+///
+/// Each line runs like [`handle_shell_command`], but all output, errors
+/// included, is written to `writer`. When `interactive`, the prompt
+/// ([`DEFAULT_PROMPT`] until changed with `PROMPT`) is written before each
+/// line and end of input is answered like `EXIT`. Otherwise, e.g. when a
+/// script is piped in, no prompt is written and the database is shut down
+/// quietly at end of input.
+///
+/// # Returns
+///
+/// Returns `Ok(())` once the shell exits, or a `DbError` if reading a line,
+/// writing output or the final shutdown fails.
+///
+/// # Examples
+///
+/// ```
+/// use mini_db::engine::DatabaseHandle;
+/// use mini_db::parser::run_repl;
+///
+/// let db = DatabaseHandle::in_memory();
+/// let mut out = Vec::new();
+/// run_repl("INSERT 1 Alice 30\n".as_bytes(), &mut out, &db, false)?;
+/// assert_eq!(out, b"Inserted row with id 1.\n");
+/// # Ok::<(), mini_db::errors::DbError>(())
+/// ```
+pub fn run_repl<R: BufRead, W: Write>(mut reader: R, mut writer: W, db: &DatabaseHandle, interactive: bool) -> Result<(), DbError> {
+    let mut state = ReplState::default();
+
+    loop {
+        if interactive {
+            write!(writer, "{}", state.prompt)?;
+            writer.flush()?;
+        }
+
+        let mut input = String::new();
+        if reader.read_line(&mut input)? == 0 {
+            // End of input (Ctrl-D or the end of a piped script)
+            if interactive {
+                writeln!(writer)?;
+                run_in_format("EXIT", db, state.format, &mut writer)?;
+                return Ok(());
             }
-            true
-        }, 
+            return db.shutdown();
+        }
+
+        if !run_shell_line(&input, db, &mut state, &mut writer)? {
+            return Ok(());
+        }
+    }
+}
+
+/// Runs a shell line, writing its output to `out`, see [`handle_shell_command`].
+fn run_shell_line(input: &str, db: &DatabaseHandle, state: &mut ReplState, out: &mut dyn Write) -> io::Result<bool> {
+    for statement in state.expand(input) {
+        if !run_shell_statement(&statement, db, state, out)? {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
 
-        Ok(Command::Exit) => {
-            if let Err(e) = db.shutdown() {
-                println!("Warning: could not flush data: {}", e);
+/// Runs a single statement of a shell line, see [`handle_shell_command`].
+fn run_shell_statement(input: &str, db: &DatabaseHandle, state: &mut ReplState, out: &mut dyn Write) -> io::Result<bool> {
+    match parse_command(input) {
+        Ok(Command::History) => {
+            if state.history.is_empty() {
+                writeln!(out, "(no history)")?;
+            }
+            for (n, command) in state.history.iter() {
+                writeln!(out, "{:>4}  {}", n, command)?;
             }
-            println!("Exiting mini_db... Goodbye!");
-            false
+            Ok(true)
         },
 
-        Ok(Command::Compact) => {
-            match db.compact() {
-                Ok(()) => println!("Database compacted successfully."),
-                Err(e) => println!("Error compacting database: {}", e)
+        Ok(Command::HistoryReplay { n }) => {
+            match state.history.get(n).map(str::to_string) {
+                Some(command) => {
+                    writeln!(out, "{}", command)?;
+                    run_shell_statement(&command, db, state, out)
+                },
+                None => {
+                    writeln!(out, "No command with number {} in history.", n)?;
+                    Ok(true)
+                },
             }
-            true
-        }
+        },
+
+        Ok(Command::Format { format }) => {
+            state.history.push(input.trim());
+            state.format = format;
+            writeln!(out, "Output format set to {:?}.", format)?;
+            Ok(true)
+        },
+
+        Ok(Command::Prompt { prompt }) => {
+            state.history.push(input.trim());
+            writeln!(out, "Prompt set to {:?}.", prompt)?;
+            state.prompt = prompt;
+            Ok(true)
+        },
 
-        Ok(Command::Help) => {
-            println!("\nAvailable commands:\nEXEC BATCH <FILEPATH.TXT>\nINSERT <ID> <NAME> <AGE>\nSELECT\nSELECT WHERE ID=<ID>\nDELETE WHERE ID=<ID>\nCOMPACT\nRESET\nEXIT\n");
-            true
+        Ok(Command::DefineAlias { name, body }) => {
+            state.history.push(input.trim());
+            writeln!(out, "Alias {} defined.", name)?;
+            state.aliases.insert(name, body);
+            Ok(true)
         },
 
         Ok(Command::Reset) => {
-            match db.reset_db() {
-                Ok(_) => println!("All data cleared."),
-                Err(_) => println!("Database could not be reset."),
+            state.history.push(input.trim());
+            let token = format!("{:06}", StdRng::from_os_rng().random_range(0..1_000_000));
+            writeln!(out, "Warning: RESET deletes every row. Type RESET CONFIRM {} to proceed.", token)?;
+            state.pending_reset = Some(token);
+            Ok(true)
+        },
+
+        Ok(Command::ResetConfirm { token }) => {
+            if state.pending_reset.as_deref() == Some(token.as_str()) {
+                state.pending_reset = None;
+                let output = match db.reset_db() {
+                    Ok(()) => CommandOutput::Message("All data cleared.".to_string()),
+                    Err(e) => CommandOutput::Error(e),
+                };
+                write_output_as(out, &output, state.format)?;
+                Ok(true)
+            } else {
+                writeln!(out, "No reset is pending with that token; nothing was cleared.")?;
+                Ok(true)
             }
-            true
-        }
+        },
 
-        Err(_) => {
-            println!("Enter a valid command");
-            true
+        Ok(_) => {
+            state.history.push(input.trim());
+            run_in_format(input, db, state.format, out)
         },
+
+        Err(_) => run_in_format(input, db, state.format, out),
     }
+}
 
+/// Runs a command and writes its output to `out` in `format`.
+fn run_in_format(input: &str, db: &DatabaseHandle, format: OutputFormat, out: &mut dyn Write) -> io::Result<bool> {
+    let output = run_command(input, db);
+    write_output_as(out, &output, format)?;
 
-}
\ No newline at end of file
+    Ok(!matches!(output, CommandOutput::Exit))
+}
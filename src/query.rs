@@ -0,0 +1,328 @@
+//! Query primitives shared by the parser and the engine.
+//!
+//! This module defines the comparison operators and the small predicate
+//! AST used by filtering commands such as `DELETE WHERE AGE<18` and
+//! `SELECT WHERE AGE>=30 AND NAME=Alice`, `SELECT WHERE NOT ID!=5` or
+//! `SELECT WHERE CREATED AFTER 1700000000`, and the pattern matching behind
+//! `SELECT WHERE NAME LIKE 'Al%'`.
+
+use std::fmt;
+
+use crate::model::Row;
+
+/// A comparison operator used in `WHERE` conditions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompareOp {
+    /// `=`
+    Eq,
+    /// `!=`
+    Ne,
+    /// `<`
+    Lt,
+    /// `<=`
+    Le,
+    /// `>`
+    Gt,
+    /// `>=`
+    Ge,
+}
+
+impl CompareOp {
+    /// Operator symbols, ordered so that two-character symbols are matched
+    /// before their single-character prefixes.
+    const SYMBOLS: [(&'static str, CompareOp); 6] = [
+        ("!=", CompareOp::Ne),
+        (">=", CompareOp::Ge),
+        ("<=", CompareOp::Le),
+        ("=", CompareOp::Eq),
+        ("<", CompareOp::Lt),
+        (">", CompareOp::Gt),
+    ];
+
+    /// Evaluates `lhs <op> rhs`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use mini_db::query::CompareOp;
+    ///
+    /// assert!(CompareOp::Lt.compare(17, 18));
+    /// assert!(!CompareOp::Gt.compare(17, 18));
+    /// ```
+    pub fn compare<T: Ord>(&self, lhs: T, rhs: T) -> bool {
+        match self {
+            CompareOp::Eq => lhs == rhs,
+            CompareOp::Ne => lhs != rhs,
+            CompareOp::Lt => lhs < rhs,
+            CompareOp::Le => lhs <= rhs,
+            CompareOp::Gt => lhs > rhs,
+            CompareOp::Ge => lhs >= rhs,
+        }
+    }
+
+    /// Returns the operator's symbol, e.g. `>=`.
+    pub fn symbol(&self) -> &'static str {
+        Self::SYMBOLS.iter()
+            .find(|(_, op)| op == self)
+            .map(|(symbol, _)| *symbol)
+            .unwrap_or_default()
+    }
+
+    /// Splits a condition such as `age<18` into its field, operator and value.
+    ///
+    /// # Returns
+    ///
+    /// Returns `None` if the condition contains no known operator.
+    pub fn split_condition(condition: &str) -> Option<(&str, CompareOp, &str)> {
+        let start = condition.find(['!', '=', '<', '>'])?;
+        let rest = &condition[start..];
+
+        Self::SYMBOLS.iter().find_map(|(symbol, op)| {
+            rest.strip_prefix(symbol)
+                .map(|value| (&condition[..start], *op, value))
+        })
+    }
+}
+
+/// A row column that can appear in a `WHERE` condition or a projection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Field {
+    /// The row's `id`
+    Id,
+    /// The row's `name`
+    Name,
+    /// The row's `age`
+    Age,
+    /// The row's `created_at` Unix timestamp
+    CreatedAt,
+    /// The row's `updated_at` Unix timestamp
+    UpdatedAt,
+}
+
+impl Field {
+    /// Looks up a field by its (case-insensitive) column name; the
+    /// timestamps can also be named `CREATED` and `UPDATED`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "id" => Some(Field::Id),
+            "name" => Some(Field::Name),
+            "age" => Some(Field::Age),
+            "created_at" | "created" => Some(Field::CreatedAt),
+            "updated_at" | "updated" => Some(Field::UpdatedAt),
+            _ => None,
+        }
+    }
+
+    /// Returns the field's column name, as used for JSON keys.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Field::Id => "id",
+            Field::Name => "name",
+            Field::Age => "age",
+            Field::CreatedAt => "created_at",
+            Field::UpdatedAt => "updated_at",
+        }
+    }
+
+    /// Returns the value of this field in `row` as JSON.
+    pub fn json_value(&self, row: &Row) -> serde_json::Value {
+        match self {
+            Field::Id => row.id.into(),
+            Field::Name => row.name.clone().into(),
+            Field::Age => row.age.into(),
+            Field::CreatedAt => row.created_at.into(),
+            Field::UpdatedAt => row.updated_at.into(),
+        }
+    }
+}
+
+/// An aggregate function computed over a numeric field, e.g. `AVG(AGE)`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AggregateFunc {
+    /// The smallest value
+    Min,
+    /// The largest value
+    Max,
+    /// The arithmetic mean
+    Avg,
+}
+
+impl AggregateFunc {
+    /// Looks up an aggregate function by its (case-insensitive) name.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "min" => Some(AggregateFunc::Min),
+            "max" => Some(AggregateFunc::Max),
+            "avg" => Some(AggregateFunc::Avg),
+            _ => None,
+        }
+    }
+}
+
+/// A literal value a field is compared against.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Value {
+    /// An integer, compared against numeric fields
+    Number(i64),
+    /// A string, compared against text fields
+    Text(String),
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Number(n) => write!(f, "{}", n),
+            Value::Text(text) => write!(f, "'{}'", text),
+        }
+    }
+}
+
+/// A boolean condition evaluated against each row.
+///
+/// # Examples
+///
+/// ```
+/// use mini_db::model::Row;
+/// use mini_db::query::{CompareOp, Field, Predicate, Value};
+///
+/// let pred = Predicate::And(
+///     Box::new(Predicate::Compare { field: Field::Age, op: CompareOp::Ge, value: Value::Number(30) }),
+///     Box::new(Predicate::Compare { field: Field::Name, op: CompareOp::Eq, value: Value::Text("Alice".into()) }),
+/// );
+///
+/// assert!(pred.matches(&Row::new(1, "Alice".into(), 31)));
+/// assert!(!pred.matches(&Row::new(2, "Bob".into(), 31)));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Predicate {
+    /// Compares one field against a literal value
+    Compare {
+        field: Field,
+        op: CompareOp,
+        value: Value,
+    },
+    /// Matches rows satisfying both predicates
+    And(Box<Predicate>, Box<Predicate>),
+    /// Matches rows not satisfying the predicate
+    Not(Box<Predicate>),
+}
+
+impl Predicate {
+    /// Returns `true` if the row satisfies the predicate.
+    ///
+    /// A comparison between a field and a value of the wrong kind (e.g. a
+    /// name against a number) never matches.
+    pub fn matches(&self, row: &Row) -> bool {
+        match self {
+            Predicate::Compare { field, op, value } => match (field, value) {
+                (Field::Id, Value::Number(n)) => op.compare(i64::from(row.id), *n),
+                (Field::Age, Value::Number(n)) => op.compare(i64::from(row.age), *n),
+                (Field::CreatedAt, Value::Number(n)) => op.compare(row.created_at, *n),
+                (Field::UpdatedAt, Value::Number(n)) => op.compare(row.updated_at, *n),
+                (Field::Name, Value::Text(text)) => op.compare(row.name.as_str(), text.as_str()),
+                _ => false,
+            },
+            Predicate::And(left, right) => left.matches(row) && right.matches(row),
+            Predicate::Not(inner) => !inner.matches(row),
+        }
+    }
+}
+
+/// Writes the predicate in lowercase condition syntax, e.g.
+/// `age>=30 AND NOT name='Bob'`.
+impl fmt::Display for Predicate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Predicate::Compare { field, op, value } => {
+                write!(f, "{}{}{}", field.name(), op.symbol(), value)
+            },
+            Predicate::And(left, right) => write!(f, "{} AND {}", left, right),
+            Predicate::Not(inner) => write!(f, "NOT {}", inner),
+        }
+    }
+}
+
+/// One element of a parsed `LIKE` pattern.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LikeToken {
+    /// `%`: any sequence of characters, including none
+    AnySequence,
+    /// `_`: exactly one character
+    AnyChar,
+    /// A character matched literally (lowercased)
+    Literal(char),
+}
+
+/// Splits a `LIKE` pattern into its tokens; `\` makes the next character literal.
+fn parse_like(pattern: &str) -> Vec<LikeToken> {
+    let mut tokens = Vec::new();
+    let mut chars = pattern.chars();
+
+    while let Some(c) = chars.next() {
+        let token = match c {
+            '%' => LikeToken::AnySequence,
+            '_' => LikeToken::AnyChar,
+            '\\' => match chars.next() {
+                Some(escaped) => LikeToken::Literal(escaped),
+                None => LikeToken::Literal('\\'),
+            },
+            c => LikeToken::Literal(c),
+        };
+        tokens.push(token);
+    }
+
+    tokens
+}
+
+/// Returns `true` if `text` matches the SQL-style `LIKE` pattern.
+///
+/// `%` matches any sequence of characters (including none) and `_` any single
+/// character; either is matched literally when preceded by `\`. Matching is
+/// case-insensitive.
+///
+/// # Examples
+///
+/// ```
+/// use mini_db::query::like_matches;
+///
+/// assert!(like_matches("al%", "Alice"));
+/// assert!(like_matches("%ce", "Alice"));
+/// assert!(like_matches("A_ice", "Alice"));
+/// assert!(!like_matches("100\\%", "1000"));
+/// ```
+pub fn like_matches(pattern: &str, text: &str) -> bool {
+    let tokens = parse_like(&pattern.to_lowercase());
+    let text: Vec<char> = text.to_lowercase().chars().collect();
+
+    // Greedy matching that backtracks to the most recent `%`
+    let (mut t, mut c) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while c < text.len() {
+        match tokens.get(t) {
+            Some(LikeToken::AnySequence) => {
+                backtrack = Some((t, c));
+                t += 1;
+            },
+            Some(LikeToken::AnyChar) => {
+                t += 1;
+                c += 1;
+            },
+            Some(LikeToken::Literal(l)) if *l == text[c] => {
+                t += 1;
+                c += 1;
+            },
+            _ => match backtrack {
+                // Let the last `%` swallow one more character and retry
+                Some((star, start)) => {
+                    backtrack = Some((star, start + 1));
+                    t = star + 1;
+                    c = start + 1;
+                },
+                None => return false,
+            },
+        }
+    }
+
+    tokens[t..].iter().all(|token| *token == LikeToken::AnySequence)
+}
@@ -7,171 +7,1816 @@
 //!
 //! The log file contains JSON-encoded entries, one per line:
 //! - Insert operations: Store the full row data with a timestamp
-//! - Delete operations: Store only the row ID to be deleted
+//! - Update operations: Store the row's new data with a timestamp
+//! - Delete operations: Store the ID of the deleted row with a timestamp
 //!
-//! On startup, the log is replayed to reconstruct the database state.
+//! The first line of a log is a header carrying a magic string and the
+//! format version, e.g. `{"magic":"mini_db","version":1}`; snapshots carry
+//! the same two fields next to their rows. Files written before headers
+//! existed are read as version 0.
+//!
+//! ## Segments
+//!
+//! The active log can be rolled over into numbered segments (`<log>.1` being
+//! the most recent). Rolled segments may be gzip-compressed (`<log>.1.gz`);
+//! the active log always stays uncompressed so it can be appended to.
+//! Setting [`Storage::max_log_bytes`] rolls the log automatically once it
+//! grows past that size.
+//!
+//! ## Group commit
+//!
+//! By default every entry is written to the log as soon as it is appended.
+//! [`Storage::configure_group_commit`] instead buffers entries and writes
+//! and syncs them together once enough have accumulated or a short delay
+//! has passed, trading a bounded window of unsynced writes for throughput.
+//!
+//! ## Locking
+//!
+//! A writable storage holds an exclusive advisory lock on `<log>.lock` for
+//! as long as it is open, so a second process opening the same log gets
+//! [`DbError::AlreadyLocked`] instead of interleaving its appends. The
+//! operating system releases the lock when the storage is dropped or its
+//! process exits, so a lock file left on disk does not block later opens.
+//!
+//! ## Atomic batches
+//!
+//! A crash in the middle of a run of appends can leave some of them in the
+//! log and cut the last one off mid-line. [`Storage::append_batch`] instead
+//! first records the log's length in `<log>.batch.tmp` and syncs it, then
+//! appends the entries and removes the record once they are synced. If a
+//! record is left behind by a crash, the log is cut back to that length on
+//! open, so a batch is on disk either whole or not at all.
+//!
+//! On startup, the segments (oldest first) and then the active log are
+//! replayed to reconstruct the database state.
+//! When several entries touch the same ID, the last write wins; "last" is
+//! decided by the storage's [`ReplayOrder`]. Lines that cannot be parsed
+//! are skipped and reported to the storage's [`LogSink`], unless its
+//! [`ReplayPolicy`] is strict, in which case the first one aborts the load.
 
-use std::io::{BufRead, BufReader, Write};
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::ffi::OsString;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
 use std::path::{Path, PathBuf};
 use std::fs::{self, File, OpenOptions};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use parking_lot::{Condvar, Mutex};
 use serde::{Serialize, Deserialize};
-use chrono::Utc;
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
 
-use crate::model::Row;
+use crate::model::{Row, unix_now};
 use crate::errors::DbError;
 
+/// Extension of the snapshot written next to the log by compaction; the
+/// snapshot of `users.log` is `users.snapshot`.
+pub const SNAPSHOT_EXTENSION: &str = "snapshot";
+
+/// Magic string identifying mini_db log and snapshot files.
+pub const FORMAT_MAGIC: &str = "mini_db";
+
+/// Version of the on-disk format written by this build.
+///
+/// Headerless files from before versioning are version 0.
+pub const FORMAT_VERSION: u32 = 1;
+
+/// Identifies the format of a log or snapshot file.
+///
+/// Written as the first line of every log and flattened into snapshots.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct FileHeader {
+    /// Always [`FORMAT_MAGIC`]
+    pub magic: String,
+    /// The format version the file was written with
+    pub version: u32,
+}
+
+impl FileHeader {
+    /// Returns the header written by this build.
+    pub fn current() -> Self {
+        FileHeader {
+            magic: FORMAT_MAGIC.to_string(),
+            version: FORMAT_VERSION,
+        }
+    }
+
+    /// Checks that the header belongs to a file this build can read.
+    ///
+    /// # Returns
+    ///
+    /// Returns `UnsupportedVersion` for files from a newer format, or a
+    /// `ParseError` if the magic string does not match.
+    pub fn validate(&self) -> Result<(), DbError> {
+        if self.magic != FORMAT_MAGIC {
+            return Err(DbError::ParseError(format!("Unknown file magic {:?}", self.magic)));
+        }
+
+        if self.version > FORMAT_VERSION {
+            return Err(DbError::UnsupportedVersion {
+                found: self.version,
+                expected: FORMAT_VERSION,
+            });
+        }
+
+        Ok(())
+    }
+}
+
+/// Size of the persisted log, as reported by [`StorageBackend::log_stats`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct LogStats {
+    /// Size of the log on disk in bytes, rolled segments included
+    pub log_bytes: u64,
+    /// Number of entries (inserts, updates and deletes) in the log
+    pub entry_count: usize,
+    /// Whether a snapshot exists
+    pub snapshot_exists: bool,
+}
+
+/// Summary of a [`Storage::repair`] run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RepairReport {
+    /// Log entries that could be read and were replayed
+    pub entries_kept: usize,
+    /// Log lines that could not be parsed and were dropped
+    pub entries_dropped: usize,
+    /// Rows in the repaired snapshot
+    pub rows: usize,
+}
+
+/// A warning raised while loading the log or snapshot, such as for a corrupt
+/// line, or by a background write that failed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogWarning {
+    /// Number of the offending line within its file, starting at 1, or `0`
+    /// for a warning not tied to one line, such as an unreadable snapshot
+    pub line: usize,
+    /// What went wrong, e.g. `could not parse line 3: expected value`
+    pub message: String,
+}
+
+/// Receives the warnings raised while loading the log and snapshot, and
+/// the failures of background writes such as group commits and flushes.
+///
+/// Set [`Storage::log_sink`] to capture or silence them; the default,
+/// [`StderrSink`], prints each one to stderr.
+pub trait LogSink: Send + Sync {
+    /// Handles one warning; lines are skipped whether or not it is reported.
+    fn warn(&self, warning: &LogWarning);
+}
+
+/// The default [`LogSink`], printing warnings to stderr.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StderrSink;
+
+impl LogSink for StderrSink {
+    fn warn(&self, warning: &LogWarning) {
+        eprintln!("Warning: {}", warning.message);
+    }
+}
+
+/// On-disk layout of a versioned snapshot.
+#[derive(Serialize, Deserialize)]
+struct Snapshot<'a> {
+    #[serde(flatten)]
+    header: FileHeader,
+    rows: Cow<'a, [Row]>,
+}
+
+/// On-disk layout of a portable backup written by [`write_backup`].
+#[derive(Serialize, Deserialize)]
+struct Backup<'a> {
+    #[serde(flatten)]
+    header: FileHeader,
+    /// Unix timestamp of when the backup was taken
+    created_at: i64,
+    /// Number of rows in the backup
+    row_count: usize,
+    rows: Cow<'a, [Row]>,
+}
+
+/// Any snapshot layout that can be read; version 0 is a bare array of rows.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum SnapshotFile {
+    Versioned(Snapshot<'static>),
+    Legacy(Vec<Row>),
+}
+
 /// Represents a single entry in the append-only log.
 ///
 /// Each log entry is serialized as JSON and written to a new line.
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub enum LogEntry {
     /// Represents an insert operation
     Insert {
         /// The row that was inserted
         row: Row,
-        /// Unix timestamp when the insert occurred
+        /// Unix timestamp when the insert occurred, `0` (or missing) when
+        /// timestamps are not recorded
+        #[serde(default)]
+        timestamp: i64
+    },
+    /// Represents an update that replaces a row's data
+    Update {
+        /// The row's data after the update
+        row: Row,
+        /// Unix timestamp when the update occurred, `0` (or missing) when
+        /// timestamps are not recorded
+        #[serde(default)]
         timestamp: i64
     },
     /// Represents a delete operation
     Delete {
         /// The ID of the row that was deleted
         id: u32,
+        /// Unix timestamp when the delete occurred, `0` for deletes logged
+        /// before they carried one
+        #[serde(default)]
+        timestamp: i64,
+    }
+}
+
+impl LogEntry {
+    /// Returns the ID of the row this entry applies to.
+    pub fn id(&self) -> u32 {
+        match self {
+            LogEntry::Insert { row, .. } | LogEntry::Update { row, .. } => row.id,
+            LogEntry::Delete { id, .. } => *id,
+        }
+    }
+}
+
+/// Extension appended to compressed log segments.
+const GZ_SUFFIX: &str = ".gz";
+
+/// Returns `path` with `suffix` appended to its file name.
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = OsString::from(path.as_os_str());
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+/// Streams `value` as JSON to a temporary file next to `path`, syncs it and
+/// renames it over `path`, so readers see either the old or the new file.
+///
+/// The JSON is written through a buffer as it is serialized, never held in
+/// memory as a whole.
+fn write_json_atomically(path: &Path, value: &impl Serialize) -> Result<(), DbError> {
+    let tmp_path = with_suffix(path, ".tmp");
+
+    let tmp_file = OpenOptions::new()
+                                            .create(true)
+                                            .truncate(true)
+                                            .write(true)
+                                            .open(&tmp_path)?;
+
+    let mut writer = BufWriter::new(tmp_file);
+    serde_json::to_writer(&mut writer, value)?;
+    let tmp_file = writer.into_inner().map_err(io::IntoInnerError::into_error)?;
+    tmp_file.sync_all()?;
+
+    fs::rename(tmp_path, path)?;
+
+    Ok(())
+}
+
+/// Returns the lock file guarding `log` against concurrent writers.
+fn lock_path(log: &Path) -> PathBuf {
+    with_suffix(log, ".lock")
+}
+
+/// Returns the temporary file in which [`Storage::append_batch`] records
+/// the length of `log` before the batch is appended.
+fn batch_tmp_path(log: &Path) -> PathBuf {
+    with_suffix(log, ".batch.tmp")
+}
+
+/// Undoes a batch that a crash left half-appended to `log`, by cutting the
+/// log back to the length recorded before the batch and removing the record.
+///
+/// A record without its trailing newline was cut short before the batch
+/// touched the log, so it is only removed.
+fn recover_batch(log: &Path) -> Result<(), DbError> {
+    let tmp_path = batch_tmp_path(log);
+    let record = match fs::read_to_string(&tmp_path) {
+        Ok(record) => record,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e.into()),
+    };
+
+    if let Some(len) = record.strip_suffix('\n').and_then(|len| len.parse::<u64>().ok()) {
+        let file = OpenOptions::new().write(true).open(log)?;
+        if file.metadata()?.len() > len {
+            file.set_len(len)?;
+            file.sync_all()?;
+        }
+    }
+
+    fs::remove_file(&tmp_path)?;
+    sync_parent_dir(log)
+}
+
+/// Syncs the directory holding `path`, so a file created in or removed from
+/// it stays created or removed after a crash. Does nothing off Unix, where
+/// directories cannot be opened for syncing.
+fn sync_parent_dir(path: &Path) -> Result<(), DbError> {
+    #[cfg(unix)]
+    if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+        File::open(parent)?.sync_all()?;
+    }
+    #[cfg(not(unix))]
+    let _ = path;
+
+    Ok(())
+}
+
+/// Returns `true` if `path` names a gzip-compressed segment.
+fn is_compressed(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "gz")
+}
+
+/// Gzip-compresses `path` into `<path>.gz` and removes the original.
+///
+/// The compressed data is written to a temporary file first and renamed
+/// into place, so a reader never observes a partially written `.gz` file.
+fn compress_file(path: &Path) -> Result<(), DbError> {
+    let gz_path = with_suffix(path, GZ_SUFFIX);
+    let tmp_path = with_suffix(&gz_path, ".tmp");
+
+    let mut encoder = GzEncoder::new(File::create(&tmp_path)?, Compression::default());
+    io::copy(&mut File::open(path)?, &mut encoder)?;
+
+    let tmp_file = encoder.finish()?;
+    tmp_file.sync_all()?;
+
+    fs::rename(&tmp_path, &gz_path)?;
+    fs::remove_file(path)?;
+
+    Ok(())
+}
+
+/// Gives a row logged before rows carried timestamps those of its entry.
+fn backfill_timestamps(row: &mut Row, timestamp: i64) {
+    if row.created_at == 0 {
+        row.created_at = timestamp;
+    }
+    if row.updated_at == 0 {
+        row.updated_at = timestamp;
+    }
+}
+
+/// A deleted row's ID and the Unix timestamp of its delete, as returned by
+/// [`Storage::load_with_tombstones`].
+pub type Tombstone = (u32, i64);
+
+/// Decides the order in which log entries are applied during replay.
+///
+/// Whichever entry is applied last for an ID determines its final state.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ReplayOrder {
+    /// Apply entries in the order they appear in the file (the default)
+    #[default]
+    LogOrder,
+    /// Apply entries in timestamp order, keeping file order for ties.
+    ///
+    /// Useful for logs merged from multiple sources, where the physical order
+    /// may not reflect the causal order. Deletes logged without a timestamp
+    /// inherit the timestamp of the entry physically preceding them.
+    Timestamp,
+}
+
+/// Decides what happens when replay meets a log line it cannot read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ReplayPolicy {
+    /// Skip the line, report it to the storage's [`LogSink`] and go on (the default)
+    #[default]
+    Lenient,
+    /// Stop reading and fail with `DbError::CorruptLog`, so data is never
+    /// lost silently; this includes a last line cut off by a crash
+    Strict,
+}
+
+/// Accumulates rows while log entries are applied.
+///
+/// Rows keep the position of their first insert; removed rows leave an
+/// empty slot so deletes don't shift the remaining entries.
+struct Replay {
+    /// Row slots in insertion order, `None` once deleted
+    slots: Vec<Option<Row>>,
+    /// Maps row ID -> slot holding the live row
+    positions: HashMap<u32, usize>,
+}
+
+impl Replay {
+    fn new() -> Self {
+        Self::with_rows(Vec::new())
+    }
+
+    /// Starts the replay from existing rows, e.g. those of a snapshot.
+    fn with_rows(rows: Vec<Row>) -> Self {
+        let mut replay = Replay {
+            slots: Vec::with_capacity(rows.len()),
+            positions: HashMap::with_capacity(rows.len()),
+        };
+        for row in rows {
+            replay.upsert(row);
+        }
+        replay
+    }
+
+    /// Applies one entry; an insert or update for a live ID replaces the
+    /// existing row, and an update for a missing ID inserts it.
+    ///
+    /// A second insert for a live ID (which a well-formed log never holds)
+    /// keeps the later row, with a warning reported to `sink`.
+    ///
+    /// Rows logged before timestamps existed take theirs from the entry.
+    fn apply(&mut self, entry: LogEntry, sink: &dyn LogSink) {
+        match entry {
+            LogEntry::Insert { row, timestamp } if self.positions.contains_key(&row.id) => {
+                sink.warn(&LogWarning {
+                    line: 0,
+                    message: format!("log inserts id {} again without deleting it; keeping the later row", row.id),
+                });
+                self.apply(LogEntry::Update { row, timestamp }, sink);
+            },
+            LogEntry::Insert { mut row, timestamp } | LogEntry::Update { mut row, timestamp } => {
+                backfill_timestamps(&mut row, timestamp);
+                self.upsert(row);
+            },
+            LogEntry::Delete { id, .. } => {
+                if let Some(slot) = self.positions.remove(&id) {
+                    self.slots[slot] = None;
+                }
+            }
+        }
+    }
+
+    fn upsert(&mut self, row: Row) {
+        match self.positions.get(&row.id) {
+            Some(&slot) => self.slots[slot] = Some(row),
+            None => {
+                self.positions.insert(row.id, self.slots.len());
+                self.slots.push(Some(row));
+            }
+        }
+    }
+
+    fn into_rows(self) -> Vec<Row> {
+        self.slots.into_iter().flatten().collect()
+    }
+}
+
+/// Applies log entries on top of `base` with the same last-write-wins
+/// rules used when a log is replayed.
+///
+/// Useful for implementing [`StorageBackend::load_onto`] in custom backends.
+///
+/// # Examples
+///
+/// ```
+/// use mini_db::model::Row;
+/// use mini_db::storage::{replay, LogEntry};
+///
+/// let base = vec![Row::new(1, "Alice".into(), 30)];
+/// let rows = replay(base, [
+///     LogEntry::Insert { row: Row::new(2, "Bob".into(), 40), timestamp: 100 },
+///     LogEntry::Delete { id: 1, timestamp: 200 },
+/// ]);
+/// assert_eq!(rows, vec![Row::new(2, "Bob".into(), 40)]);
+/// ```
+pub fn replay(base: Vec<Row>, entries: impl IntoIterator<Item = LogEntry>) -> Vec<Row> {
+    replay_reporting(base, entries, &StderrSink)
+}
+
+/// Like [`replay`], but reports warnings to `sink` instead of stderr.
+fn replay_reporting(base: Vec<Row>, entries: impl IntoIterator<Item = LogEntry>, sink: &dyn LogSink) -> Vec<Row> {
+    let mut replay = Replay::with_rows(base);
+    for entry in entries {
+        replay.apply(entry, sink);
+    }
+    replay.into_rows()
+}
+
+/// Prefixes a `CorruptLog` error's detail with the file it was found in, so
+/// its line number can be told apart between the log and its segments.
+fn name_corrupt_file(path: &Path, e: DbError) -> DbError {
+    match e {
+        DbError::CorruptLog { line, detail } => DbError::CorruptLog {
+            line,
+            detail: format!("{}: {}", path.display(), detail),
+        },
+        e => e,
+    }
+}
+
+/// Writes `rows` to a single self-contained backup file at `path`.
+///
+/// The backup is a JSON object holding the format header, the time it was
+/// taken, the row count and the rows. It is independent of any log and is
+/// written atomically.
+///
+/// # Returns
+///
+/// Returns `Ok(())` on success or a `DbError` if the file cannot be written.
+pub fn write_backup(path: &Path, rows: &[Row]) -> Result<(), DbError> {
+    let backup = Backup {
+        header: FileHeader::current(),
+        created_at: unix_now(),
+        row_count: rows.len(),
+        rows: Cow::Borrowed(rows),
+    };
+
+    write_json_atomically(path, &backup)
+}
+
+/// Reads the rows of a backup written by [`write_backup`].
+///
+/// # Returns
+///
+/// Returns the rows, or a `DbError` if the file cannot be read, is not a
+/// backup, has an unsupported format version, or its row count does not
+/// match its rows.
+pub fn read_backup(path: &Path) -> Result<Vec<Row>, DbError> {
+    let reader = BufReader::new(File::open(path)?);
+    let backup: Backup = serde_json::from_reader(reader)?;
+    backup.header.validate()?;
+
+    if backup.row_count != backup.rows.len() {
+        return Err(DbError::ParseError(format!(
+            "Backup lists {} rows but holds {}", backup.row_count, backup.rows.len()
+        )));
+    }
+
+    Ok(backup.rows.into_owned())
+}
+
+/// Reads the entries of a JSON Lines file in the log's format, such as
+/// another instance's log.
+///
+/// A header on the first line is checked and skipped. Lines that cannot be
+/// parsed are skipped and reported to `sink`.
+///
+/// # Returns
+///
+/// Returns the entries in file order along with the number of lines
+/// skipped, or a `DbError` if the file cannot be opened or its header names
+/// an unsupported format version.
+pub fn read_entry_file(path: &Path, sink: &dyn LogSink) -> Result<(Vec<LogEntry>, usize), DbError> {
+    let mut entries = Vec::new();
+    let skipped = Storage::read_entries(
+        BufReader::new(File::open(path)?),
+        &mut 0,
+        sink,
+        ReplayPolicy::Lenient,
+        &mut |_, entry| entries.push(entry),
+    )?;
+
+    Ok((entries, skipped))
+}
+
+/// A place the database persists its log and snapshot to.
+///
+/// [`Storage`] is the file-based implementation; other backends (in-memory,
+/// remote object stores, ...) can be plugged into
+/// [`Database::from_backend`](crate::engine::Database::from_backend).
+///
+/// Writes go to an append-only log of inserts, updates and deletes. A
+/// snapshot holds a full copy of the rows; on open, the log is replayed
+/// on top of it.
+pub trait StorageBackend: Send + Sync {
+    /// Appends an insert of `row` to the log.
+    fn append_entry(&mut self, row: &Row) -> Result<(), DbError>;
+
+    /// Appends an insert of every row in `rows` to the log.
+    ///
+    /// Appends them one at a time by default; backends that can should
+    /// write the batch all-or-nothing.
+    fn append_entries(&mut self, rows: &[Row]) -> Result<(), DbError> {
+        rows.iter().try_for_each(|row| self.append_entry(row))
+    }
+
+    /// Appends an update replacing the row with `row.id` to the log.
+    fn append_update(&mut self, row: &Row) -> Result<(), DbError>;
+
+    /// Appends a delete of the row with `id` to the log.
+    fn append_delete(&mut self, id: u32) -> Result<(), DbError>;
+
+    /// Replays the log on top of `base` and returns the resulting rows.
+    ///
+    /// See [`replay`] for a helper applying entries with the standard rules.
+    fn load_onto(&self, base: Vec<Row>) -> Result<Vec<Row>, DbError>;
+
+    /// Replays the log from an empty table.
+    fn load_all(&self) -> Result<Vec<Row>, DbError> {
+        self.load_onto(Vec::new())
+    }
+
+    /// Replays only the log entries made at or before `cutoff`.
+    ///
+    /// Backends that do not record entry times return an `Unsupported`
+    /// I/O error.
+    fn load_until(&self, cutoff: i64) -> Result<Vec<Row>, DbError> {
+        let _ = cutoff;
+        Err(io::Error::new(io::ErrorKind::Unsupported, "backend does not record entry times").into())
+    }
+
+    /// Makes every appended entry durable.
+    fn flush(&mut self) -> Result<(), DbError>;
+
+    /// Replaces the snapshot with `rows`.
+    fn snapshot_write(&mut self, rows: &[Row]) -> Result<(), DbError>;
+
+    /// Returns the snapshot's rows, or `None` if there is no snapshot.
+    fn snapshot_read(&self) -> Result<Option<Vec<Row>>, DbError>;
+
+    /// Discards every log entry, e.g. once a snapshot covers them.
+    fn log_truncate(&mut self) -> Result<(), DbError>;
+
+    /// Returns the time of the most recent insert of each ID in the log.
+    ///
+    /// Backends that do not record insert times return an empty map, so
+    /// retention never deletes their rows.
+    fn insert_timestamps(&self) -> Result<HashMap<u32, i64>, DbError> {
+        Ok(HashMap::new())
+    }
+
+    /// Reports the size of the log and whether a snapshot exists.
+    ///
+    /// Backends that do not track these report all zeros.
+    fn log_stats(&self) -> Result<LogStats, DbError> {
+        Ok(LogStats::default())
+    }
+
+    /// Returns where warnings about unreadable lines are reported.
+    ///
+    /// Defaults to [`StderrSink`].
+    fn log_sink(&self) -> &dyn LogSink {
+        &StderrSink
+    }
+
+    /// Drops superseded log entries, returning how many were removed.
+    ///
+    /// Does nothing by default.
+    fn vacuum(&mut self) -> Result<usize, DbError> {
+        Ok(0)
+    }
+
+    /// Discards all persisted data, log and snapshot alike.
+    fn reset(&mut self) -> Result<(), DbError> {
+        self.log_truncate()?;
+        self.snapshot_write(&[])
+    }
+}
+
+/// Manages persistent storage using an append-only log.
+///
+/// The storage layer provides:
+/// - Atomic write operations (each entry is a single line)
+/// - Crash recovery through log replay
+/// - Sequential access for efficient bulk loading
+pub struct Storage {
+    /// Path to the log file on disk
+    pub path: PathBuf,
+    /// File handle for append operations
+    pub file: File,
+    /// Order in which entries are applied by `load_all`
+    pub replay_order: ReplayOrder,
+    /// Whether unreadable log lines are skipped or abort reading the log
+    pub replay_policy: ReplayPolicy,
+    /// Whether segments are gzip-compressed in the background after rolling
+    pub compress_rolled: bool,
+    /// Size in bytes past which the active log is rolled into a segment
+    /// after an append; `None` never rolls automatically
+    pub max_log_bytes: Option<u64>,
+    /// Whether entries carry timestamps; when `false`, entry timestamps and
+    /// the logged rows' `created_at`/`updated_at` are written as `0`, so the
+    /// log's contents depend only on the writes made
+    pub record_timestamps: bool,
+    /// Background compression of the most recently rolled segment, if running
+    compression: Option<JoinHandle<Result<(), DbError>>>,
+    /// Buffered appends and their timer thread, when group commit is enabled
+    group: Option<GroupCommitHandle>,
+    /// Where warnings about unreadable or corrupt log lines, and failed
+    /// group commits, are reported; group commit's timer thread keeps the
+    /// sink set when it was configured
+    pub log_sink: Arc<dyn LogSink>,
+    /// Number of entry lines read so far, parsed or skipped
+    lines_read: AtomicUsize,
+    /// The locked `<log>.lock`, held until the storage is dropped; `None`
+    /// when opened read-only
+    lock: Option<File>,
+}
+
+/// Entries buffered by group commit, shared with the timer thread.
+struct GroupCommit {
+    /// Serialized lines waiting to be written, each ending in a newline
+    pending: Vec<u8>,
+    /// Number of lines in `pending`
+    entries: usize,
+    /// When the oldest pending line was buffered
+    since: Option<Instant>,
+    /// A clone of the active log's append handle
+    file: File,
+    /// Set when group commit is turned off, telling the timer thread to exit
+    closed: bool,
+}
+
+impl GroupCommit {
+    /// Writes and syncs the pending lines, if any.
+    fn commit(&mut self) -> Result<(), DbError> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+
+        self.file.write_all(&self.pending)?;
+        self.file.sync_all()?;
+        self.discard();
+
+        Ok(())
+    }
+
+    /// Drops the pending lines without writing them.
+    fn discard(&mut self) {
+        self.pending.clear();
+        self.entries = 0;
+        self.since = None;
+    }
+}
+
+/// Group commit state owned by a [`Storage`].
+struct GroupCommitHandle {
+    shared: Arc<(Mutex<GroupCommit>, Condvar)>,
+    /// Commit once this many entries are pending
+    max_entries: usize,
+    /// Commits pending entries once they are `max_delay` old
+    timer: JoinHandle<()>,
+}
+
+impl Storage {
+    /// Creates a new storage instance, opening or creating the log file.
+    ///
+    /// A relative `path` is resolved under the `data` directory of the
+    /// current working directory at the time of the call; see
+    /// [`with_base`](Self::with_base).
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path to the log file
+    ///
+    /// # Returns
+    ///
+    /// Returns a `Storage` instance ready for append operations,
+    /// or a `DbError` if the file cannot be opened/created.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use mini_db::storage::Storage;
+    ///
+    /// let storage = Storage::new("mini_db.log")?;
+    /// # Ok::<(), mini_db::errors::DbError>(())
+    /// ```
+    pub fn new(path: impl AsRef<Path>) -> Result<Self, DbError> {
+        Self::with_base(std::env::current_dir()?, path)
+    }
+
+    /// Creates a new storage instance whose relative `path` is resolved
+    /// under `<base>/data`, creating the log's directory if it is missing.
+    ///
+    /// The resolved path is kept, so later changes of the working directory
+    /// do not move the database. An absolute `path` is used as is.
+    ///
+    /// # Arguments
+    ///
+    /// * `base` - The directory holding the `data` directory
+    /// * `path` - Path to the log file
+    ///
+    /// # Returns
+    ///
+    /// Returns a `Storage` instance ready for append operations, or a
+    /// `DbError` if the log's directory cannot be created (`DataDirError`,
+    /// e.g. for lack of permissions), another storage holds the log's lock
+    /// (`AlreadyLocked`) or the file cannot be opened/created.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use mini_db::storage::Storage;
+    ///
+    /// // Uses /srv/app/data/mini_db.log wherever the binary is started from
+    /// let storage = Storage::with_base("/srv/app", "mini_db.log")?;
+    /// # Ok::<(), mini_db::errors::DbError>(())
+    /// ```
+    pub fn with_base(base: impl AsRef<Path>, path: impl AsRef<Path>) -> Result<Self, DbError> {
+        let path = base.as_ref().join("data").join(path.as_ref());
+
+        // Create the directory holding the log (and its snapshot) up front,
+        // so a missing directory doesn't surface as a bare `NotFound`
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(|source| DbError::DataDirError {
+                path: parent.to_path_buf(),
+                source,
+            })?;
+        }
+
+        let lock = File::create(lock_path(&path))?;
+        lock.try_lock().map_err(|e| match e {
+            fs::TryLockError::WouldBlock => DbError::AlreadyLocked { path: path.clone() },
+            fs::TryLockError::Error(e) => e.into(),
+        })?;
+
+        // A batch whose record is still on disk never finished
+        if path.exists() {
+            recover_batch(&path)?;
+        }
+
+        // Open file in append mode, creating it if it doesn't exist
+        let file = OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(&path)?;
+
+        let mut storage = Self::from_file(path, file)?;
+        storage.lock = Some(lock);
+        Ok(storage)
+    }
+
+    /// Opens an existing log for reading only.
+    ///
+    /// The path is resolved as in [`new`](Self::new), but neither the data
+    /// directory nor the log is created, and the log is opened without write
+    /// access, so appending to the returned storage fails. No lock is taken,
+    /// so this works while another process has the log open.
+    ///
+    /// # Returns
+    ///
+    /// Returns the storage, or a `DbError` if the log does not exist, cannot
+    /// be opened or has an unsupported format version.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use mini_db::storage::Storage;
+    ///
+    /// let storage = Storage::open_readonly("mini_db.log")?;
+    /// # Ok::<(), mini_db::errors::DbError>(())
+    /// ```
+    pub fn open_readonly(path: impl AsRef<Path>) -> Result<Self, DbError> {
+        let path = std::env::current_dir()?.join("data").join(path.as_ref());
+        let file = File::open(&path)?;
+
+        Self::from_file(path, file)
+    }
+
+    /// Wraps an opened log, checking its header, with default settings.
+    fn from_file(path: PathBuf, file: File) -> Result<Self, DbError> {
+        if let Some(header) = Self::read_header(&path)? {
+            header.validate()?;
+        }
+
+        Ok(Storage {
+            path,
+            file,
+            replay_order: ReplayOrder::default(),
+            replay_policy: ReplayPolicy::default(),
+            compress_rolled: false,
+            max_log_bytes: None,
+            record_timestamps: true,
+            compression: None,
+            group: None,
+            log_sink: Arc::new(StderrSink),
+            lines_read: AtomicUsize::new(0),
+            lock: None,
+        })
+    }
+
+    /// Reads the header line of a log file.
+    ///
+    /// # Returns
+    ///
+    /// Returns `None` for a missing, empty or headerless (version 0) log,
+    /// or a `DbError` if the file cannot be read.
+    pub fn read_header(path: &Path) -> Result<Option<FileHeader>, DbError> {
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        let mut first_line = String::new();
+        BufReader::new(File::open(path)?).read_line(&mut first_line)?;
+
+        Ok(serde_json::from_str(first_line.trim()).ok())
+    }
+
+    /// Rewrites a version 0 log and snapshot in the current format.
+    ///
+    /// The log gets its header and every row its timestamps; a headerless
+    /// snapshot is rewritten with its version. The original of each rewritten
+    /// file is kept next to it with a `.bak` suffix. Files that are already
+    /// current (or missing) are left untouched, so migrating twice is a no-op.
+    /// Rolled segments are not rewritten; they are read fine in either format.
+    ///
+    /// # Returns
+    ///
+    /// Returns `true` if anything was rewritten, or a `DbError` if reading,
+    /// backing up or replacing a file fails.
+    pub fn migrate(&mut self) -> Result<bool, DbError> {
+        let mut migrated = false;
+
+        let log_is_v0 = Self::read_header(&self.path)?.is_none()
+            && fs::metadata(&self.path)?.len() > 0;
+        if log_is_v0 {
+            self.flush()?;
+            fs::copy(&self.path, with_suffix(&self.path, ".bak"))?;
+
+            let mut entries = Vec::new();
+            let reader = BufReader::new(File::open(&self.path)?);
+            Self::read_entries(reader, &mut 0, self.log_sink.as_ref(), self.replay_policy, &mut |timestamp, entry| entries.push((timestamp, entry)))
+                .map_err(|e| name_corrupt_file(&self.path, e))?;
+
+            let entries = entries.into_iter().map(|(timestamp, mut entry)| {
+                if let LogEntry::Insert { row, .. } | LogEntry::Update { row, .. } = &mut entry {
+                    backfill_timestamps(row, timestamp);
+                }
+                entry
+            });
+            self.replace_log(entries)?;
+            migrated = true;
+        }
+
+        let snapshot_path = self.snapshot_path();
+        if snapshot_path.exists() {
+            let reader = BufReader::new(File::open(&snapshot_path)?);
+            if let SnapshotFile::Legacy(rows) = serde_json::from_reader(reader)? {
+                fs::copy(&snapshot_path, with_suffix(&snapshot_path, ".bak"))?;
+                self.write_snapshot(&rows)?;
+                migrated = true;
+            }
+        }
+
+        Ok(migrated)
+    }
+
+    /// Rebuilds a clean snapshot and log from whatever can still be read.
+    ///
+    /// The snapshot, if readable, is used as the base; every log entry that
+    /// parses is replayed on top of it and corrupt lines are dropped. The
+    /// result is written to a fresh snapshot, after which the log is replaced
+    /// by an empty one and its segments are removed. The original log and
+    /// snapshot are kept next to them with a `.bak` suffix.
+    ///
+    /// # Returns
+    ///
+    /// Returns how many entries were kept and dropped, or a `DbError` if the
+    /// files cannot be read, backed up or rewritten.
+    pub fn repair(&mut self) -> Result<RepairReport, DbError> {
+        self.flush()?;
+
+        let base = StorageBackend::snapshot_read(self)?.unwrap_or_default();
+        // Dropping unreadable lines is the point of a repair
+        let (entries, entries_dropped) = self.read_log(ReplayPolicy::Lenient)?;
+        let entries_kept = entries.len();
+
+        let mut entries = entries;
+        if self.replay_order == ReplayOrder::Timestamp {
+            entries.sort_by_key(|(timestamp, _)| *timestamp);
+        }
+        let rows = replay_reporting(base, entries.into_iter().map(|(_, entry)| entry), self.log_sink.as_ref());
+
+        for path in [self.path.clone(), self.snapshot_path()] {
+            if path.exists() {
+                fs::copy(&path, with_suffix(&path, ".bak"))?;
+            }
+        }
+
+        self.write_snapshot(&rows)?;
+        self.replace_log([])?;
+        self.remove_segments()?;
+
+        Ok(RepairReport {
+            entries_kept,
+            entries_dropped,
+            rows: rows.len(),
+        })
+    }
+
+    /// Rewrites the log keeping only the final state of each ID.
+    ///
+    /// Segments and the active log are replayed and written back as a single
+    /// log with one entry per surviving row; inserts later deleted and
+    /// superseded updates are dropped. Rows first logged by an update (their
+    /// insert predates the log, e.g. in a snapshot) stay updates, and deletes
+    /// of such rows are kept so they still hide the older copy. The new log
+    /// is written to a temporary file and renamed into place.
+    ///
+    /// Lighter than compaction, as no snapshot is written.
+    ///
+    /// # Returns
+    ///
+    /// Returns the number of entries removed, or a `DbError` if the log
+    /// cannot be read or replaced.
+    pub fn vacuum(&mut self) -> Result<usize, DbError> {
+        self.flush()?;
+
+        let mut entries = self.read_all_entries()?;
+        if self.replay_order == ReplayOrder::Timestamp {
+            entries.sort_by_key(|(timestamp, _)| *timestamp);
+        }
+        let before = entries.len();
+
+        // IDs in the order they first appear, and those first seen as an insert
+        let mut seen = Vec::new();
+        let mut seen_ids = HashSet::new();
+        let mut born = HashSet::new();
+        let mut deleted_at = HashMap::new();
+        let mut replay = Replay::new();
+        for (_, entry) in entries {
+            let id = entry.id();
+            if seen_ids.insert(id) {
+                seen.push(id);
+                if matches!(entry, LogEntry::Insert { .. }) {
+                    born.insert(id);
+                }
+            }
+            if let LogEntry::Delete { timestamp, .. } = entry {
+                deleted_at.insert(id, timestamp);
+            }
+            replay.apply(entry, self.log_sink.as_ref());
+        }
+
+        let rows = replay.into_rows();
+        let live: HashSet<u32> = rows.iter().map(|row| row.id).collect();
+
+        let mut kept: Vec<LogEntry> = rows.into_iter()
+            .map(|row| {
+                if born.contains(&row.id) {
+                    LogEntry::Insert { timestamp: row.created_at, row }
+                } else {
+                    LogEntry::Update { timestamp: row.updated_at, row }
+                }
+            })
+            .collect();
+        kept.extend(seen.into_iter()
+            .filter(|id| !live.contains(id) && !born.contains(id))
+            .map(|id| LogEntry::Delete { id, timestamp: deleted_at[&id] }));
+
+        let removed = before - kept.len();
+        self.replace_log(kept)?;
+        self.remove_segments()?;
+
+        Ok(removed)
+    }
+
+    /// Atomically replaces the active log with `entries`, preceded by the header.
+    ///
+    /// The entries are written to a temporary file which is synced and
+    /// renamed over the log; the append handle is then reopened.
+    fn replace_log(&mut self, entries: impl IntoIterator<Item = LogEntry>) -> Result<(), DbError> {
+        self.commit_pending()?;
+        let tmp_path = with_suffix(&self.path, ".tmp");
+        let mut tmp_file = File::create(&tmp_path)?;
+
+        writeln!(tmp_file, "{}", serde_json::to_string(&FileHeader::current())?)?;
+        for entry in entries {
+            writeln!(tmp_file, "{}", serde_json::to_string(&entry)?)?;
+        }
+        tmp_file.sync_all()?;
+        fs::rename(&tmp_path, &self.path)?;
+
+        self.reopen_log()
+    }
+
+    /// Returns the directory containing the log file.
+    ///
+    /// Snapshots are written to this directory so they live next to the log.
+    pub fn data_dir(&self) -> PathBuf {
+        self.path
+            .parent()
+            .map(Path::to_path_buf)
+            .unwrap_or_default()
+    }
+
+    /// Returns the path of the snapshot belonging to this log: the log's path
+    /// with its extension replaced by `.snapshot`.
+    pub fn snapshot_path(&self) -> PathBuf {
+        self.path.with_extension(SNAPSHOT_EXTENSION)
+    }
+
+    /// Deletes the snapshot belonging to this log, if there is one.
+    pub fn remove_snapshot(&self) -> Result<(), DbError> {
+        match fs::remove_file(self.snapshot_path()) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Atomically writes the snapshot next to the log.
+    fn write_snapshot(&self, rows: &[Row]) -> Result<(), DbError> {
+        let snapshot = Snapshot {
+            header: FileHeader::current(),
+            rows: Cow::Borrowed(rows),
+        };
+
+        write_json_atomically(&self.snapshot_path(), &snapshot)
+    }
+
+    /// Reads and validates the snapshot at `path`, in either format version.
+    fn read_snapshot(&self, path: &Path) -> Result<Vec<Row>, DbError> {
+        let file = File::open(path)?;
+        let reader = BufReader::new(file);
+
+        match serde_json::from_reader(reader)? {
+            SnapshotFile::Versioned(snapshot) => {
+                snapshot.header.validate()?;
+                Ok(snapshot.rows.into_owned())
+            },
+            SnapshotFile::Legacy(rows) => Ok(rows),
+        }
+    }
+
+    /// Returns the path of rolled segment `n` (uncompressed form).
+    fn segment_path(&self, n: usize) -> PathBuf {
+        with_suffix(&self.path, &format!(".{}", n))
+    }
+
+    /// Lists the rolled segments as `(number, path)`, most recent first.
+    ///
+    /// If both the compressed and uncompressed form of a segment exist
+    /// (compression was interrupted), the uncompressed one is used.
+    fn segments(&self) -> Vec<(usize, PathBuf)> {
+        let mut segments = Vec::new();
+
+        for n in 1.. {
+            let plain = self.segment_path(n);
+            let compressed = with_suffix(&plain, GZ_SUFFIX);
+
+            if plain.exists() {
+                segments.push((n, plain));
+            } else if compressed.exists() {
+                segments.push((n, compressed));
+            } else {
+                break;
+            }
+        }
+
+        segments
+    }
+
+    /// Returns the paths of all rolled segments, oldest first.
+    ///
+    /// This is the order in which they are replayed by `load_all`.
+    pub fn segment_paths(&self) -> Vec<PathBuf> {
+        self.segments().into_iter().rev().map(|(_, path)| path).collect()
+    }
+
+    /// Rolls the active log over into segment `<log>.1` and starts a fresh log.
+    ///
+    /// Existing segments are shifted up by one (`<log>.1` becomes `<log>.2`,
+    /// and so on), keeping their compression. If `compress_rolled` is set, the
+    /// new segment is compressed on a background thread.
+    ///
+    /// # Returns
+    ///
+    /// Returns `Ok(())` on success or a `DbError` if flushing, renaming or
+    /// reopening the log fails.
+    pub fn roll_segment(&mut self) -> Result<(), DbError> {
+        self.flush()?;
+
+        for (n, path) in self.segments().into_iter().rev() {
+            let mut next = self.segment_path(n + 1);
+            if is_compressed(&path) {
+                next = with_suffix(&next, GZ_SUFFIX);
+            }
+            fs::rename(&path, next)?;
+        }
+
+        let rolled = self.segment_path(1);
+        fs::rename(&self.path, &rolled)?;
+
+        self.reopen_log()?;
+
+        if self.compress_rolled {
+            self.compression = Some(thread::spawn(move || compress_file(&rolled)));
+        }
+
+        Ok(())
+    }
+
+    /// Rolls the active log into a segment if it has grown past `max_bytes`.
+    ///
+    /// Called after every append when `max_log_bytes` is set.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_bytes` - The largest size the active log may keep
+    ///
+    /// # Returns
+    ///
+    /// Returns whether the log was rolled, or a `DbError` if it could not be
+    /// inspected or rolled.
+    pub fn rotate_if_needed(&mut self, max_bytes: u64) -> Result<bool, DbError> {
+        if self.file.metadata()?.len() <= max_bytes {
+            return Ok(false);
+        }
+
+        self.roll_segment()?;
+        Ok(true)
+    }
+
+    /// Compresses every rolled segment that is not yet compressed.
+    ///
+    /// # Returns
+    ///
+    /// Returns the number of segments compressed, or a `DbError` if any
+    /// compression fails.
+    pub fn compress_segments(&mut self) -> Result<usize, DbError> {
+        self.finish_compression()?;
+
+        let mut compressed = 0;
+        for (_, path) in self.segments() {
+            if !is_compressed(&path) {
+                compress_file(&path)?;
+                compressed += 1;
+            }
+        }
+
+        Ok(compressed)
+    }
+
+    /// Waits for any background compression to finish.
+    ///
+    /// # Returns
+    ///
+    /// Returns the background compression's error, if it failed.
+    pub fn finish_compression(&mut self) -> Result<(), DbError> {
+        match self.compression.take() {
+            Some(handle) => handle.join().map_err(|_| {
+                DbError::IoError(io::Error::other("log compression thread panicked"))
+            })?,
+            None => Ok(()),
+        }
+    }
+
+    /// Deletes all rolled segments, leaving only the active log.
+    ///
+    /// Used when the log's history is no longer needed, e.g. after compaction.
+    pub fn remove_segments(&mut self) -> Result<(), DbError> {
+        self.finish_compression()?;
+
+        for (_, path) in self.segments() {
+            fs::remove_file(path)?;
+        }
+
+        Ok(())
+    }
+
+    /// Appends an insert operation to the log.
+    ///
+    /// The row is serialized to JSON along with a timestamp and written
+    /// as a single line to the log file.
+    ///
+    /// # Arguments
+    ///
+    /// * `row` - The row to insert
+    ///
+    /// # Returns
+    ///
+    /// Returns `Ok(())` on success or a `DbError` if serialization or
+    /// writing fails.
+    pub fn append_entry(&mut self, row: &Row) -> Result<(), DbError> {
+        let log_entry = LogEntry::Insert {
+            row: self.logged_row(row),
+            timestamp: self.entry_timestamp(),
+        };
+
+        self.append_line(&log_entry)
+    }
+
+    /// Appends an update operation to the log.
+    ///
+    /// The row's new data is serialized to JSON along with a timestamp and
+    /// written as a single line; on replay it replaces the existing row.
+    ///
+    /// # Arguments
+    ///
+    /// * `row` - The row after the update
+    ///
+    /// # Returns
+    ///
+    /// Returns `Ok(())` on success or a `DbError` if serialization or
+    /// writing fails.
+    pub fn append_update(&mut self, row: &Row) -> Result<(), DbError> {
+        let log_entry = LogEntry::Update {
+            row: self.logged_row(row),
+            timestamp: self.entry_timestamp(),
+        };
+
+        self.append_line(&log_entry)
+    }
+
+    /// Appends a delete operation to the log.
+    ///
+    /// Only the ID is stored in the log, along with a timestamp; the actual
+    /// row removal happens during replay.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The ID of the row to delete
+    ///
+    /// # Returns
+    ///
+    /// Returns `Ok(())` on success or a `DbError` if serialization or
+    /// writing fails.
+    pub fn append_delete(&mut self, id: u32) -> Result<(), DbError> {
+        let log_entry = LogEntry::Delete {
+            id,
+            timestamp: self.entry_timestamp(),
+        };
+
+        self.append_line(&log_entry)
+    }
+
+    /// Appends an insert of every row in `rows` as one all-or-nothing write.
+    ///
+    /// The log's current length is recorded in `<log>.batch.tmp` and synced
+    /// before the entries are appended, and the record is removed once they
+    /// are synced. A failed write cuts the log back to that length, as does
+    /// the next open after a crash, so the log holds either the whole batch
+    /// or none of it, never a partial line. Pending group commit entries are
+    /// committed first.
+    ///
+    /// # Arguments
+    ///
+    /// * `rows` - The rows inserted, in order
+    ///
+    /// # Returns
+    ///
+    /// Returns `Ok(())` on success or a `DbError` if serialization, writing
+    /// the record or writing the entries fails, in which case the log is
+    /// cut back to how it was.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use mini_db::model::Row;
+    /// # use mini_db::storage::Storage;
+    /// # let mut storage = Storage::new("mini_db.log")?;
+    /// let rows: Vec<Row> = (1..=3).map(|id| Row::new(id, format!("user{id}"), 30)).collect();
+    /// storage.append_batch(&rows)?;
+    /// # Ok::<(), mini_db::errors::DbError>(())
+    /// ```
+    pub fn append_batch(&mut self, rows: &[Row]) -> Result<(), DbError> {
+        if rows.is_empty() {
+            return Ok(());
+        }
+        self.flush()?;
+
+        let len = self.file.metadata()?.len();
+        let mut lines = Vec::new();
+        if len == 0 {
+            writeln!(lines, "{}", serde_json::to_string(&FileHeader::current())?)?;
+        }
+        for row in rows {
+            let log_entry = LogEntry::Insert {
+                row: self.logged_row(row),
+                timestamp: self.entry_timestamp(),
+            };
+            writeln!(lines, "{}", serde_json::to_string(&log_entry)?)?;
+        }
+
+        let tmp_path = batch_tmp_path(&self.path);
+        let mut tmp_file = File::create(&tmp_path)?;
+        writeln!(tmp_file, "{}", len)?;
+        tmp_file.sync_all()?;
+        sync_parent_dir(&self.path)?;
+
+        let written = self.file.write_all(&lines).and_then(|()| self.file.sync_all());
+        if let Err(e) = written {
+            self.file.set_len(len)?;
+            self.file.sync_all()?;
+            fs::remove_file(&tmp_path)?;
+            return Err(e.into());
+        }
+
+        fs::remove_file(&tmp_path)?;
+        sync_parent_dir(&self.path)?;
+
+        if let Some(max_bytes) = self.max_log_bytes {
+            self.rotate_if_needed(max_bytes)?;
+        }
+
+        Ok(())
+    }
+
+    /// Returns the timestamp for a new entry, or `0` when timestamps are
+    /// not recorded.
+    fn entry_timestamp(&self) -> i64 {
+        if self.record_timestamps { unix_now() } else { 0 }
+    }
+
+    /// Returns the copy of `row` written to the log, with its own
+    /// timestamps zeroed when timestamps are not recorded.
+    fn logged_row(&self, row: &Row) -> Row {
+        let mut row = row.clone();
+        if !self.record_timestamps {
+            row.created_at = 0;
+            row.updated_at = 0;
+        }
+        row
+    }
+
+    /// Serializes an entry and writes it as a single line.
+    ///
+    /// A fresh or truncated log is given its header first. With group
+    /// commit enabled, the line is buffered instead (see
+    /// [`configure_group_commit`](Self::configure_group_commit)).
+    fn append_line(&mut self, log_entry: &LogEntry) -> Result<(), DbError> {
+        // Serialize to JSON and write as a single line
+        let json = serde_json::to_string(log_entry)?;
+
+        if let Some(group) = &self.group {
+            let (lock, wake) = &*group.shared;
+            let mut state = lock.lock();
+
+            if state.pending.is_empty() {
+                if self.file.metadata()?.len() == 0 {
+                    writeln!(state.pending, "{}", serde_json::to_string(&FileHeader::current())?)?;
+                }
+                state.since = Some(Instant::now());
+                wake.notify_one();
+            }
+            writeln!(state.pending, "{}", json)?;
+            state.entries += 1;
+
+            if state.entries >= group.max_entries {
+                state.commit()?;
+            }
+        } else {
+            if self.file.metadata()?.len() == 0 {
+                let header = serde_json::to_string(&FileHeader::current())?;
+                writeln!(self.file, "{}", header)?;
+            }
+            writeln!(self.file, "{}", json)?;
+        }
+
+        if let Some(max_bytes) = self.max_log_bytes {
+            self.rotate_if_needed(max_bytes)?;
+        }
+
+        Ok(())
     }
-}
 
-/// Manages persistent storage using an append-only log.
-///
-/// The storage layer provides:
-/// - Atomic write operations (each entry is a single line)
-/// - Crash recovery through log replay
-/// - Sequential access for efficient bulk loading
-pub struct Storage {
-    /// Path to the log file on disk
-    pub path: PathBuf,
-    /// File handle for append operations
-    pub file: File
-}
+    /// Loads and replays all operations from the log file.
+    ///
+    /// This method reads the entire log file and reconstructs the database
+    /// state by applying each operation in `replay_order`:
+    /// - Insert operations add rows to the result vector, replacing any
+    ///   live row with the same ID (last write wins)
+    /// - Update operations replace the row with the same ID
+    /// - Delete operations remove rows with matching IDs
+    ///
+    /// # Returns
+    ///
+    /// Returns a vector of all rows after replaying all operations,
+    /// or a `DbError` if the file cannot be read or a header names an
+    /// unsupported format version.
+    ///
+    /// # Error Handling
+    ///
+    /// The method attempts to be resilient to corrupted entries:
+    /// - Malformed lines are reported to [`log_sink`](Self::log_sink) and skipped
+    /// - Incomplete final lines (from crashes) are detected and skipped
+    pub fn load_all(&self) -> Result<Vec<Row>, DbError> {
+        self.load_onto(Vec::new())
+    }
 
-impl Storage {
-    /// Creates a new storage instance, opening or creating the log file.
+    /// Replays the log on top of existing rows.
+    ///
+    /// Works like [`load_all`](Self::load_all), except that replay starts
+    /// from `base` (typically the snapshot's rows) instead of an empty table,
+    /// so the log's deletes and updates apply to those rows and an insert of
+    /// an ID already in `base` replaces it.
     ///
     /// # Arguments
     ///
-    /// * `path` - Path to the log file
+    /// * `base` - The rows the log's entries are applied to
     ///
     /// # Returns
     ///
-    /// Returns a `Storage` instance ready for append operations,
-    /// or a `DbError` if the file cannot be opened/created.
+    /// Returns the rows after replay, or a `DbError` if the log cannot be read.
+    pub fn load_onto(&self, base: Vec<Row>) -> Result<Vec<Row>, DbError> {
+        // Right after compaction there is nothing to replay on top of the snapshot
+        if self.log_is_empty()? {
+            return Ok(base);
+        }
+
+        let entries = self.entries_in_replay_order()?;
+        Ok(replay_reporting(base, entries.into_iter().map(|(_, entry)| entry), self.log_sink.as_ref()))
+    }
+
+    /// Replays the log like [`load_all`](Self::load_all), also returning the
+    /// IDs whose rows were deleted, with the time of their delete.
     ///
-    /// # Examples
+    /// Only IDs that are still deleted at the end of the log are listed: a
+    /// later insert of the same ID drops its tombstone. Deletes of IDs that
+    /// had no row leave none. Tombstones are ordered by delete time, then ID,
+    /// and do not survive compaction, which truncates the log.
     ///
-    /// ```no_run
-    /// use mini_db::storage::Storage;
+    /// # Returns
     ///
-    /// let storage = Storage::new("mini_db.log")?;
-    /// # Ok::<(), mini_db::errors::DbError>(())
-    /// ```
-    pub fn new(path: impl AsRef<Path>) -> Result<Self, DbError> {
-        let dir_path = PathBuf::from("data");
-        let path = dir_path.join(path.as_ref());
+    /// Returns the live rows and the `(id, delete timestamp)` tombstones, or
+    /// a `DbError` if the log cannot be read.
+    pub fn load_with_tombstones(&self) -> Result<(Vec<Row>, Vec<Tombstone>), DbError> {
+        let mut replay = Replay::new();
+        let mut tombstones = HashMap::new();
 
-        // Open file in append mode, creating it if it doesn't exist
-        let file = OpenOptions::new()
-            .append(true)
-            .create(true)
-            .open(&path)?;
+        for (timestamp, entry) in self.entries_in_replay_order()? {
+            match &entry {
+                LogEntry::Delete { id, .. } if replay.positions.contains_key(id) => {
+                    tombstones.insert(*id, timestamp);
+                },
+                LogEntry::Insert { row, .. } | LogEntry::Update { row, .. } => {
+                    tombstones.remove(&row.id);
+                },
+                LogEntry::Delete { .. } => {},
+            }
+            replay.apply(entry, self.log_sink.as_ref());
+        }
 
-        Ok(Storage {
-            path,
-            file
-        })
+        let mut tombstones: Vec<Tombstone> = tombstones.into_iter().collect();
+        tombstones.sort_by_key(|&(id, timestamp)| (timestamp, id));
+
+        Ok((replay.into_rows(), tombstones))
     }
 
-    /// Appends an insert operation to the log.
+    /// Reads every entry, sorted by timestamp if `replay_order` asks for it.
+    fn entries_in_replay_order(&self) -> Result<Vec<(i64, LogEntry)>, DbError> {
+        let mut entries = self.read_all_entries()?;
+
+        if self.replay_order == ReplayOrder::Timestamp {
+            // Stable sort keeps file order for entries sharing a timestamp
+            entries.sort_by_key(|(timestamp, _)| *timestamp);
+        }
+
+        Ok(entries)
+    }
+
+    /// Replays only the log entries made at or before `cutoff`, rebuilding
+    /// the table as it was at that time.
     ///
-    /// The row is serialized to JSON along with a timestamp and written
-    /// as a single line to the log file.
+    /// Deletes logged without a timestamp count as made at the time of the
+    /// entry preceding them. Rows folded into the snapshot by compaction are
+    /// not included, since the snapshot keeps no history of its rows.
     ///
     /// # Arguments
     ///
-    /// * `row` - The row to insert
+    /// * `cutoff` - The Unix timestamp to reconstruct the table at
     ///
     /// # Returns
     ///
-    /// Returns `Ok(())` on success or a `DbError` if serialization or
-    /// writing fails.
-    pub fn append_entry(&mut self, row: &Row) -> Result<(), DbError> {
-        let log_entry = LogEntry::Insert {
-            row: row.clone(),
-            timestamp: Utc::now().timestamp(),
-        };
+    /// Returns the rows as of `cutoff`, or a `DbError` if the log cannot be
+    /// read. Without [`record_timestamps`](Self::record_timestamps) every
+    /// entry is logged at time `0`, so this fails with an `Unsupported` I/O
+    /// error instead.
+    pub fn load_until(&self, cutoff: i64) -> Result<Vec<Row>, DbError> {
+        if !self.record_timestamps {
+            return Err(io::Error::new(io::ErrorKind::Unsupported, "entry timestamps are not recorded").into());
+        }
 
-        // Serialize to JSON and write as a single line
-        let json = serde_json::to_string(&log_entry)?;
-        writeln!(self.file, "{}", json)?;    
+        let mut entries = self.read_all_entries()?;
+        entries.retain(|(timestamp, _)| *timestamp <= cutoff);
 
-        Ok(())
-    } 
+        if self.replay_order == ReplayOrder::Timestamp {
+            entries.sort_by_key(|(timestamp, _)| *timestamp);
+        }
 
-    /// Appends a delete operation to the log.
+        Ok(replay_reporting(Vec::new(), entries.into_iter().map(|(_, entry)| entry), self.log_sink.as_ref()))
+    }
+
+    /// Reports the size of the log, segments included, and whether a
+    /// snapshot exists.
+    ///
+    /// Counting entries reads the whole log.
+    ///
+    /// # Returns
+    ///
+    /// Returns the statistics, or a `DbError` if the log cannot be read.
+    pub fn log_stats(&self) -> Result<LogStats, DbError> {
+        self.commit_pending()?;
+        let mut log_bytes = 0;
+        for path in self.segment_paths().iter().chain([&self.path]) {
+            if path.exists() {
+                log_bytes += fs::metadata(path)?.len();
+            }
+        }
+
+        Ok(LogStats {
+            log_bytes,
+            entry_count: self.fold_entries(0, |count, _| count + 1)?,
+            snapshot_exists: self.snapshot_path().exists(),
+        })
+    }
+
+    /// Folds over every log entry, segments first, without materializing
+    /// the entries or the rows they build.
     ///
-    /// Only the ID is stored in the log; the actual row removal happens
-    /// during replay.
+    /// Entries are streamed one at a time in file order, regardless of the
+    /// replay order; unparseable lines are skipped as during replay. Useful
+    /// for counting or verifying a log too large to load comfortably.
     ///
     /// # Arguments
     ///
-    /// * `id` - The ID of the row to delete
+    /// * `init` - The initial accumulator
+    /// * `f` - Combines the accumulator with the next entry
     ///
     /// # Returns
     ///
-    /// Returns `Ok(())` on success or a `DbError` if serialization or
-    /// writing fails.
-    pub fn append_delete(&mut self, id: u32) -> Result<(), DbError> {
-        let log_entry = LogEntry::Delete { id };
-
-        // Serialize to JSON and write as a single line
-        let json = serde_json::to_string(&log_entry)?;
-        writeln!(self.file, "{}", json)?;
+    /// Returns the final accumulator, or a `DbError` if the log cannot be read.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use mini_db::storage::{LogEntry, Storage};
+    ///
+    /// let storage = Storage::new("mini_db.log")?;
+    /// let deletes = storage.fold_entries(0, |count, entry| {
+    ///     count + usize::from(matches!(entry, LogEntry::Delete { .. }))
+    /// })?;
+    /// # Ok::<(), mini_db::errors::DbError>(())
+    /// ```
+    pub fn fold_entries<B, F: FnMut(B, LogEntry) -> B>(&self, init: B, mut f: F) -> Result<B, DbError> {
+        let mut acc = Some(init);
+        self.for_each_entry(self.replay_policy, |_, entry| {
+            acc = acc.take().map(|acc| f(acc, entry));
+        })?;
 
-        Ok(())
+        Ok(acc.expect("accumulator is restored after every entry"))
     }
 
-    /// Loads and replays all operations from the log file.
+    /// Returns the timestamp of the most recent insert of each ID in the log.
     ///
-    /// This method reads the entire log file and reconstructs the database
-    /// state by applying each operation in order:
-    /// - Insert operations add rows to the result vector
-    /// - Delete operations remove rows with matching IDs
+    /// IDs whose rows only exist in the snapshot have no entry. Without
+    /// [`record_timestamps`](Self::record_timestamps) the map is empty, since
+    /// every entry is logged at time `0`.
     ///
     /// # Returns
     ///
-    /// Returns a vector of all rows after replaying all operations,
-    /// or a `DbError` if the file cannot be read.
+    /// A map of row ID -> Unix timestamp, or a `DbError` if the log cannot be read.
+    pub fn insert_timestamps(&self) -> Result<HashMap<u32, i64>, DbError> {
+        if !self.record_timestamps {
+            return Ok(HashMap::new());
+        }
+
+        let mut timestamps = HashMap::new();
+
+        for (_, entry) in self.read_all_entries()? {
+            if let LogEntry::Insert { row, timestamp } = entry {
+                timestamps.insert(row.id, timestamp);
+            }
+        }
+
+        Ok(timestamps)
+    }
+
+    /// Returns `true` if there are no segments and the active log is zero
+    /// bytes long, as left by compaction, so replay would read nothing.
+    fn log_is_empty(&self) -> Result<bool, DbError> {
+        self.commit_pending()?;
+        if !self.segments().is_empty() {
+            return Ok(false);
+        }
+
+        match fs::metadata(&self.path) {
+            Ok(metadata) => Ok(metadata.len() == 0),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(true),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Returns the number of entry lines read since this storage was opened,
+    /// including lines skipped as unparseable; headers are not counted.
     ///
-    /// # Error Handling
+    /// Reopening a compacted database reads no lines, since its empty log
+    /// is not replayed.
+    pub fn lines_read(&self) -> usize {
+        self.lines_read.load(Ordering::Relaxed)
+    }
+
+    /// Reads the entries of every segment and the active log, in file order.
     ///
-    /// The method attempts to be resilient to corrupted entries:
-    /// - Malformed lines are logged as warnings and skipped
-    /// - Incomplete final lines (from crashes) are detected and skipped
-    pub fn load_all(&self) -> Result<Vec<Row>, DbError> {
-        let path = &self.path;
+    /// Each entry is paired with the timestamp used by `ReplayOrder::Timestamp`.
+    fn read_all_entries(&self) -> Result<Vec<(i64, LogEntry)>, DbError> {
+        self.read_log(self.replay_policy).map(|(entries, _)| entries)
+    }
 
-        if !path.exists() {
-            return Ok(Vec::new());
+    /// Reads the entries of every segment and the active log, along with
+    /// the number of lines skipped because they could not be parsed.
+    fn read_log(&self, policy: ReplayPolicy) -> Result<(Vec<(i64, LogEntry)>, usize), DbError> {
+        let mut entries: Vec<(i64, LogEntry)> = Vec::new();
+        let skipped = self.for_each_entry(policy, |timestamp, entry| entries.push((timestamp, entry)))?;
+
+        Ok((entries, skipped))
+    }
+
+    /// Streams the entries of every segment and the active log, in file
+    /// order, to `on_entry` without keeping them.
+    ///
+    /// Each entry is passed with the timestamp used by `ReplayOrder::Timestamp`.
+    /// Unreadable lines are handled according to `policy`. Returns the
+    /// number of lines skipped because they could not be parsed.
+    fn for_each_entry(&self, policy: ReplayPolicy, mut on_entry: impl FnMut(i64, LogEntry)) -> Result<usize, DbError> {
+        self.commit_pending()?;
+        let mut last_timestamp = 0;
+        let mut skipped = 0;
+        let mut parsed = 0;
+        let mut on_entry = |timestamp, entry| {
+            parsed += 1;
+            on_entry(timestamp, entry);
+        };
+
+        for segment in self.segment_paths() {
+            let file = File::open(&segment)?;
+            let reader: Box<dyn Read> = if is_compressed(&segment) {
+                Box::new(GzDecoder::new(file))
+            } else {
+                Box::new(file)
+            };
+            skipped += Self::read_entries(BufReader::new(reader), &mut last_timestamp, self.log_sink.as_ref(), policy, &mut on_entry)
+                .map_err(|e| name_corrupt_file(&segment, e))?;
         }
 
-        let file = File::open(&path)?;
-        let reader = BufReader::new(file);
+        if self.path.exists() {
+            let file = File::open(&self.path)?;
+            skipped += Self::read_entries(BufReader::new(file), &mut last_timestamp, self.log_sink.as_ref(), policy, &mut on_entry)
+                .map_err(|e| name_corrupt_file(&self.path, e))?;
+        }
 
-        let mut rows = Vec::new();
+        self.lines_read.fetch_add(parsed + skipped, Ordering::Relaxed);
+        Ok(skipped)
+    }
+
+    /// Parses the log entries of one file and passes each to `on_entry`.
+    ///
+    /// Each entry comes with the timestamp used by `ReplayOrder::Timestamp`;
+    /// `last_timestamp` carries the most recent entry timestamp across files.
+    /// A header on the first line is validated and skipped; unreadable
+    /// lines are reported to `sink`, or fail with `CorruptLog` under
+    /// `ReplayPolicy::Strict`.
+    ///
+    /// Returns the number of lines skipped because they could not be parsed.
+    fn read_entries(
+        mut reader: impl BufRead,
+        last_timestamp: &mut i64,
+        sink: &dyn LogSink,
+        policy: ReplayPolicy,
+        on_entry: &mut impl FnMut(i64, LogEntry),
+    ) -> Result<usize, DbError> {
+        // Under the strict policy, the first unreadable line ends the read
+        let skip = |warning: LogWarning, detail: String| {
+            if policy == ReplayPolicy::Strict {
+                return Err(DbError::CorruptLog { line: warning.line, detail });
+            }
+            sink.warn(&warning);
+            Ok(())
+        };
+
+        let mut buf = Vec::new();
+        let mut line_num = 0;
+        let mut skipped = 0;
 
         // Loops over each line in file
-        for (line_num, line_res) in reader.lines().enumerate() {
-            let line = match line_res {
-                Ok(l) => l.trim().to_string(),
+        loop {
+            buf.clear();
+            match reader.read_until(b'\n', &mut buf) {
+                Ok(0) => break,
+                Ok(_) => line_num += 1,
+                Err(e) => {
+                    skip(LogWarning {
+                        line: line_num + 1,
+                        message: format!("failed to read line {}: {}", line_num + 1, e),
+                    }, e.to_string())?;
+                    break;
+                }
+            }
+
+            // Only a line cut off by EOF lacks its trailing newline, so this
+            // can only be true for the actual final line of the file
+            let torn = buf.last() != Some(&b'\n');
+
+            let line = match std::str::from_utf8(&buf) {
+                Ok(l) => l.trim(),
                 Err(e) => {
-                    eprintln!("Warning: failed to read line {}: {}", line_num + 1, e);
+                    skip(LogWarning {
+                        line: line_num,
+                        message: format!("failed to read line {}: {}", line_num, e),
+                    }, e.to_string())?;
+                    skipped += 1;
                     continue;
                 }
             };
@@ -179,30 +1824,52 @@ impl Storage {
             if line.is_empty() {
                 continue;
             }
-            
-            // Deserialize each line and append to row
-            match serde_json::from_str(&line) {
-                Ok(LogEntry::Insert {row, ..}) => rows.push(row),
-                Ok(LogEntry:: Delete { id }) => rows.retain(|r| r.id != id),
-                Err(e) => {
-                    eprintln!("Warning: could not parse line {}: {}", line_num + 1, e);
-                    
-                    if line_num == rows.len() {
-                        eprintln!("Skipping possibly incomplete last line.");
-                        break;
-                    } else {
-                        continue;
+
+            if line_num == 1
+                && let Ok(header) = serde_json::from_str::<FileHeader>(line)
+            {
+                header.validate()?;
+                continue;
+            }
+
+            // Deserialize each line and queue it for replay
+            match serde_json::from_str(line) {
+                Ok(entry) => {
+                    match &entry {
+                        LogEntry::Insert { timestamp, .. } | LogEntry::Update { timestamp, .. } => {
+                            *last_timestamp = *timestamp;
+                        },
+                        LogEntry::Delete { timestamp, .. } if *timestamp != 0 => {
+                            *last_timestamp = *timestamp;
+                        },
+                        LogEntry::Delete { .. } => {},
                     }
+                    on_entry(*last_timestamp, entry);
+                },
+                Err(e) if torn => {
+                    skip(LogWarning {
+                        line: line_num,
+                        message: format!("skipping incomplete last line {}: {}", line_num, e),
+                    }, format!("incomplete last line: {}", e))?;
+                    skipped += 1;
+                }
+                Err(e) => {
+                    skip(LogWarning {
+                        line: line_num,
+                        message: format!("could not parse line {}: {}", line_num, e),
+                    }, e.to_string())?;
+                    skipped += 1;
                 }
             }
         }
 
-        Ok(rows)
+        Ok(skipped)
     }
 
     /// Ensures all pending writes are flushed and synced to disk.
     ///
-    /// This method performs a two-phase flush:
+    /// Any background segment compression is waited for first. This method
+    /// then performs a two-phase flush:
     /// 1. Flushes the file's internal buffer
     /// 2. Syncs all data to physical storage
     ///
@@ -223,54 +1890,245 @@ impl Storage {
     /// # Ok::<(), mini_db::errors::DbError>(())
     /// ```
     pub fn flush(&mut self) -> Result<(), DbError> {
+        self.finish_compression()?;
+        self.commit_pending()?;
         self.file.flush()?;
         self.file.sync_all()?;
 
         Ok(())
-    }  
+    }
 
-    pub fn snapshot_write(&self, rows: &[Row], path: &Path) -> Result<(), DbError> {
-        let snapshot_path = path.join("mini_db.snapshot");
-        let tmp_path = path.join("mini_db.snapshot.tmp");
+    /// Buffers appends and writes them in groups: pending entries are
+    /// written and synced together once `max_entries` have accumulated or
+    /// the oldest has waited `max_delay`, whichever comes first.
+    ///
+    /// A background thread enforces the delay. [`flush`](Self::flush) and
+    /// reads of the log commit pending entries immediately, as does dropping
+    /// the storage. Calling this again replaces the previous settings.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_entries` - Number of pending entries that triggers a commit
+    /// * `max_delay` - Longest time an entry may stay pending
+    ///
+    /// # Returns
+    ///
+    /// Returns `Ok(())` on success or a `DbError` if the previous group
+    /// cannot be committed or the log handle cannot be cloned.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::time::Duration;
+    /// use mini_db::engine::Database;
+    /// use mini_db::storage::Storage;
+    ///
+    /// let mut storage = Storage::new("mini_db.log")?;
+    /// storage.configure_group_commit(256, Duration::from_millis(10))?;
+    /// let db = Database::from_storage(storage)?;
+    /// # Ok::<(), mini_db::errors::DbError>(())
+    /// ```
+    pub fn configure_group_commit(&mut self, max_entries: usize, max_delay: Duration) -> Result<(), DbError> {
+        self.disable_group_commit()?;
 
-        let serialized = serde_json::to_string(rows)?;
+        let shared = Arc::new((
+            Mutex::new(GroupCommit {
+                pending: Vec::new(),
+                entries: 0,
+                since: None,
+                file: self.file.try_clone()?,
+                closed: false,
+            }),
+            Condvar::new(),
+        ));
 
-        let mut tmp_file = OpenOptions::new()
-                                                .create(true)
-                                                .truncate(true)
-                                                .write(true)
-                                                .open(&tmp_path)?;
+        let timer_shared = Arc::clone(&shared);
+        let sink = Arc::clone(&self.log_sink);
+        let timer = thread::spawn(move || {
+            let (lock, wake) = &*timer_shared;
+            let mut state = lock.lock();
 
-        tmp_file.write_all(serialized.as_bytes())?;
-        tmp_file.flush()?;
-        tmp_file.sync_all()?;
+            while !state.closed {
+                match state.since {
+                    Some(since) if since.elapsed() >= max_delay => {
+                        if let Err(e) = state.commit() {
+                            sink.warn(&LogWarning { line: 0, message: format!("group commit failed: {}", e) });
+                        }
+                    },
+                    Some(since) => {
+                        wake.wait_until(&mut state, since + max_delay);
+                    },
+                    None => wake.wait(&mut state),
+                }
+            }
+        });
 
-        fs::rename(tmp_path, &snapshot_path)?;
+        self.group = Some(GroupCommitHandle { shared, max_entries: max_entries.max(1), timer });
 
         Ok(())
-                                            
-    }   
+    }
 
-    pub fn snapshot_read(&self, path: &Path) -> Result<Vec<Row>, DbError> {
-        let file = File::open(path)?;
-        let reader = BufReader::new(file);
+    /// Commits any pending entries and turns group commit off again.
+    ///
+    /// # Returns
+    ///
+    /// Returns `Ok(())` on success or a `DbError` if the pending entries
+    /// cannot be written.
+    pub fn disable_group_commit(&mut self) -> Result<(), DbError> {
+        let Some(group) = self.group.take() else {
+            return Ok(());
+        };
+
+        let result = {
+            let (lock, wake) = &*group.shared;
+            let mut state = lock.lock();
+            state.closed = true;
+            wake.notify_one();
+            state.commit()
+        };
+        let _ = group.timer.join();
+
+        result
+    }
+
+    /// Writes and syncs the entries buffered by group commit, if any.
+    fn commit_pending(&self) -> Result<(), DbError> {
+        match &self.group {
+            Some(group) => group.shared.0.lock().commit(),
+            None => Ok(()),
+        }
+    }
+
+    /// Drops the entries buffered by group commit, if any, e.g. once the
+    /// log they were meant for is truncated.
+    fn discard_pending(&self) {
+        if let Some(group) = &self.group {
+            group.shared.0.lock().discard();
+        }
+    }
+
+    /// Reopens the append handle after the log file was replaced or rolled.
+    ///
+    /// Pending group commit entries must already have been committed.
+    fn reopen_log(&mut self) -> Result<(), DbError> {
+        self.file = OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(&self.path)?;
+
+        if let Some(group) = &self.group {
+            group.shared.0.lock().file = self.file.try_clone()?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for Storage {
+    fn drop(&mut self) {
+        if let Err(e) = self.disable_group_commit() {
+            self.log_sink.warn(&LogWarning { line: 0, message: format!("group commit failed: {}", e) });
+        }
+    }
+}
+
+impl StorageBackend for Storage {
+    fn append_entry(&mut self, row: &Row) -> Result<(), DbError> {
+        Storage::append_entry(self, row)
+    }
+
+    fn append_entries(&mut self, rows: &[Row]) -> Result<(), DbError> {
+        Storage::append_batch(self, rows)
+    }
+
+    fn append_update(&mut self, row: &Row) -> Result<(), DbError> {
+        Storage::append_update(self, row)
+    }
+
+    fn append_delete(&mut self, id: u32) -> Result<(), DbError> {
+        Storage::append_delete(self, id)
+    }
+
+    fn load_onto(&self, base: Vec<Row>) -> Result<Vec<Row>, DbError> {
+        Storage::load_onto(self, base)
+    }
+
+    fn load_until(&self, cutoff: i64) -> Result<Vec<Row>, DbError> {
+        Storage::load_until(self, cutoff)
+    }
+
+    fn flush(&mut self) -> Result<(), DbError> {
+        Storage::flush(self)
+    }
+
+    /// Writes the snapshot next to the log via a temporary file and rename.
+    fn snapshot_write(&mut self, rows: &[Row]) -> Result<(), DbError> {
+        self.write_snapshot(rows)
+    }
 
-        let rows: Vec<Row> = serde_json::from_reader(reader)?;
+    /// Reads the snapshot next to the log.
+    ///
+    /// A snapshot from a newer format version is an error; an otherwise
+    /// unreadable snapshot is reported and treated as missing.
+    fn snapshot_read(&self) -> Result<Option<Vec<Row>>, DbError> {
+        let snapshot_path = self.snapshot_path();
+        if !snapshot_path.exists() {
+            return Ok(None);
+        }
 
-        Ok(rows)
+        match self.read_snapshot(&snapshot_path) {
+            Ok(rows) => Ok(Some(rows)),
+            Err(e @ DbError::UnsupportedVersion { .. }) => Err(e),
+            Err(e) => {
+                self.log_sink.warn(&LogWarning {
+                    line: 0,
+                    message: format!("ignoring unreadable snapshot: {}", e),
+                });
+                Ok(None)
+            }
+        }
     }
 
-    pub fn log_truncate(&self, path: &Path) -> Result<(), DbError> {
+    /// Truncates the active log and deletes every rolled segment.
+    ///
+    /// Entries still buffered by group commit are dropped as well, so they
+    /// cannot land in the truncated log; the next append then starts it
+    /// with a fresh header.
+    fn log_truncate(&mut self) -> Result<(), DbError> {
+        self.discard_pending();
+
         let mut file = OpenOptions::new()
             .create(true)
             .write(true)
             .truncate(true)
-            .open(path)?;
-        
+            .open(&self.path)?;
+
         file.flush()?;
         file.sync_all()?;
-        
-        Ok(())
+
+        self.remove_segments()
+    }
+
+    fn insert_timestamps(&self) -> Result<HashMap<u32, i64>, DbError> {
+        Storage::insert_timestamps(self)
+    }
+
+    fn log_stats(&self) -> Result<LogStats, DbError> {
+        Storage::log_stats(self)
+    }
+
+    fn log_sink(&self) -> &dyn LogSink {
+        self.log_sink.as_ref()
+    }
+
+    fn vacuum(&mut self) -> Result<usize, DbError> {
+        Storage::vacuum(self)
+    }
+
+    /// Truncates the log and deletes its segments and snapshot.
+    fn reset(&mut self) -> Result<(), DbError> {
+        self.log_truncate()?;
+        self.remove_snapshot()
     }
 }
 
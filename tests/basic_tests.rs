@@ -48,17 +48,9 @@ fn select_returns_all_inserted_rows() -> Result<(), DbError> {
     let mut db = Database::new(file_path)?;
 
     // Create row0 and row1 which will be used to compare with rows inserted into database from select_all (Rows with same content as row0 and row1 will be inserted into database)
-    let row0 = Row {
-        id: 1,
-        name: "name".into(),
-        age: 20,
-    };
-
-    let row1 = Row {
-        id: 2,
-        name: "name".into(),
-        age: 30,
-    };
+    let row0 = Row::new(1, "name".into(), 20);
+
+    let row1 = Row::new(2, "name".into(), 30);
 
     // Insert two rows into db
     db.insert(1, "name".to_string(), 20)?;
@@ -101,3 +93,52 @@ fn parse_valid_insert_returns_command() -> Result<(), DbError> {
     Ok(())
 }
 
+#[test]
+// A name exactly at the limit is accepted, one character more is rejected without touching the log
+fn insert_enforces_max_name_len() -> Result<(), DbError> {
+    let dir = tempdir()?;
+
+    let path: &'static str = "temp_data.json";
+    let file_path = dir.path().join(path);
+
+    let mut db = Database::new(&file_path)?;
+    db.set_max_name_len(8);
+
+    db.insert(1, "a".repeat(8), 20)?;
+    let log_len = std::fs::metadata(&file_path)?.len();
+
+    let err = db.insert(2, "a".repeat(9), 20);
+
+    assert!(matches!(err, Err(DbError::NameTooLong { len: 9, max: 8 })));
+    assert_eq!(std::fs::metadata(&file_path)?.len(), log_len, "rejected insert must not be logged");
+    assert_eq!(db.select_all().len(), 1);
+
+    Ok(())
+}
+
+#[test]
+// With an age range set, ages above the maximum are rejected by insert and update
+fn insert_and_update_enforce_age_range() -> Result<(), DbError> {
+    let dir = tempdir()?;
+
+    let path: &'static str = "temp_data.json";
+    let file_path = dir.path().join(path);
+
+    let mut db = Database::new(&file_path)?;
+    db.set_age_range(Some(0..=150));
+
+    db.insert(1, "Alice".into(), 30)?;
+    let log_len = std::fs::metadata(&file_path)?.len();
+
+    let err = db.insert(2, "Bob".into(), 200);
+    assert!(matches!(err, Err(DbError::AgeOutOfRange { value: 200, min: 0, max: 150 })));
+
+    let err = db.update(1, "Alice".into(), 151);
+    assert!(matches!(err, Err(DbError::AgeOutOfRange { value: 151, min: 0, max: 150 })));
+
+    assert_eq!(std::fs::metadata(&file_path)?.len(), log_len, "rejected writes must not be logged");
+    assert_eq!(db.select_all().len(), 1);
+    assert_eq!(db.select_by_id(1)?.map(|row| row.age), Some(30));
+
+    Ok(())
+}
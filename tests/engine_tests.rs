@@ -1,6 +1,9 @@
-use mini_db::engine::Database;
+use mini_db::engine::{BatchMode, Database, DatabaseHandle, EvictionPolicy};
 use mini_db::errors::DbError;
 use mini_db::model::Row;
+use mini_db::query::{AggregateFunc, CompareOp, Field};
+use mini_db::parser::{self, CommandOutcome, CommandOutput};
+use std::sync::Arc;
 use tempfile::tempdir;
 
 
@@ -13,9 +16,9 @@ fn insert_and_select_all() -> Result<(), DbError> {
 
     let mut db = Database::new(&file_path)?;
 
-    let first_entry = Row {id: 1, name: "name1".into(), age: 20};
-    let second_entry = Row {id: 2, name: "name2".into(), age: 30};
-    let thrid_entry =Row {id: 3, name: "name3".into(), age: 40};
+    let first_entry = Row::new(1, "name1".into(), 20);
+    let second_entry = Row::new(2, "name2".into(), 30);
+    let thrid_entry =Row::new(3, "name3".into(), 40);
 
     let entries = vec![first_entry, second_entry, thrid_entry];
 
@@ -43,7 +46,7 @@ fn insert_and_select_by_id() -> Result<(), DbError> {
     db.insert(2, "name2".into(), 30)?;
     db.insert(3, "name3".into(), 40)?;
 
-    let first_entry = Row {id: 1, name: "name1".into(), age: 20};
+    let first_entry = Row::new(1, "name1".into(), 20);
 
     let selected_row = db.select_by_id(1)?;
 
@@ -66,7 +69,7 @@ fn delete_existing_row() -> Result<(), DbError> {
     let deleted_row = db.delete_by_id(1)?;
     let select_deleted_row = db.select_by_id(1)?;
 
-    assert!(deleted_row == true);
+    assert!(deleted_row);
     assert_eq!(select_deleted_row, None);
 
     Ok(())
@@ -123,4 +126,1332 @@ fn prevent_duplicate_id_insert() -> Result<(), DbError> {
     assert!(matches!(err, Err(DbError::DuplicateIdError(_))));
 
     Ok(())
-}
\ No newline at end of file
+}
+
+#[test]
+fn delete_where_age_removes_matching_rows_and_persists() -> Result<(), DbError> {
+    let dir = tempdir()?;
+
+    let path = "temp_data.json";
+    let file_path = dir.path().join(path);
+
+    {
+        let mut db = Database::new(&file_path)?;
+
+        db.insert(1, "name1".into(), 12)?;
+        db.insert(2, "name2".into(), 30)?;
+        db.insert(3, "name3".into(), 17)?;
+        db.insert(4, "name4".into(), 18)?;
+
+        let deleted = db.delete_where_age(CompareOp::Lt, 18)?;
+
+        assert_eq!(deleted, 2);
+        assert_eq!(db.select_all().iter().map(|r| r.id).collect::<Vec<_>>(), vec![2, 4]);
+        assert_eq!(db.select_by_id(4)?, Some(Row::new(4, "name4".into(), 18)));
+    }
+
+    let db = Database::new(&file_path)?;
+    let survivors: Vec<u32> = db.select_all().iter().map(|r| r.id).collect();
+
+    assert_eq!(survivors, vec![2, 4]);
+    assert!(db.select_by_id(1)?.is_none());
+
+    Ok(())
+}
+
+#[test]
+fn select_keys_returns_only_matching_ids() -> Result<(), DbError> {
+    let dir = tempdir()?;
+
+    let path = "temp_data.json";
+    let file_path = dir.path().join(path);
+
+    let mut db = Database::new(&file_path)?;
+
+    db.insert(1, "name1".into(), 25)?;
+    db.insert(2, "name2".into(), 35)?;
+    db.insert(3, "name3".into(), 45)?;
+
+    let cmd = parser::parse_command("SELECT KEYS WHERE AGE>30")?;
+
+    assert_eq!(db.query(&cmd)?, CommandOutcome::Keys(vec![2, 3]));
+    assert_eq!(db.ids(), vec![1, 2, 3]);
+
+    Ok(())
+}
+
+#[test]
+fn strict_lookups_error_on_missing_id() -> Result<(), DbError> {
+    let dir = tempdir()?;
+
+    let path = "temp_data.json";
+    let file_path = dir.path().join(path);
+
+    let mut db = Database::new(&file_path)?;
+
+    db.insert(1, "name1".into(), 20)?;
+
+    assert_eq!(db.select_by_id_strict(1)?, Row::new(1, "name1".into(), 20));
+    assert!(matches!(db.select_by_id_strict(2), Err(DbError::RowNotFound(2))));
+
+    db.delete_by_id_strict(1)?;
+    assert!(matches!(db.delete_by_id_strict(1), Err(DbError::RowNotFound(1))));
+
+    Ok(())
+}
+
+#[test]
+fn select_where_and_matches_subset() -> Result<(), DbError> {
+    let dir = tempdir()?;
+
+    let path = "temp_data.json";
+    let file_path = dir.path().join(path);
+
+    let mut db = Database::new(&file_path)?;
+
+    db.insert(1, "Alice".into(), 35)?;
+    db.insert(2, "Alice".into(), 25)?;
+    db.insert(3, "Bob".into(), 40)?;
+
+    let cmd = parser::parse_command("SELECT WHERE AGE>=30 AND NAME=Alice")?;
+    assert_eq!(db.query(&cmd)?, CommandOutcome::Rows(vec![Row::new(1, "Alice".into(), 35)]));
+
+    let cmd = parser::parse_command("SELECT WHERE AGE>50 AND NAME=Bob")?;
+    assert_eq!(db.query(&cmd)?, CommandOutcome::Rows(vec![]));
+
+    Ok(())
+}
+
+#[test]
+fn select_all_shared_reuses_rows_until_write() -> Result<(), DbError> {
+    let dir = tempdir()?;
+
+    let path = "temp_data.json";
+    let file_path = dir.path().join(path);
+
+    let db = DatabaseHandle::new(&file_path)?;
+
+    db.insert(1, "name1".into(), 20)?;
+
+    let first = db.select_all_shared();
+    let second = db.select_all_shared();
+    assert!(Arc::ptr_eq(&first, &second), "no write happened, rows should be shared");
+
+    db.insert(2, "name2".into(), 30)?;
+
+    let third = db.select_all_shared();
+    assert!(!Arc::ptr_eq(&first, &third), "a write must invalidate the cached rows");
+    assert_eq!(first.len(), 1);
+    assert_eq!(third.len(), 2);
+
+    Ok(())
+}
+
+#[test]
+// Aggregates over the rows through the handle's closure API instead of a cloned vector
+fn for_each_row_computes_average_age() -> Result<(), DbError> {
+    let dir = tempdir()?;
+
+    let path = "temp_data.json";
+    let file_path = dir.path().join(path);
+
+    let db = DatabaseHandle::new(&file_path)?;
+    db.insert(1, "Alice".into(), 20)?;
+    db.insert(2, "Bob".into(), 30)?;
+    db.insert(3, "Carol".into(), 40)?;
+
+    let (mut total, mut count) = (0u32, 0u32);
+    db.for_each_row(|row| {
+        total += u32::from(row.age);
+        count += 1;
+    });
+
+    assert_eq!(count, 3);
+    assert_eq!(total / count, 30);
+
+    Ok(())
+}
+
+#[test]
+// Comments and blank lines in a batch file are skipped rather than reported as invalid commands
+fn exec_batch_skips_comments_and_blank_lines() -> Result<(), DbError> {
+    let dir = tempdir()?;
+
+    let path = "temp_data.json";
+    let file_path = dir.path().join(path);
+    let batch_path = dir.path().join("seed.txt");
+
+    std::fs::write(&batch_path, "# Seed users\n\nINSERT 1 Alice 20\n   # indented comment\nINSERT 2 Bob 30\n\n\n# last one\nINSERT 3 Carol 40\n")?;
+
+    let mut db = Database::new(&file_path)?;
+    db.exec_batch(batch_path)?;
+
+    assert_eq!(db.select_all().len(), 3);
+    assert_eq!(db.ids(), vec![1, 2, 3]);
+
+    Ok(())
+}
+
+#[test]
+// A dry run reports the first unparseable line and leaves rows and log untouched
+fn exec_batch_dry_run_reports_bad_line_without_changes() -> Result<(), DbError> {
+    let dir = tempdir()?;
+
+    let path = "temp_data.json";
+    let file_path = dir.path().join(path);
+    let batch_path = dir.path().join("seed.txt");
+
+    let mut db = Database::new(&file_path)?;
+    db.insert(1, "Alice".into(), 20)?;
+    db.shutdown()?;
+    let log_before = std::fs::read(&file_path)?;
+
+    std::fs::write(&batch_path, "# seed\nINSERT 2 Bob 30\n\nINSERT 3 Carol old\nDELETE WHERE ID=1\n")?;
+
+    match db.exec_batch_dry_run(&batch_path) {
+        Err(DbError::ParseError(msg)) => assert!(msg.starts_with("line 4:"), "unexpected message: {msg}"),
+        other => panic!("expected a parse error, got {:?}", other),
+    }
+
+    assert_eq!(db.ids(), vec![1]);
+    assert_eq!(std::fs::read(&file_path)?, log_before);
+
+    // Once fixed, every command is returned with its line number
+    std::fs::write(&batch_path, "# seed\nINSERT 2 Bob 30\n\nDELETE WHERE ID=1\n")?;
+    let commands = db.exec_batch_dry_run(&batch_path)?;
+    let lines: Vec<usize> = commands.iter().map(|(line, _)| *line).collect();
+    assert_eq!(lines, vec![2, 4]);
+    assert_eq!(db.ids(), vec![1]);
+
+    Ok(())
+}
+
+#[test]
+// Readers hammer select_by_id on cloned handles while a writer inserts; the final count is exact
+fn concurrent_readers_and_writer_stay_consistent() -> Result<(), DbError> {
+    let dir = tempdir()?;
+
+    let path = "temp_data.json";
+    let file_path = dir.path().join(path);
+
+    let db = DatabaseHandle::new(&file_path)?;
+    const ROWS: u32 = 500;
+
+    let writer = {
+        let db = db.clone();
+        std::thread::spawn(move || -> Result<(), DbError> {
+            for id in 1..=ROWS {
+                db.insert(id, format!("user{id}"), (id % 100) as u8)?;
+            }
+            Ok(())
+        })
+    };
+
+    let readers: Vec<_> = (0..4)
+        .map(|_| {
+            let db = db.clone();
+            std::thread::spawn(move || -> Result<(), DbError> {
+                for id in 1..=ROWS {
+                    // A row is either not inserted yet or fully visible
+                    if let Some(row) = db.select_by_id(id)? {
+                        assert_eq!(row.id, id);
+                        assert_eq!(row.name, format!("user{id}"));
+                    }
+                }
+                Ok(())
+            })
+        })
+        .collect();
+
+    writer.join().expect("writer panicked")?;
+    for reader in readers {
+        reader.join().expect("reader panicked")?;
+    }
+
+    assert_eq!(db.select_all()?.len(), ROWS as usize);
+    assert_eq!(db.select_all_shared().len(), ROWS as usize);
+
+    Ok(())
+}
+
+#[test]
+// An insert racing a batch lands entirely before or after it, and neither side deadlocks
+fn exec_batch_is_serialized_with_concurrent_insert() -> Result<(), DbError> {
+    let dir = tempdir()?;
+
+    let path = "temp_data.json";
+    let file_path = dir.path().join(path);
+    let batch_path = dir.path().join("seed.txt");
+
+    let batch: String = (1..=200).map(|id| format!("INSERT {id} user{id} 30\n")).collect();
+    std::fs::write(&batch_path, batch)?;
+
+    let db = DatabaseHandle::new(&file_path)?;
+    let (done_tx, done_rx) = std::sync::mpsc::channel();
+
+    let batch_thread = {
+        let (db, done_tx) = (db.clone(), done_tx.clone());
+        std::thread::spawn(move || done_tx.send(db.exec_batch(batch_path).map(|_| ())).unwrap())
+    };
+    let insert_thread = {
+        let db = db.clone();
+        std::thread::spawn(move || done_tx.send(db.insert(1_000, "racer".into(), 40)).unwrap())
+    };
+
+    for _ in 0..2 {
+        done_rx
+            .recv_timeout(std::time::Duration::from_secs(10))
+            .expect("batch and insert deadlocked")?;
+    }
+    batch_thread.join().expect("batch thread panicked");
+    insert_thread.join().expect("insert thread panicked");
+
+    let ids = db.select_all()?.iter().map(|row| row.id).collect::<Vec<_>>();
+    assert_eq!(ids.len(), 201);
+
+    // The racing insert is never interleaved with the batch's rows
+    let pos = ids.iter().position(|&id| id == 1_000).unwrap();
+    assert!(pos == 0 || pos == 200, "insert landed inside the batch at {pos}");
+
+    Ok(())
+}
+
+#[test]
+// run_command returns structured results instead of printing them
+fn run_command_returns_structured_output() -> Result<(), DbError> {
+    let dir = tempdir()?;
+
+    let path = "temp_data.json";
+    let file_path = dir.path().join(path);
+
+    let db = DatabaseHandle::new(&file_path)?;
+    db.insert(1, "Alice".into(), 20)?;
+    db.insert(2, "Bob".into(), 30)?;
+
+    match parser::run_command("SELECT WHERE ID=1", &db) {
+        CommandOutput::Rows(rows) => assert_eq!(rows, vec![Row::new(1, "Alice".into(), 20)]),
+        other => panic!("expected rows, got {:?}", other),
+    }
+
+    assert!(matches!(parser::run_command("INSERT 3 Carol 40", &db), CommandOutput::Message(_)));
+    assert!(matches!(parser::run_command("DELETE WHERE AGE>=30", &db), CommandOutput::Affected(2)));
+    assert!(matches!(parser::run_command("INSERT 1 Again 50", &db), CommandOutput::Error(DbError::DuplicateIdError(1))));
+    assert!(matches!(parser::run_command("FROBNICATE", &db), CommandOutput::Error(DbError::InvalidCommandError)));
+    assert!(matches!(parser::run_command("EXIT", &db), CommandOutput::Exit));
+
+    Ok(())
+}
+
+#[test]
+// Bulk loading skips the parser entirely
+fn insert_many_loads_all_rows() -> Result<(), DbError> {
+    let dir = tempdir()?;
+
+    let path = "temp_data.json";
+    let file_path = dir.path().join(path);
+
+    {
+        let mut db = Database::new(&file_path)?;
+        let rows = (1..=1000).map(|id| (id, format!("user{id}"), (id % 100) as u8));
+
+        assert_eq!(db.insert_many(rows)?, 1000);
+        assert_eq!(db.select_all().len(), 1000);
+        assert_eq!(db.select_by_id_strict(500)?, Row::new(500, "user500".into(), 0));
+    }
+
+    let db = Database::new(&file_path)?;
+    assert_eq!(db.select_all().len(), 1000);
+
+    Ok(())
+}
+
+#[test]
+// A collision anywhere in the batch aborts the whole call, in memory and on disk
+fn insert_many_rolls_back_on_collision() -> Result<(), DbError> {
+    let dir = tempdir()?;
+
+    let path = "temp_data.json";
+    let file_path = dir.path().join(path);
+
+    {
+        let mut db = Database::new(&file_path)?;
+        db.insert(1, "Alice".into(), 20)?;
+
+        // Collides with an existing row
+        let rows = vec![(2, "Bob".to_string(), 30), (1, "Again".to_string(), 40)];
+        assert!(matches!(db.insert_many(rows), Err(DbError::DuplicateIdError(1))));
+
+        // Collides within the batch
+        let rows = vec![(3, "Carol".to_string(), 30), (4, "Dan".to_string(), 40), (3, "Again".to_string(), 50)];
+        assert!(matches!(db.insert_many(rows), Err(DbError::DuplicateIdError(3))));
+
+        assert_eq!(db.ids(), vec![1]);
+    }
+
+    let db = Database::new(&file_path)?;
+    assert_eq!(db.ids(), vec![1]);
+
+    Ok(())
+}
+
+#[test]
+// Upserting a new id inserts it; upserting an existing id overwrites it in place, also after restart
+fn upsert_inserts_then_updates() -> Result<(), DbError> {
+    let dir = tempdir()?;
+
+    let path = "temp_data.json";
+    let file_path = dir.path().join(path);
+
+    {
+        let mut db = Database::new(&file_path)?;
+        db.insert(1, "Alice".into(), 20)?;
+
+        assert!(db.upsert(2, "Bob".into(), 30)?);
+        assert_eq!(db.select_all().len(), 2);
+
+        assert!(!db.upsert(1, "Alicia".into(), 21)?);
+        assert_eq!(db.select_all().len(), 2);
+        assert_eq!(db.select_by_id_strict(1)?, Row::new(1, "Alicia".into(), 21));
+    }
+
+    let db = Database::new(&file_path)?;
+    assert_eq!(db.select_all(), &vec![Row::new(1, "Alicia".into(), 21), Row::new(2, "Bob".into(), 30)]);
+
+    Ok(())
+}
+
+#[test]
+fn delete_and_return_gives_back_removed_row() -> Result<(), DbError> {
+    let dir = tempdir()?;
+
+    let path = "temp_data.json";
+    let file_path = dir.path().join(path);
+
+    let mut db = Database::new(&file_path)?;
+    db.insert(1, "Alice".into(), 20)?;
+    db.insert(2, "Bob".into(), 30)?;
+
+    let removed = db.delete_and_return(1)?;
+    assert_eq!(removed, Some(Row::new(1, "Alice".into(), 20)));
+    assert_eq!(db.delete_and_return(1)?, None);
+
+    assert_eq!(db.ids(), vec![2]);
+    assert_eq!(db.get_index_position(2), Some(0));
+
+    Ok(())
+}
+
+#[test]
+// An in-memory database works end to end without creating any files
+fn in_memory_database_creates_no_files() -> Result<(), DbError> {
+    let list_cwd = || -> Result<Vec<std::path::PathBuf>, DbError> {
+        let mut entries: Vec<_> = std::fs::read_dir(".")?
+            .map(|entry| entry.map(|e| e.path()))
+            .collect::<Result<_, _>>()?;
+        entries.sort();
+        Ok(entries)
+    };
+    let before = list_cwd()?;
+
+    let mut db = Database::in_memory();
+    db.insert(1, "Alice".into(), 20)?;
+    db.insert(2, "Bob".into(), 30)?;
+    db.delete_by_id(1)?;
+    db.compact()?;
+    db.shutdown()?;
+    assert_eq!(db.select_all(), &vec![Row::new(2, "Bob".into(), 30)]);
+
+    let handle = DatabaseHandle::in_memory();
+    handle.insert(1, "Carol".into(), 40)?;
+    assert_eq!(handle.select_by_id(1)?, Some(Row::new(1, "Carol".into(), 40)));
+    handle.shutdown()?;
+
+    assert_eq!(list_cwd()?, before);
+
+    Ok(())
+}
+
+#[test]
+// Sorted output is id-ascending no matter the insert/delete history
+fn select_all_sorted_is_id_ascending() -> Result<(), DbError> {
+    let dir = tempdir()?;
+
+    let path = "temp_data.json";
+    let file_path = dir.path().join(path);
+
+    let db = DatabaseHandle::new(&file_path)?;
+    for id in [5, 1, 4, 2, 3] {
+        db.insert(id, format!("user{id}"), 30)?;
+    }
+    let sorted_ids = |db: &DatabaseHandle| db.select_all_sorted().iter().map(|r| r.id).collect::<Vec<_>>();
+    assert_eq!(sorted_ids(&db), vec![1, 2, 3, 4, 5]);
+
+    db.delete_by_id(4)?;
+    assert_eq!(sorted_ids(&db), vec![1, 2, 3, 5]);
+
+    db.insert(4, "again".into(), 31)?;
+    assert_eq!(sorted_ids(&db), vec![1, 2, 3, 4, 5]);
+
+    // SELECT uses the same order
+    match parser::run_command("SELECT", &db) {
+        CommandOutput::Rows(rows) => assert_eq!(rows, db.select_all_sorted()),
+        other => panic!("expected rows, got {:?}", other),
+    }
+
+    Ok(())
+}
+
+#[test]
+// Projected rows only carry the requested fields
+fn select_projection_keeps_only_requested_fields() -> Result<(), DbError> {
+    let dir = tempdir()?;
+
+    let path = "temp_data.json";
+    let file_path = dir.path().join(path);
+
+    let mut db = Database::new(&file_path)?;
+    db.insert(2, "Bob".into(), 30)?;
+    db.insert(1, "Alice".into(), 20)?;
+
+    let names = db.select_projection(&[Field::Name]);
+    assert_eq!(names, vec![serde_json::json!({"name": "Alice"}), serde_json::json!({"name": "Bob"})]);
+
+    let pairs = db.select_projection(&[Field::Id, Field::Age]);
+    assert_eq!(pairs, vec![serde_json::json!({"id": 1, "age": 20}), serde_json::json!({"id": 2, "age": 30})]);
+    assert!(pairs.iter().all(|value| value.get("name").is_none()));
+
+    Ok(())
+}
+
+#[test]
+// Repeated ages and names collapse into one sorted value each
+fn distinct_values_are_sorted_and_deduplicated() -> Result<(), DbError> {
+    let dir = tempdir()?;
+
+    let path = "temp_data.json";
+    let file_path = dir.path().join(path);
+
+    let mut db = Database::new(&file_path)?;
+    db.insert(1, "Carol".into(), 40)?;
+    db.insert(2, "Alice".into(), 25)?;
+    db.insert(3, "Bob".into(), 40)?;
+    db.insert(4, "Alice".into(), 25)?;
+    db.insert(5, "Dave".into(), 18)?;
+
+    assert_eq!(db.distinct_ages(), vec![18, 25, 40]);
+    assert_eq!(db.distinct_names(), vec!["Alice", "Bob", "Carol", "Dave"]);
+
+    let cmd = parser::parse_command("SELECT DISTINCT AGE")?;
+    assert_eq!(db.query(&cmd)?, CommandOutcome::Values(vec!["18".into(), "25".into(), "40".into()]));
+
+    Ok(())
+}
+
+#[test]
+// MIN, MAX and AVG over a known set of ages
+fn aggregate_age_computes_min_max_avg() -> Result<(), DbError> {
+    let dir = tempdir()?;
+
+    let path = "temp_data.json";
+    let file_path = dir.path().join(path);
+
+    let mut db = Database::new(&file_path)?;
+    db.insert(1, "Alice".into(), 20)?;
+    db.insert(2, "Bob".into(), 35)?;
+    db.insert(3, "Carol".into(), 30)?;
+    db.insert(4, "Dave".into(), 20)?;
+
+    assert_eq!(db.aggregate_age(AggregateFunc::Min), Some(20.0));
+    assert_eq!(db.aggregate_age(AggregateFunc::Max), Some(35.0));
+    assert_eq!(db.aggregate_age(AggregateFunc::Avg), Some(26.25));
+
+    let cmd = parser::parse_command("SELECT MAX(AGE)")?;
+    assert_eq!(db.query(&cmd)?, CommandOutcome::Values(vec!["35".into()]));
+
+    Ok(())
+}
+
+#[test]
+// Aggregates over an empty table have no value
+fn aggregate_age_on_empty_table_is_none() -> Result<(), DbError> {
+    let db = Database::in_memory();
+
+    assert_eq!(db.aggregate_age(AggregateFunc::Min), None);
+    assert_eq!(db.aggregate_age(AggregateFunc::Max), None);
+    assert_eq!(db.aggregate_age(AggregateFunc::Avg), None);
+
+    let cmd = parser::parse_command("SELECT AVG(AGE)")?;
+    assert_eq!(db.query(&cmd)?, CommandOutcome::Values(vec![]));
+
+    Ok(())
+}
+
+#[test]
+// FLUSH makes inserted rows visible to a second handle opened on the same log
+fn flush_command_persists_rows() -> Result<(), DbError> {
+    let dir = tempdir()?;
+
+    let path = "temp_data.json";
+    let file_path = dir.path().join(path);
+
+    let db = DatabaseHandle::new(&file_path)?;
+    db.insert(1, "Alice".into(), 20)?;
+    db.insert(2, "Bob".into(), 30)?;
+
+    match parser::run_command("FLUSH", &db) {
+        CommandOutput::Message(message) => assert_eq!(message, "Flushed to disk."),
+        other => panic!("expected a message, got {:?}", other),
+    }
+
+    // The first handle still holds the lock, so read the files without taking it
+    let reopened = DatabaseHandle::open_readonly(&file_path)?;
+    assert_eq!(reopened.select_all_sorted(), db.select_all_sorted());
+    assert_eq!(reopened.select_all()?.len(), 2);
+
+    Ok(())
+}
+
+#[test]
+// The COMPACT command shrinks the log without changing the rows
+fn compact_command_shrinks_log() -> Result<(), DbError> {
+    let dir = tempdir()?;
+
+    let path = "temp_data.json";
+    let file_path = dir.path().join(path);
+
+    let db = DatabaseHandle::new(&file_path)?;
+    for id in 1..=20 {
+        db.insert(id, format!("user{id}"), 30)?;
+    }
+    db.delete_by_id(1)?;
+    let before = db.select_all()?;
+    let log_size = std::fs::metadata(&file_path)?.len();
+
+    match parser::run_command("COMPACT", &db) {
+        CommandOutput::Message(message) => {
+            assert_eq!(message, "Compacted 19 row(s) into the snapshot; log truncated.")
+        },
+        other => panic!("expected a message, got {:?}", other),
+    }
+
+    assert!(std::fs::metadata(&file_path)?.len() < log_size);
+    assert_eq!(db.select_all()?, before);
+    drop(db);
+
+    let reopened = DatabaseHandle::new(&file_path)?;
+    assert_eq!(reopened.select_all_sorted().len(), 19);
+
+    Ok(())
+}
+
+#[test]
+// LIKE patterns with prefix, suffix and middle wildcards, case-insensitively
+fn select_by_name_like_matches_wildcards() -> Result<(), DbError> {
+    let mut db = Database::in_memory();
+    db.insert(1, "Alice".into(), 30)?;
+    db.insert(2, "alfred".into(), 40)?;
+    db.insert(3, "Bob".into(), 50)?;
+    db.insert(4, "Grace".into(), 60)?;
+    db.insert(5, "100%".into(), 70)?;
+
+    let ids = |rows: Vec<Row>| rows.iter().map(|row| row.id).collect::<Vec<_>>();
+
+    assert_eq!(ids(db.select_by_name_like("Al%")), vec![1, 2]);
+    assert_eq!(ids(db.select_by_name_like("%CE")), vec![1, 4]);
+    assert_eq!(ids(db.select_by_name_like("a%e")), vec![1]);
+    assert_eq!(ids(db.select_by_name_like("B_b")), vec![3]);
+    assert_eq!(ids(db.select_by_name_like("%")), vec![1, 2, 3, 4, 5]);
+    assert_eq!(ids(db.select_by_name_like("100\\%")), vec![5]);
+    assert_eq!(ids(db.select_by_name_like("10\\_")), Vec::<u32>::new());
+
+    let cmd = parser::parse_command("SELECT WHERE NAME LIKE 'gr%'")?;
+    assert_eq!(db.query(&cmd)?, CommandOutcome::Rows(vec![Row::new(4, "Grace".into(), 60)]));
+
+    Ok(())
+}
+
+#[test]
+// Entry count covers both the inserts and the delete, while row count only covers survivors
+fn stats_count_entries_and_rows() -> Result<(), DbError> {
+    let dir = tempdir()?;
+
+    let path = "temp_data.json";
+    let file_path = dir.path().join(path);
+
+    let db = DatabaseHandle::new(&file_path)?;
+    db.insert(1, "Alice".into(), 20)?;
+    db.insert(2, "Bob".into(), 30)?;
+    db.insert(3, "Carol".into(), 40)?;
+    db.delete_by_id(2)?;
+
+    let stats = db.stats()?;
+    assert_eq!(stats.row_count, 2);
+    assert_eq!(stats.entry_count, 4);
+    assert_eq!(stats.log_bytes, std::fs::metadata(&file_path)?.len());
+    assert!(!stats.snapshot_exists);
+
+    db.compact()?;
+    let stats = db.stats()?;
+    assert_eq!(stats.row_count, 2);
+    assert_eq!(stats.entry_count, 0);
+    assert!(stats.snapshot_exists);
+
+    assert!(matches!(parser::run_command("STATS", &db), CommandOutput::Message(m) if m.starts_with("Rows: 2\n")));
+
+    Ok(())
+}
+
+#[test]
+// Undoing an insert, a delete and an update each restores the prior state, also after a restart
+fn undo_reverses_insert_delete_and_update() -> Result<(), DbError> {
+    let dir = tempdir()?;
+
+    let path = "temp_data.json";
+    let file_path = dir.path().join(path);
+
+    let mut db = Database::new(&file_path)?;
+
+    // Insert, then undo: no rows left
+    db.insert(1, "Alice".into(), 30)?;
+    assert!(db.undo()?);
+    assert!(db.select_all().is_empty());
+
+    // Delete, then undo: the exact row is back
+    db.insert(2, "Bob".into(), 40)?;
+    let original = db.select_by_id(2)?.unwrap();
+    db.delete_by_id(2)?;
+    assert!(db.undo()?);
+    let restored = db.select_by_id(2)?.unwrap();
+    assert_eq!(restored, original);
+    assert_eq!(restored.created_at, original.created_at);
+
+    // Update, then undo: the prior values are back
+    db.update(2, "Robert".into(), 41)?;
+    assert!(db.undo()?);
+    assert_eq!(db.select_by_id(2)?, Some(Row::new(2, "Bob".into(), 40)));
+
+    // Undone writes stay undone after a restart
+    db.shutdown()?;
+    drop(db);
+    let mut db = Database::new(&file_path)?;
+    assert_eq!(db.select_all_sorted(), vec![Row::new(2, "Bob".into(), 40)]);
+
+    // The history does not survive a restart
+    assert!(!db.undo()?);
+
+    Ok(())
+}
+
+#[test]
+// Rows come back in list order, skipping missing IDs and collapsing duplicates
+fn select_by_ids_follows_list_order() -> Result<(), DbError> {
+    let mut db = Database::in_memory();
+    for id in 1..=12 {
+        db.insert(id, format!("user{id}"), 20)?;
+    }
+
+    let ids = |rows: Vec<Row>| rows.iter().map(|row| row.id).collect::<Vec<_>>();
+
+    assert_eq!(ids(db.select_by_ids(&[9, 5, 42, 12])), vec![9, 5, 12]);
+    assert_eq!(ids(db.select_by_ids(&[3, 3, 7, 3])), vec![3, 7]);
+    assert!(db.select_by_ids(&[100, 200]).is_empty());
+
+    let cmd = parser::parse_command("SELECT WHERE ID IN (12, 2, 99)")?;
+    assert_eq!(db.query(&cmd)?, CommandOutcome::Rows(db.select_by_ids(&[12, 2])));
+
+    Ok(())
+}
+
+#[test]
+// The index stays consistent with the rows through inserts, updates, deletes, undo and a restart
+fn integrity_holds_across_operations() -> Result<(), DbError> {
+    let dir = tempdir()?;
+
+    let path = "temp_data.json";
+    let file_path = dir.path().join(path);
+
+    let mut db = Database::new(&file_path)?;
+    db.check_integrity()?;
+
+    for id in 1..=10 {
+        db.insert(id, format!("User{id}"), 20 + id as u8)?;
+    }
+    db.check_integrity()?;
+
+    db.delete_by_id(3)?;
+    db.update(7, "Seven".into(), 70)?;
+    db.delete_where_age(CompareOp::Gt, 28)?;
+    db.check_integrity()?;
+
+    db.undo()?;
+    db.upsert(11, "Eleven".into(), 11)?;
+    db.delete_by_ids(&[1, 11, 42])?;
+    db.check_integrity()?;
+    db.shutdown()?;
+    drop(db);
+
+    let db = Database::new(&file_path)?;
+    db.check_integrity()?;
+
+    Ok(())
+}
+
+#[test]
+// The youngest and oldest rows come back in order, with all rows returned when n exceeds the count
+fn top_n_by_age_returns_extremes_in_order() -> Result<(), DbError> {
+    let dir = tempdir()?;
+
+    let path = "temp_data.json";
+    let file_path = dir.path().join(path);
+
+    let mut db = Database::new(&file_path)?;
+    let ages = [40, 18, 65, 33, 18, 90, 27, 51, 12, 70];
+    for (id, age) in (1..).zip(ages) {
+        db.insert(id, format!("User{id}"), age)?;
+    }
+
+    let youngest: Vec<(u32, u8)> = db.top_n_by_age(5, true).iter().map(|r| (r.id, r.age)).collect();
+    assert_eq!(youngest, vec![(9, 12), (2, 18), (5, 18), (7, 27), (4, 33)]);
+
+    let oldest: Vec<(u32, u8)> = db.top_n_by_age(5, false).iter().map(|r| (r.id, r.age)).collect();
+    assert_eq!(oldest, vec![(6, 90), (10, 70), (3, 65), (8, 51), (1, 40)]);
+
+    let all = db.top_n_by_age(50, true);
+    assert_eq!(all.len(), ages.len());
+    assert!(all.windows(2).all(|pair| pair[0].age <= pair[1].age));
+    assert!(db.top_n_by_age(0, true).is_empty());
+
+    let outcome = db.query(&parser::parse_command("SELECT OLDEST 2")?)?;
+    assert_eq!(outcome, CommandOutcome::Rows(db.top_n_by_age(2, false)));
+
+    Ok(())
+}
+
+#[test]
+// Failing batch lines are reported with their line numbers: all of them when lenient, the first when strict
+fn exec_batch_reports_failing_lines() -> Result<(), DbError> {
+    let dir = tempdir()?;
+
+    let path = "temp_data.json";
+    let file_path = dir.path().join(path);
+    let batch_path = dir.path().join("seed.txt");
+
+    std::fs::write(&batch_path, "INSERT 1 Alice 20\nINSERT 2 Bob old\nINSERT 3 Carol 40\nINSERT 1 Dave 50\n")?;
+
+    let mut db = Database::new(&file_path)?;
+    let failures = db.exec_batch_with(&batch_path, BatchMode::Lenient)?;
+    let lines: Vec<usize> = failures.iter().map(|(line, _)| *line).collect();
+    assert_eq!(lines, vec![2, 4]);
+    assert!(matches!(failures[0].1, DbError::ParseError(_)));
+    assert!(matches!(failures[1].1, DbError::DuplicateIdError(1)));
+    assert_eq!(db.ids(), vec![1, 3]);
+
+    db.reset_db()?;
+    match db.exec_batch_with(&batch_path, BatchMode::Strict) {
+        Err(DbError::BatchLineError { line: 2, source }) => assert!(matches!(*source, DbError::ParseError(_))),
+        other => panic!("expected a failure on line 2, got {:?}", other),
+    }
+    assert_eq!(db.ids(), vec![1]);
+
+    Ok(())
+}
+
+#[test]
+// Rows are counted per age in ascending age order, and per name
+fn count_group_by_age_and_name() -> Result<(), DbError> {
+    let dir = tempdir()?;
+
+    let path = "temp_data.json";
+    let file_path = dir.path().join(path);
+
+    let mut db = Database::new(&file_path)?;
+    db.insert(1, "Alice".into(), 30)?;
+    db.insert(2, "Bob".into(), 18)?;
+    db.insert(3, "Alice".into(), 30)?;
+    db.insert(4, "Carol".into(), 65)?;
+    db.insert(5, "Dave".into(), 18)?;
+    db.insert(6, "Eve".into(), 30)?;
+
+    let by_age: Vec<(u8, usize)> = db.count_by_age().into_iter().collect();
+    assert_eq!(by_age, vec![(18, 2), (30, 3), (65, 1)]);
+
+    let by_name = db.count_by_name();
+    assert_eq!(by_name.len(), 5);
+    assert_eq!(by_name["Alice"], 2);
+    assert_eq!(by_name["Eve"], 1);
+
+    let outcome = db.query(&parser::parse_command("SELECT COUNT GROUP BY AGE")?)?;
+    assert_eq!(outcome, CommandOutcome::Values(vec!["18: 2".into(), "30: 3".into(), "65: 1".into()]));
+
+    Ok(())
+}
+
+#[test]
+// The handle's select_all returns every row through a Result, and select_all_ref sees the same rows without cloning
+fn handle_select_all_returns_result() -> Result<(), DbError> {
+    let db = DatabaseHandle::in_memory();
+    db.insert(1, "Alice".into(), 30)?;
+    db.insert(2, "Bob".into(), 17)?;
+
+    let rows: Result<Vec<Row>, DbError> = db.select_all();
+    let mut rows = rows?;
+    rows.sort_by_key(|row| row.id);
+    assert_eq!(rows, vec![Row::new(1, "Alice".into(), 30), Row::new(2, "Bob".into(), 17)]);
+
+    let adults = db.select_all_ref(|rows| rows.iter().filter(|row| row.age >= 18).count());
+    assert_eq!(adults, 1);
+    assert_eq!(db.select_all_ref(<[Row]>::len), 2);
+
+    Ok(())
+}
+
+#[test]
+// contains_id answers from the index for present, absent and deleted ids, and EXISTS prints the answer
+fn contains_id_reports_presence() -> Result<(), DbError> {
+    let db = DatabaseHandle::in_memory();
+    db.insert(5, "Alice".into(), 30)?;
+    db.insert(6, "Bob".into(), 17)?;
+
+    assert!(db.contains_id(5));
+    assert!(!db.contains_id(7));
+
+    db.delete_by_id(6)?;
+    assert!(!db.contains_id(6));
+
+    let outcome = db.query(&parser::parse_command("EXISTS ID=5")?)?;
+    assert_eq!(outcome, CommandOutcome::Values(vec!["true".into()]));
+    let outcome = db.query(&parser::parse_command("EXISTS ID=7")?)?;
+    assert_eq!(outcome, CommandOutcome::Values(vec!["false".into()]));
+
+    Ok(())
+}
+
+#[test]
+// Under the default Reject policy, inserts past the capacity fail and leave the rows untouched
+fn capacity_rejects_inserts_past_the_cap() -> Result<(), DbError> {
+    let mut db = Database::in_memory().with_capacity(2);
+    assert_eq!(db.eviction_policy(), EvictionPolicy::Reject);
+
+    db.insert(1, "Alice".into(), 30)?;
+    db.insert(2, "Bob".into(), 25)?;
+    assert!(matches!(db.insert(3, "Carol".into(), 40), Err(DbError::CapacityExceeded { capacity: 2 })));
+    assert!(matches!(db.insert_many([(3, "Carol".into(), 40)]), Err(DbError::CapacityExceeded { .. })));
+    assert_eq!(db.ids(), vec![1, 2]);
+
+    db.delete_by_id(1)?;
+    db.insert(3, "Carol".into(), 40)?;
+    assert_eq!(db.ids(), vec![2, 3]);
+
+    Ok(())
+}
+
+#[test]
+// Opening more rows than the capacity keeps them all until an insert, which evicts down to the cap or is rejected
+fn capacity_applies_to_over_capacity_data_on_insert() -> Result<(), DbError> {
+    let dir = tempdir()?;
+
+    let path = "temp_data.json";
+    let file_path = dir.path().join(path);
+
+    {
+        let mut db = Database::new(&file_path)?;
+        for id in 1..=4 {
+            let mut row = Row::new(id, format!("user{id}"), 30);
+            row.created_at = id as i64;
+            db.insert_row(row)?;
+        }
+    }
+
+    {
+        let mut db = Database::new(&file_path)?.with_capacity(2);
+        assert_eq!(db.ids(), vec![1, 2, 3, 4]);
+        assert!(matches!(db.insert(5, "Erin".into(), 22), Err(DbError::CapacityExceeded { capacity: 2 })));
+        assert_eq!(db.ids(), vec![1, 2, 3, 4]);
+
+        db.set_eviction_policy(EvictionPolicy::EvictOldest);
+        db.insert(5, "Erin".into(), 22)?;
+        assert_eq!(db.ids(), vec![4, 5]);
+    }
+
+    let db = Database::new(&file_path)?;
+    assert_eq!(db.ids(), vec![4, 5]);
+
+    Ok(())
+}
+
+#[test]
+// Under EvictOldest the count stays at the cap, the earliest-inserted row goes first, and evictions are logged
+fn capacity_evicts_oldest_rows() -> Result<(), DbError> {
+    let dir = tempdir()?;
+
+    let path = "temp_data.json";
+    let file_path = dir.path().join(path);
+
+    {
+        let mut db = Database::new(&file_path)?.with_capacity(3);
+        db.set_eviction_policy(EvictionPolicy::EvictOldest);
+
+        // Row 2 is the oldest by created_at even though it was inserted second
+        let mut old = Row::new(2, "Old".into(), 50);
+        old.created_at = 1;
+        db.insert(1, "Alice".into(), 30)?;
+        db.insert_row(old)?;
+        db.insert(3, "Carol".into(), 40)?;
+
+        db.insert(4, "Dave".into(), 20)?;
+        assert_eq!(db.ids(), vec![1, 3, 4]);
+
+        db.insert(5, "Erin".into(), 22)?;
+        assert_eq!(db.ids(), vec![3, 4, 5]);
+
+        assert_eq!(db.insert_many([(6, "Frank".into(), 33), (7, "Gina".into(), 44)])?, 2);
+        assert_eq!(db.ids(), vec![5, 6, 7]);
+        db.flush()?;
+    }
+
+    let db = Database::new(&file_path)?;
+    assert_eq!(db.ids(), vec![5, 6, 7]);
+
+    Ok(())
+}
+
+#[test]
+// Time filters include both bounds, an inverted range is empty, and CREATED AFTER/BEFORE select the same rows
+fn select_created_between_filters_by_timestamp() -> Result<(), DbError> {
+    let mut db = Database::in_memory();
+    for (id, created_at) in [(1, 100), (2, 200), (3, 300), (4, 400)] {
+        let mut row = Row::new(id, format!("User{id}"), 30);
+        row.created_at = created_at;
+        db.insert_row(row)?;
+    }
+
+    let ids = |rows: Vec<Row>| rows.iter().map(|row| row.id).collect::<Vec<_>>();
+    assert_eq!(ids(db.select_created_between(200, 300)), vec![2, 3]);
+    assert_eq!(ids(db.select_created_between(150, 150)), Vec::<u32>::new());
+    assert_eq!(ids(db.select_created_between(300, 200)), Vec::<u32>::new());
+
+    let outcome = db.query(&parser::parse_command("SELECT WHERE CREATED AFTER 200 AND CREATED BEFORE 300")?)?;
+    assert_eq!(outcome, CommandOutcome::Rows(db.select_created_between(200, 300)));
+
+    let outcome = db.query(&parser::parse_command("SELECT WHERE CREATED BEFORE 199")?)?;
+    assert_eq!(outcome, CommandOutcome::Rows(vec![Row::new(1, "User1".into(), 30)]));
+
+    Ok(())
+}
+
+#[test]
+// insert_if_absent inserts a new id once, then leaves the existing row unchanged without an error
+fn insert_if_absent_keeps_existing_row() -> Result<(), DbError> {
+    let mut db = Database::in_memory();
+
+    assert!(db.insert_if_absent(1, "Alice".into(), 30)?);
+    assert!(!db.insert_if_absent(1, "Alicia".into(), 31)?);
+    assert_eq!(db.select_by_id(1)?.map(|row| (row.name, row.age)), Some(("Alice".into(), 30)));
+
+    let handle = DatabaseHandle::in_memory();
+    parser::run_command("INSERT IF ABSENT 1 Alice 30", &handle);
+    let output = parser::run_command("INSERT IF ABSENT 1 Bob 40", &handle);
+    assert!(matches!(output, CommandOutput::Message(message) if message.contains("already exists")));
+    assert_eq!(handle.select_all()?, vec![Row::new(1, "Alice".into(), 30)]);
+
+    Ok(())
+}
+
+#[test]
+#[cfg(feature = "timestamps")]
+// With the timestamps feature, inserts and updates stamp rows with the current time
+fn timestamps_are_populated() -> Result<(), DbError> {
+    let mut db = Database::in_memory();
+    db.insert(1, "Alice".into(), 30)?;
+    db.update(1, "Alice".into(), 31)?;
+
+    let row = db.select_by_id(1)?.ok_or(DbError::RowNotFound(1))?;
+    assert!(row.created_at > 1_600_000_000);
+    assert!(row.updated_at >= row.created_at);
+
+    Ok(())
+}
+
+#[test]
+// A fixed seed always samples the same rows, n is capped by the table size, and an empty table samples nothing
+fn sample_seeded_is_deterministic() -> Result<(), DbError> {
+    let mut db = Database::in_memory();
+    assert!(db.sample(5).is_empty());
+    assert!(db.sample_seeded(5, 42).is_empty());
+
+    for id in 1..=20 {
+        db.insert(id, format!("User{id}"), 20 + id as u8)?;
+    }
+
+    let ids = |rows: Vec<Row>| rows.iter().map(|row| row.id).collect::<Vec<_>>();
+    assert_eq!(ids(db.sample_seeded(5, 42)), vec![3, 5, 9, 11, 18]);
+    assert_eq!(db.sample_seeded(5, 42), db.sample_seeded(5, 42));
+    assert_eq!(db.sample(5).len(), 5);
+    assert_eq!(db.sample(50).len(), 20);
+
+    let outcome = db.query(&parser::parse_command("SELECT SAMPLE 3")?)?;
+    assert!(matches!(outcome, CommandOutcome::Rows(rows) if rows.len() == 3));
+
+    Ok(())
+}
+
+#[test]
+// all_ids lists the remaining ids sorted ascending, whatever the insertion order, and SELECT IDS returns the same
+fn all_ids_are_sorted_index_keys() -> Result<(), DbError> {
+    let mut db = Database::in_memory();
+    for id in [42, 7, 19, 3, 28] {
+        db.insert(id, format!("User{id}"), 30)?;
+    }
+    db.delete_by_id(19)?;
+
+    assert_eq!(db.all_ids(), vec![3, 7, 28, 42]);
+    let mut ids = db.ids();
+    ids.sort_unstable();
+    assert_eq!(db.all_ids(), ids);
+
+    let outcome = db.query(&parser::parse_command("SELECT IDS")?)?;
+    assert_eq!(outcome, CommandOutcome::Keys(vec![3, 7, 28, 42]));
+
+    Ok(())
+}
+
+#[test]
+// Name lookups are exact by default; in case-insensitive mode "alice" finds "Alice", which keeps its stored case
+fn select_by_name_respects_case_policy() -> Result<(), DbError> {
+    let mut db = Database::in_memory();
+    db.insert(1, "Alice".into(), 30)?;
+    db.insert(2, "Bob".into(), 25)?;
+
+    assert!(!db.case_insensitive_names());
+    assert!(db.select_by_name("alice").is_empty());
+    assert_eq!(db.select_by_name("Alice"), vec![Row::new(1, "Alice".into(), 30)]);
+
+    db.set_case_insensitive_names(true);
+    let rows = db.select_by_name("alice");
+    assert_eq!(rows, vec![Row::new(1, "Alice".into(), 30)]);
+    assert_eq!(rows[0].name, "Alice");
+    assert_eq!(db.select_by_name("ALICE"), rows);
+
+    Ok(())
+}
+
+#[test]
+// RESET inside a batch file, or sent through run_command, clears nothing without the shell's token
+fn reset_in_batch_leaves_rows() -> Result<(), DbError> {
+    let dir = tempdir()?;
+    let batch_path = dir.path().join("reset.txt");
+    std::fs::write(&batch_path, "INSERT 2 Bob 25\nRESET\n")?;
+
+    let db = DatabaseHandle::in_memory();
+    db.insert(1, "Alice".into(), 30)?;
+
+    let mut state = parser::ReplState::default();
+    assert!(parser::handle_shell_command(&format!("EXEC BATCH {}", batch_path.display()), &db, &mut state));
+    assert_eq!(db.all_ids(), vec![1, 2]);
+
+    let report = db.exec_batch(batch_path)?;
+    assert_eq!(report.failed, 2);
+    assert!(matches!(parser::run_command("RESET", &db), CommandOutput::Message(_)));
+    assert_eq!(db.all_ids(), vec![1, 2]);
+
+    Ok(())
+}
+
+#[test]
+// A batch file of nothing but garbage reports zero executed lines, and EXEC BATCH turns that into an error
+fn exec_batch_reports_all_garbage_file() -> Result<(), DbError> {
+    let dir = tempdir()?;
+    let batch_path = dir.path().join("garbage.txt");
+    std::fs::write(&batch_path, "hello there\nINSERT x y z\n# a comment\nSELECT WHERE\n")?;
+
+    let mut db = Database::in_memory();
+    let report = db.exec_batch(batch_path.clone())?;
+    assert_eq!((report.executed, report.failed), (0, 3));
+    assert!(report.outputs.iter().all(|o| matches!(o, CommandOutput::Error(DbError::BatchLineError { .. }))));
+    assert!(db.select_all().is_empty());
+
+    let handle = DatabaseHandle::in_memory();
+    let output = parser::run_command(&format!("EXEC BATCH {}", batch_path.display()), &handle);
+    let CommandOutput::Batch(outputs) = output else { panic!("expected batch output") };
+    assert!(matches!(outputs.last(), Some(CommandOutput::Error(DbError::ParseError(_)))));
+
+    let seed_path = dir.path().join("seed.txt");
+    std::fs::write(&seed_path, "INSERT 1 Alice 30\nnonsense\n")?;
+    let report = db.exec_batch(seed_path)?;
+    assert_eq!((report.executed, report.failed), (1, 1));
+
+    Ok(())
+}
+
+#[test]
+// A batch file that runs itself fails on that line instead of recursing, and can be run again afterwards
+fn exec_batch_rejects_batch_running_itself() -> Result<(), DbError> {
+    let dir = tempdir()?;
+    let batch_path = dir.path().join("self.txt");
+    std::fs::write(&batch_path, format!("INSERT 1 Alice 30\nEXEC BATCH {}\nINSERT 2 Bob 25\n", batch_path.display()))?;
+
+    let mut db = Database::in_memory();
+    let report = db.exec_batch(batch_path.clone())?;
+    assert_eq!((report.executed, report.failed), (2, 1));
+    match &report.outputs[..] {
+        [CommandOutput::Error(DbError::BatchLineError { line: 2, source })] => {
+            assert!(matches!(**source, DbError::RecursiveBatch { .. }));
+        },
+        other => panic!("expected a recursion error on line 2, got {:?}", other),
+    }
+    assert_eq!(db.ids(), vec![1, 2]);
+
+    db.reset_db()?;
+    let failures = db.exec_batch_with(&batch_path, BatchMode::Lenient)?;
+    assert_eq!(failures.len(), 1);
+    assert_eq!(db.ids(), vec![1, 2]);
+
+    Ok(())
+}
+
+#[test]
+// A closure filter returns the matching rows and releases the read lock afterwards
+fn select_where_fn_filters_with_closure() -> Result<(), DbError> {
+    let db = DatabaseHandle::in_memory();
+    db.insert(1, "Alice".into(), 30)?;
+    db.insert(2, "Bob".into(), 25)?;
+    db.insert(3, "Carol".into(), 42)?;
+
+    let rows = db.select_where_fn(|r| r.age % 2 == 0);
+    let ids: Vec<u32> = rows.iter().map(|r| r.id).collect();
+    assert_eq!(ids, vec![1, 3]);
+
+    // A write right after the scan would deadlock if the lock were still held
+    db.insert(4, "Dave".into(), 50)?;
+    assert_eq!(db.select_where_fn(|r| r.age % 2 == 0).len(), 3);
+
+    Ok(())
+}
+
+#[test]
+// A re-keyed row keeps its data under the new ID, also after reopening the log
+fn rekey_persists_across_restart() -> Result<(), DbError> {
+    let dir = tempdir()?;
+
+    let path = "temp_data.json";
+    let file_path = dir.path().join(path);
+
+    let mut db = Database::new(&file_path)?;
+    db.insert(5, "Alice".into(), 30)?;
+    db.insert(6, "Bob".into(), 25)?;
+    db.rekey(5, 9)?;
+    assert_eq!(db.all_ids(), vec![6, 9]);
+    db.shutdown()?;
+    drop(db);
+
+    let db = Database::new(&file_path)?;
+    assert_eq!(db.select_by_id(5)?, None);
+    let row = db.select_by_id(9)?.expect("row moved to id 9");
+    assert_eq!((row.name.as_str(), row.age), ("Alice", 30));
+
+    Ok(())
+}
+
+#[test]
+// Rekeying onto a taken ID or from a missing one fails and changes nothing
+fn rekey_rejects_taken_and_missing_ids() -> Result<(), DbError> {
+    let mut db = Database::in_memory();
+    db.insert(1, "Alice".into(), 30)?;
+    db.insert(2, "Bob".into(), 25)?;
+
+    assert!(matches!(db.rekey(1, 2), Err(DbError::DuplicateIdError(2))));
+    assert!(matches!(db.rekey(7, 8), Err(DbError::RowNotFound(7))));
+    assert_eq!(db.all_ids(), vec![1, 2]);
+
+    // Undo moves a successful rekey back
+    db.rekey(1, 3)?;
+    assert!(db.undo()?);
+    assert_eq!(db.all_ids(), vec![1, 2]);
+
+    Ok(())
+}
+
+#[test]
+// Paging with select_after_id visits every row once, in ascending ID order, whatever the insertion order
+fn select_after_id_pages_cover_every_row() -> Result<(), DbError> {
+    let mut db = Database::in_memory();
+    for id in (1..=23).rev() {
+        db.insert(id * 3, format!("user{id}"), 30)?;
+    }
+
+    let mut seen = Vec::new();
+    let mut cursor = 0;
+    loop {
+        let page = db.select_after_id(cursor, 5);
+        assert!(page.len() <= 5);
+        let Some(last) = page.last() else { break };
+        cursor = last.id;
+        seen.extend(page.iter().map(|r| r.id));
+    }
+
+    assert_eq!(seen, (1..=23).map(|id| id * 3).collect::<Vec<u32>>());
+    assert!(db.select_after_id(69, 5).is_empty());
+    assert!(db.select_after_id(0, 0).is_empty());
+
+    Ok(())
+}
+
+#[test]
+// The schema lists the core columns with their Rust types, then any extra fields in use
+fn schema_lists_core_and_extra_fields() -> Result<(), DbError> {
+    let mut db = Database::in_memory();
+    let schema = db.schema();
+    for (name, ty) in [("id", "u32"), ("name", "String"), ("age", "u8")] {
+        assert!(schema.contains(&(name.to_string(), ty.to_string())), "missing {name}: {ty}");
+    }
+
+    let mut row = Row::new(1, "Alice".into(), 30);
+    row.set_extra("city", "Paris".into());
+    db.insert_row(row)?;
+    assert_eq!(db.schema().last(), Some(&("city".to_string(), "json".to_string())));
+
+    Ok(())
+}
+
+#[test]
+// EXPLAIN names the index for ID lookups and a full scan with its filter otherwise
+fn explain_describes_access_method() -> Result<(), DbError> {
+    let db = Database::in_memory();
+    let explain = |input: &str| -> Result<String, DbError> {
+        Ok(db.explain(&parser::parse_command(input)?))
+    };
+
+    assert_eq!(explain("SELECT WHERE ID=5")?, "IndexLookup(id=5)");
+    assert_eq!(explain("SELECT WHERE NAME=Alice")?, "FullScan + filter(name='Alice')");
+    assert_eq!(explain("SELECT WHERE AGE>30 AND AGE<=40")?, "FullScan + filter(age>30 AND age<=40)");
+    assert_eq!(explain("DELETE WHERE ID IN (1, 2)")?, "IndexLookup(id IN (1, 2))");
+    assert_eq!(explain("STATS")?, "NoRowAccess");
+
+    Ok(())
+}
+
+#[test]
+// A filtered, limited scan yields exactly the first matches and stops reading rows once it has them
+fn scan_filters_and_takes_lazily() -> Result<(), DbError> {
+    let mut db = Database::in_memory();
+    for id in 1..=10 {
+        db.insert(id, format!("user{id}"), 20 + id as u8)?;
+    }
+
+    let visited = std::cell::Cell::new(0);
+    let rows: Vec<&Row> = db.scan()
+        .inspect(|_| visited.set(visited.get() + 1))
+        .filter(|r| r.age % 2 == 0)
+        .take(3)
+        .collect();
+
+    let ids: Vec<u32> = rows.iter().map(|r| r.id).collect();
+    assert_eq!(ids, vec![2, 4, 6]);
+    assert_eq!(visited.get(), 6);
+
+    let handle = DatabaseHandle::in_memory();
+    handle.insert(1, "Alice".into(), 30)?;
+    assert_eq!(handle.scan(|rows| rows.len()), 1);
+
+    Ok(())
+}
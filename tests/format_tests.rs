@@ -0,0 +1,39 @@
+use mini_db::format::{format_rows, OutputFormat};
+use mini_db::model::Row;
+
+fn sample_rows() -> Vec<Row> {
+    vec![
+        Row::new(1, "Alice".into(), 30),
+        Row::new(12, "Bob".into(), 7),
+    ]
+}
+
+#[test]
+// Table output pads every column to its widest cell
+fn table_format_aligns_columns() {
+    let expected = concat!(
+        "id | name  | age\n",
+        "---+-------+----\n",
+        "1  | Alice | 30\n",
+        "12 | Bob   | 7",
+    );
+
+    assert_eq!(format_rows(&sample_rows(), OutputFormat::Table), expected);
+}
+
+#[test]
+// JSON output is a single array of row objects
+fn json_format_renders_array() {
+    let expected = r#"[{"age":30,"id":1,"name":"Alice"},{"age":7,"id":12,"name":"Bob"}]"#;
+
+    assert_eq!(format_rows(&sample_rows(), OutputFormat::Json), expected);
+}
+
+#[test]
+// Debug output matches the rows' Debug form, one per line
+fn debug_format_prints_one_row_per_line() {
+    let rows = sample_rows();
+    let expected = format!("{:?}\n{:?}", rows[0], rows[1]);
+
+    assert_eq!(format_rows(&rows, OutputFormat::Debug), expected);
+}
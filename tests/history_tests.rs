@@ -0,0 +1,21 @@
+use mini_db::history::History;
+
+#[test]
+// Pushing past capacity evicts the oldest commands while later ones keep their numbers
+fn history_wraps_around_at_capacity() {
+    let mut history = History::new(3);
+
+    for command in ["INSERT 1 A 10", "INSERT 2 B 20", "SELECT", "SELECT KEYS", "COMPACT"] {
+        history.push(command);
+    }
+
+    assert_eq!(history.len(), 3);
+    assert_eq!(history.get(1), None);
+    assert_eq!(history.get(2), None);
+    assert_eq!(history.get(3), Some("SELECT"));
+    assert_eq!(history.get(5), Some("COMPACT"));
+    assert_eq!(history.get(6), None);
+
+    let listed: Vec<(usize, &str)> = history.iter().collect();
+    assert_eq!(listed, vec![(3, "SELECT"), (4, "SELECT KEYS"), (5, "COMPACT")]);
+}
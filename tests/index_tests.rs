@@ -0,0 +1,35 @@
+use mini_db::errors::DbError;
+use mini_db::index::IdIndex;
+
+#[test]
+// An index keyed by u64 round-trips IDs beyond the u32 range and rejects duplicates
+fn u64_keys_round_trip() {
+    let mut index: IdIndex<u64> = IdIndex::new();
+    let big = u64::from(u32::MAX) + 1;
+
+    assert!(index.try_insert(big, 0));
+    assert!(index.try_insert(7, 1));
+    assert!(!index.try_insert(big, 2));
+
+    assert_eq!(index.get(big), Some(0));
+    assert_eq!(index.get(7), Some(1));
+    assert_eq!(index.get(8), None);
+    assert_eq!(index.len(), 2);
+
+    index.remove(big);
+    assert_eq!(index.get(big), None);
+    assert_eq!(index.iter().collect::<Vec<_>>(), vec![(7, 1)]);
+
+    index.clear();
+    assert!(index.is_empty());
+}
+
+#[test]
+// The default u32 index still reports duplicates as DuplicateIdError
+fn default_index_rejects_duplicate_ids() {
+    let mut index = IdIndex::default();
+
+    assert!(index.insert(1, 0).is_ok());
+    assert!(matches!(index.insert(1, 1), Err(DbError::DuplicateIdError(1))));
+    assert_eq!(index.get(1), Some(0));
+}
@@ -78,7 +78,7 @@ fn index_rebuild_correctness() -> Result<(), DbError> {
     // Verify every entry can be retrieved and matches expected state
     for &(id, expected_name, expected_age) in &test_data {
         let row = db.select_by_id(id)?
-            .expect(&format!("Row with id {} should exist after restart", id));
+            .unwrap_or_else(|| panic!("Row with id {} should exist after restart", id));
         
         assert_eq!(row.id, id, "ID mismatch for entry {}", id);
         assert_eq!(row.name, expected_name, "Name mismatch for entry {}", id);
@@ -160,7 +160,7 @@ fn index_update_on_insert() -> Result<(), DbError> {
     
     // Verify the indexed position points to the correct row data
     let row = db.select_by_id(TEST_ID)?
-        .expect(&format!("select_by_id should return Some for inserted row with id {}", TEST_ID));
+        .unwrap_or_else(|| panic!("select_by_id should return Some for inserted row with id {}", TEST_ID));
     
     assert_eq!(row.id, TEST_ID, "Retrieved row has incorrect ID");
     assert_eq!(row.name, TEST_NAME, "Retrieved row has incorrect name");
@@ -214,4 +214,75 @@ fn timing_insert_and_select() -> Result<(), DbError> {
     println!("===========================\n");
     
     Ok(())
-}
\ No newline at end of file
+}
+
+/// Performance check for inserts into a large table.
+///
+/// Times the same number of inserts into an empty table and into one that
+/// already holds 50,000 rows. With the duplicate check answered by the
+/// index, both take about as long; a linear scan would be many times slower.
+/// Note: Run with `--nocapture` flag to see output: `cargo test -- --nocapture`
+#[test]
+fn timing_insert_into_large_table() -> Result<(), DbError> {
+    use std::time::Instant;
+
+    const BATCH: u32 = 5_000;
+    const PREFILL: u32 = 50_000;
+
+    let mut db = Database::in_memory();
+
+    let empty_start = Instant::now();
+    for i in 1..=BATCH {
+        db.insert(i, format!("User{}", i), 30)?;
+    }
+    let empty_duration = empty_start.elapsed();
+
+    for i in BATCH + 1..=PREFILL {
+        db.insert(i, format!("User{}", i), 30)?;
+    }
+
+    let full_start = Instant::now();
+    for i in PREFILL + 1..=PREFILL + BATCH {
+        db.insert(i, format!("User{}", i), 30)?;
+    }
+    let full_duration = full_start.elapsed();
+
+    println!("\n=== Insert Scaling ===");
+    println!("{} inserts into empty table: {:?}", BATCH, empty_duration);
+    println!("{} inserts into {} rows: {:?}", BATCH, PREFILL, full_duration);
+    println!("======================\n");
+
+    // Loose bound, with a little slack for scheduler noise on a short run
+    assert!(
+        full_duration < empty_duration * 5 + std::time::Duration::from_millis(50),
+        "inserts into a large table took {:?}, vs {:?} into an empty one",
+        full_duration,
+        empty_duration,
+    );
+
+    Ok(())
+}
+
+/// Tests that duplicate IDs are still rejected after deletes and reinserts.
+///
+/// Deletes shift the positions of later rows, so this verifies the index used
+/// by the duplicate check stays in step with the rows.
+#[test]
+fn duplicate_check_survives_deletes_and_reinserts() -> Result<(), DbError> {
+    let mut db = Database::in_memory();
+    for i in 1..=5 {
+        db.insert(i, format!("User{}", i), 30)?;
+    }
+
+    db.delete_by_id(2)?;
+    db.delete_by_id(4)?;
+    assert!(matches!(db.insert(5, "Dup".into(), 30), Err(DbError::DuplicateIdError(5))));
+    assert!(matches!(db.insert(1, "Dup".into(), 30), Err(DbError::DuplicateIdError(1))));
+
+    db.insert(2, "Again".into(), 31)?;
+    assert!(matches!(db.insert(2, "Dup".into(), 30), Err(DbError::DuplicateIdError(2))));
+    db.insert(4, "Again".into(), 32)?;
+    assert_eq!(db.select_all().len(), 5);
+
+    Ok(())
+}
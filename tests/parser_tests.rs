@@ -1,9 +1,14 @@
-use mini_db::{errors::DbError, parser };
+use mini_db::{errors::DbError, parser, query::{AggregateFunc, CompareOp, Field, Predicate, Value} };
+use mini_db::format::OutputFormat;
+use mini_db::engine::DatabaseHandle;
+use mini_db::model::Row;
+use mini_db::parser::{CommandOutput, ReplState};
+use tempfile::tempdir;
 
 #[test]
 fn parse_insert_command_valid() -> Result<(), DbError> {
     let input = "insert 1 alice 20";
-    let cmd = parser::parse_command(&input)?;
+    let cmd = parser::parse_command(input)?;
 
     assert_eq!(cmd, parser::Command::Insert { id: 1, name: "alice".into(), age: 20 });
 
@@ -13,7 +18,7 @@ fn parse_insert_command_valid() -> Result<(), DbError> {
 #[test]
 fn parse_select_command_valid() -> Result<(), DbError> {
     let input = "select";
-    let cmd = parser::parse_command(&input)?;
+    let cmd = parser::parse_command(input)?;
 
     assert_eq!(cmd, parser::Command::Select);
 
@@ -23,7 +28,7 @@ fn parse_select_command_valid() -> Result<(), DbError> {
 #[test]
 fn parse_select_where_command_valid() -> Result<(), DbError> {
     let input = "select where id=5";
-    let cmd = parser::parse_command(&input)?;
+    let cmd = parser::parse_command(input)?;
 
     assert_eq!(cmd, parser::Command::SelectById { id:5 });
 
@@ -33,7 +38,7 @@ fn parse_select_where_command_valid() -> Result<(), DbError> {
 #[test]
 fn parse_delete_command_valid() -> Result<(), DbError> {
     let input = "delete where id=5";
-    let cmd = parser::parse_command(&input)?;
+    let cmd = parser::parse_command(input)?;
 
     assert_eq!(cmd, parser::Command::DeleteById { id:5 });
 
@@ -43,7 +48,7 @@ fn parse_delete_command_valid() -> Result<(), DbError> {
 #[test]
 fn parse_invalid_missing_id() -> Result<(), DbError> {
     let input = "select where";
-    let cmd = parser::parse_command(&input);
+    let cmd = parser::parse_command(input);
 
     assert!(matches!(cmd, Err(DbError::InvalidCommandError)));
 
@@ -53,9 +58,555 @@ fn parse_invalid_missing_id() -> Result<(), DbError> {
 #[test]
 fn parse_invalid_non_number_id() -> Result<(), DbError> {
     let input = "delete where id=abc";
-    let cmd = parser::parse_command(&input);
+    let cmd = parser::parse_command(input);
 
     assert!(matches!(cmd, Err(DbError::ParseError(_))));
 
     Ok(())
-}
\ No newline at end of file
+}
+
+#[test]
+fn parse_delete_where_age_command_valid() -> Result<(), DbError> {
+    let cmd = parser::parse_command("DELETE WHERE AGE<18")?;
+    assert_eq!(cmd, parser::Command::DeleteByAge { op: CompareOp::Lt, value: 18 });
+
+    let cmd = parser::parse_command("delete where age >= 65")?;
+    assert_eq!(cmd, parser::Command::DeleteByAge { op: CompareOp::Ge, value: 65 });
+
+    Ok(())
+}
+
+#[test]
+fn parse_select_keys_command_valid() -> Result<(), DbError> {
+    let cmd = parser::parse_command("SELECT KEYS")?;
+    assert_eq!(cmd, parser::Command::SelectKeys { filter: None });
+
+    let cmd = parser::parse_command("select keys where age>30")?;
+    assert_eq!(cmd, parser::Command::SelectKeys {
+        filter: Some(Predicate::Compare { field: Field::Age, op: CompareOp::Gt, value: Value::Number(30) }),
+    });
+
+    Ok(())
+}
+
+#[test]
+fn parse_select_where_and_command_valid() -> Result<(), DbError> {
+    let cmd = parser::parse_command("SELECT WHERE AGE>=30 AND NAME=Alice")?;
+
+    let expected = Predicate::And(
+        Box::new(Predicate::Compare { field: Field::Age, op: CompareOp::Ge, value: Value::Number(30) }),
+        Box::new(Predicate::Compare { field: Field::Name, op: CompareOp::Eq, value: Value::Text("Alice".into()) }),
+    );
+    assert_eq!(cmd, parser::Command::SelectWhere { predicate: expected });
+
+    Ok(())
+}
+
+#[test]
+fn parse_history_commands_valid() -> Result<(), DbError> {
+    let cmd = parser::parse_command("HISTORY")?;
+    assert_eq!(cmd, parser::Command::History);
+
+    let cmd = parser::parse_command("HISTORY REPLAY 2")?;
+    assert_eq!(cmd, parser::Command::HistoryReplay { n: 2 });
+
+    assert!(parser::parse_command("HISTORY REPLAY two").is_err());
+
+    Ok(())
+}
+
+#[test]
+fn parse_exec_batch_dry_run_command_valid() -> Result<(), DbError> {
+    let cmd = parser::parse_command("EXEC BATCH Seed.txt DRYRUN")?;
+    assert_eq!(cmd, parser::Command::ExecBatchDryRun { path: "Seed.txt".into() });
+
+    Ok(())
+}
+
+#[test]
+fn parse_upsert_command_valid() -> Result<(), DbError> {
+    let cmd = parser::parse_command("UPSERT 7 Alice 30")?;
+    assert_eq!(cmd, parser::Command::Upsert { id: 7, name: "Alice".into(), age: 30 });
+
+    assert!(parser::parse_command("UPSERT 7 Alice").is_err());
+
+    Ok(())
+}
+
+#[test]
+fn parse_update_command_valid() -> Result<(), DbError> {
+    let cmd = parser::parse_command("UPDATE WHERE ID=1 SET age=31")?;
+    assert_eq!(cmd, parser::Command::Update { id: 1, name: None, age: Some(31) });
+
+    let cmd = parser::parse_command("update where id=2 set NAME=Bob, AGE=40")?;
+    assert_eq!(cmd, parser::Command::Update { id: 2, name: Some("Bob".into()), age: Some(40) });
+
+    assert!(parser::parse_command("UPDATE WHERE ID=1 SET").is_err());
+    assert!(parser::parse_command("UPDATE WHERE ID=1 SET id=2").is_err());
+    assert!(parser::parse_command("UPDATE WHERE ID=1 SET age=31 age=32").is_err());
+
+    Ok(())
+}
+
+#[test]
+fn parse_select_fields_command_valid() -> Result<(), DbError> {
+    let cmd = parser::parse_command("SELECT name")?;
+    assert_eq!(cmd, parser::Command::SelectFields { fields: vec![Field::Name] });
+
+    let cmd = parser::parse_command("SELECT id, NAME")?;
+    assert_eq!(cmd, parser::Command::SelectFields { fields: vec![Field::Id, Field::Name] });
+
+    assert!(parser::parse_command("SELECT id, height").is_err());
+
+    Ok(())
+}
+
+#[test]
+fn parse_select_distinct_command_valid() -> Result<(), DbError> {
+    let cmd = parser::parse_command("SELECT DISTINCT AGE")?;
+    assert_eq!(cmd, parser::Command::SelectDistinct { field: Field::Age });
+
+    let cmd = parser::parse_command("select distinct name")?;
+    assert_eq!(cmd, parser::Command::SelectDistinct { field: Field::Name });
+
+    assert!(parser::parse_command("SELECT DISTINCT ID").is_err());
+
+    Ok(())
+}
+
+#[test]
+fn parse_aggregate_command_valid() -> Result<(), DbError> {
+    let cmd = parser::parse_command("SELECT AVG(AGE)")?;
+    assert_eq!(cmd, parser::Command::Aggregate { func: AggregateFunc::Avg, field: Field::Age });
+
+    let cmd = parser::parse_command("select min( age )")?;
+    assert_eq!(cmd, parser::Command::Aggregate { func: AggregateFunc::Min, field: Field::Age });
+
+    assert!(parser::parse_command("SELECT MAX(NAME)").is_err());
+    assert!(parser::parse_command("SELECT SUM(AGE)").is_err());
+
+    Ok(())
+}
+
+#[test]
+fn parse_flush_command_valid() -> Result<(), DbError> {
+    let cmd = parser::parse_command("FLUSH")?;
+    assert_eq!(cmd, parser::Command::Flush);
+
+    Ok(())
+}
+
+#[test]
+fn parse_compact_command_valid() -> Result<(), DbError> {
+    let cmd = parser::parse_command("COMPACT")?;
+    assert_eq!(cmd, parser::Command::Compact);
+
+    Ok(())
+}
+
+#[test]
+fn parse_format_command_valid() -> Result<(), DbError> {
+    let cmd = parser::parse_command("FORMAT TABLE")?;
+    assert_eq!(cmd, parser::Command::Format { format: OutputFormat::Table });
+
+    let cmd = parser::parse_command("format json")?;
+    assert_eq!(cmd, parser::Command::Format { format: OutputFormat::Json });
+
+    assert!(parser::parse_command("FORMAT XML").is_err());
+
+    Ok(())
+}
+
+#[test]
+fn parse_select_name_like_command_valid() -> Result<(), DbError> {
+    let cmd = parser::parse_command("SELECT WHERE NAME LIKE 'Al%'")?;
+    assert_eq!(cmd, parser::Command::SelectByNameLike { pattern: "Al%".into() });
+
+    let cmd = parser::parse_command("select where name like %ce")?;
+    assert_eq!(cmd, parser::Command::SelectByNameLike { pattern: "%ce".into() });
+
+    Ok(())
+}
+
+#[test]
+fn parse_stats_command_valid() -> Result<(), DbError> {
+    let cmd = parser::parse_command("STATS")?;
+    assert_eq!(cmd, parser::Command::Stats);
+
+    Ok(())
+}
+
+#[test]
+fn parse_describe_command_valid() -> Result<(), DbError> {
+    let cmd = parser::parse_command("describe")?;
+    assert_eq!(cmd, parser::Command::Describe);
+
+    Ok(())
+}
+
+#[test]
+fn parse_prompt_command_valid() -> Result<(), DbError> {
+    let cmd = parser::parse_command("PROMPT foo>")?;
+    assert_eq!(cmd, parser::Command::Prompt { prompt: "foo>".to_string() });
+
+    let cmd = parser::parse_command(r#"prompt "Db One> ""#)?;
+    assert_eq!(cmd, parser::Command::Prompt { prompt: "Db One> ".to_string() });
+
+    assert!(parser::parse_command("PROMPT").is_err());
+
+    Ok(())
+}
+
+#[test]
+fn parse_explain_command_valid() -> Result<(), DbError> {
+    let cmd = parser::parse_command("EXPLAIN SELECT WHERE ID=5")?;
+    let inner = parser::Command::SelectById { id: 5 };
+    assert_eq!(cmd, parser::Command::Explain { inner: Box::new(inner) });
+
+    assert!(parser::parse_command("EXPLAIN").is_err());
+    assert!(parser::parse_command("EXPLAIN SELECT WHERE").is_err());
+
+    Ok(())
+}
+
+#[test]
+fn parse_undo_command_valid() -> Result<(), DbError> {
+    let cmd = parser::parse_command("UNDO")?;
+    assert_eq!(cmd, parser::Command::Undo);
+
+    Ok(())
+}
+
+#[test]
+fn parse_select_ids_in_command_valid() -> Result<(), DbError> {
+    let cmd = parser::parse_command("SELECT WHERE ID IN (5, 9, 12)")?;
+    assert_eq!(cmd, parser::Command::SelectByIds { ids: vec![5, 9, 12] });
+
+    let cmd = parser::parse_command("select where id in (7)")?;
+    assert_eq!(cmd, parser::Command::SelectByIds { ids: vec![7] });
+
+    assert!(parser::parse_command("SELECT WHERE ID IN ()").is_err());
+    assert!(parser::parse_command("SELECT WHERE ID IN (1, x)").is_err());
+
+    Ok(())
+}
+
+#[test]
+fn parse_delete_ids_in_command_valid() -> Result<(), DbError> {
+    let cmd = parser::parse_command("DELETE WHERE ID IN (1, 2, 3)")?;
+    assert_eq!(cmd, parser::Command::DeleteByIds { ids: vec![1, 2, 3] });
+
+    Ok(())
+}
+
+#[test]
+fn parse_select_youngest_oldest_command_valid() -> Result<(), DbError> {
+    let cmd = parser::parse_command("SELECT YOUNGEST 5")?;
+    assert_eq!(cmd, parser::Command::SelectTopByAge { n: 5, ascending: true });
+
+    let cmd = parser::parse_command("select oldest 3")?;
+    assert_eq!(cmd, parser::Command::SelectTopByAge { n: 3, ascending: false });
+
+    let cmd = parser::parse_command("SELECT ORDER BY AGE DESC LIMIT 5")?;
+    assert_eq!(cmd, parser::Command::SelectTopByAge { n: 5, ascending: false });
+
+    assert!(parser::parse_command("SELECT OLDEST many").is_err());
+
+    Ok(())
+}
+
+#[test]
+fn parse_count_group_by_command_valid() -> Result<(), DbError> {
+    let cmd = parser::parse_command("SELECT COUNT GROUP BY AGE")?;
+    assert_eq!(cmd, parser::Command::CountGroupByAge);
+
+    let cmd = parser::parse_command("select count group by name")?;
+    assert_eq!(cmd, parser::Command::CountGroupByName);
+
+    assert!(parser::parse_command("SELECT COUNT GROUP BY ID").is_err());
+
+    Ok(())
+}
+
+#[test]
+fn parse_alias_command_valid() -> Result<(), DbError> {
+    let cmd = parser::parse_command(r#"ALIAS seed = "INSERT 1 Alice 30; INSERT 2 Bob 25""#)?;
+    assert_eq!(cmd, parser::Command::DefineAlias {
+        name: "seed".to_string(),
+        body: "INSERT 1 Alice 30; INSERT 2 Bob 25".to_string(),
+    });
+
+    // Aliases may not shadow commands or be empty
+    assert!(parser::parse_command(r#"ALIAS select = "STATS""#).is_err());
+    assert!(parser::parse_command(r#"ALIAS seed = """#).is_err());
+
+    Ok(())
+}
+
+#[test]
+// A defined alias expands to its statements and runs them; an undefined one is still an invalid command
+fn alias_expands_to_its_statements() -> Result<(), DbError> {
+    let db = DatabaseHandle::in_memory();
+    let mut state = ReplState::default();
+
+    assert!(parser::handle_shell_command(r#"ALIAS seed = "INSERT 1 Alice 30; INSERT 2 Bob 25""#, &db, &mut state));
+    assert_eq!(state.expand("seed"), vec!["INSERT 1 Alice 30", "INSERT 2 Bob 25"]);
+
+    assert!(parser::handle_shell_command("SEED", &db, &mut state));
+    assert_eq!(db.select_all_sorted(), vec![Row::new(1, "Alice".into(), 30), Row::new(2, "Bob".into(), 25)]);
+
+    assert_eq!(state.expand("reseed"), vec!["reseed"]);
+    assert!(matches!(parser::run_command("reseed", &db), CommandOutput::Error(DbError::InvalidCommandError)));
+
+    Ok(())
+}
+
+#[test]
+// Every statement of a multi-statement line runs in order, and a quoted `;` stays part of the name
+fn handle_command_runs_each_statement() -> Result<(), DbError> {
+    let db = DatabaseHandle::in_memory();
+
+    assert!(parser::handle_command("INSERT 1 A 20; INSERT 2 B 30;; DELETE WHERE ID=1", &db));
+    assert_eq!(db.select_all()?, vec![Row::new(2, "B".into(), 30)]);
+
+    assert!(parser::handle_command(r#"INSERT 3 "C;D" 40; UPDATE WHERE ID=2 SET AGE=31"#, &db));
+    assert_eq!(db.select_all_sorted(), vec![Row::new(2, "B".into(), 31), Row::new(3, "C;D".into(), 40)]);
+
+    // Statements after EXIT are not run
+    assert!(!parser::handle_command("EXIT; INSERT 4 E 50", &db));
+    assert_eq!(db.select_all()?.len(), 2);
+
+    Ok(())
+}
+
+#[test]
+fn parse_rename_command_valid() -> Result<(), DbError> {
+    let cmd = parser::parse_command("RENAME ID=5 TO Alice")?;
+    assert_eq!(cmd, parser::Command::Rename { id: 5, name: "Alice".to_string() });
+
+    let cmd = parser::parse_command(r#"rename id=5 to "Mary Ann""#)?;
+    assert_eq!(cmd, parser::Command::Rename { id: 5, name: "Mary Ann".to_string() });
+
+    assert!(parser::parse_command("RENAME ID=5 TO").is_err());
+
+    Ok(())
+}
+
+#[test]
+fn parse_rekey_command_valid() -> Result<(), DbError> {
+    let cmd = parser::parse_command("REKEY 5 TO 9")?;
+    assert_eq!(cmd, parser::Command::Rekey { old_id: 5, new_id: 9 });
+
+    assert!(parser::parse_command("REKEY 5 9").is_err());
+    assert!(parser::parse_command("REKEY 5 TO x").is_err());
+
+    Ok(())
+}
+
+#[test]
+fn parse_select_where_not_equal_command_valid() -> Result<(), DbError> {
+    let cmd = parser::parse_command("SELECT WHERE NOT AGE>=18 AND ID!=5")?;
+
+    let expected = Predicate::And(
+        Box::new(Predicate::Not(Box::new(
+            Predicate::Compare { field: Field::Age, op: CompareOp::Ge, value: Value::Number(18) },
+        ))),
+        Box::new(Predicate::Compare { field: Field::Id, op: CompareOp::Ne, value: Value::Number(5) }),
+    );
+    assert_eq!(cmd, parser::Command::SelectWhere { predicate: expected });
+
+    assert!(parser::parse_command("SELECT WHERE NOT").is_err());
+    assert!(parser::parse_command("SELECT WHERE NAME!=Bob").is_ok());
+
+    Ok(())
+}
+
+#[test]
+// != excludes one row, NOT inverts a condition, and NOT combines with AND
+fn select_where_not_and_not_equal_filter_rows() -> Result<(), DbError> {
+    let db = DatabaseHandle::in_memory();
+    assert!(parser::handle_command("INSERT 1 A 12; INSERT 5 B 40; INSERT 7 C 16; INSERT 9 D 30", &db));
+
+    let ids = |input: &str| match parser::run_command(input, &db) {
+        CommandOutput::Rows(rows) => rows.iter().map(|row| row.id).collect::<Vec<_>>(),
+        _ => panic!("expected rows for {input}"),
+    };
+
+    assert_eq!(ids("SELECT WHERE ID!=5"), vec![1, 7, 9]);
+    assert_eq!(ids("SELECT WHERE NOT AGE>=18"), vec![1, 7]);
+    assert_eq!(ids("SELECT WHERE NOT AGE>=18 AND NOT ID=7"), vec![1]);
+    assert_eq!(ids("SELECT WHERE NAME!=D AND NOT AGE<18"), vec![5]);
+
+    Ok(())
+}
+
+#[test]
+fn parse_exists_command_valid() -> Result<(), DbError> {
+    let cmd = parser::parse_command("EXISTS ID=5")?;
+    assert_eq!(cmd, parser::Command::Exists { id: 5 });
+
+    assert!(parser::parse_command("EXISTS ID=five").is_err());
+    assert!(parser::parse_command("EXISTS").is_err());
+
+    Ok(())
+}
+
+#[test]
+fn parse_select_where_created_command_valid() -> Result<(), DbError> {
+    let cmd = parser::parse_command("SELECT WHERE CREATED AFTER 100 AND updated before 200")?;
+
+    let expected = Predicate::And(
+        Box::new(Predicate::Compare { field: Field::CreatedAt, op: CompareOp::Ge, value: Value::Number(100) }),
+        Box::new(Predicate::Compare { field: Field::UpdatedAt, op: CompareOp::Le, value: Value::Number(200) }),
+    );
+    assert_eq!(cmd, parser::Command::SelectWhere { predicate: expected });
+
+    let cmd = parser::parse_command("SELECT WHERE CREATED_AT>100")?;
+    let expected = Predicate::Compare { field: Field::CreatedAt, op: CompareOp::Gt, value: Value::Number(100) };
+    assert_eq!(cmd, parser::Command::SelectWhere { predicate: expected });
+
+    assert!(parser::parse_command("SELECT WHERE CREATED AFTER yesterday").is_err());
+    assert!(parser::parse_command("SELECT WHERE CREATED DURING 100").is_err());
+
+    Ok(())
+}
+
+#[test]
+fn parse_insert_if_absent_command_valid() -> Result<(), DbError> {
+    let cmd = parser::parse_command("INSERT IF ABSENT 7 Alice 30")?;
+    assert_eq!(cmd, parser::Command::InsertIfAbsent { id: 7, name: "Alice".into(), age: 30 });
+
+    assert!(parser::parse_command("INSERT IF ABSENT 7 Alice").is_err());
+
+    Ok(())
+}
+
+#[test]
+fn parse_select_sample_command_valid() -> Result<(), DbError> {
+    let cmd = parser::parse_command("SELECT SAMPLE 5")?;
+    assert_eq!(cmd, parser::Command::Sample { n: 5 });
+
+    assert!(parser::parse_command("SELECT SAMPLE five").is_err());
+
+    Ok(())
+}
+
+#[test]
+fn parse_reset_commands_valid() -> Result<(), DbError> {
+    let cmd = parser::parse_command("RESET")?;
+    assert_eq!(cmd, parser::Command::Reset);
+
+    let cmd = parser::parse_command("RESET CONFIRM abc")?;
+    assert_eq!(cmd, parser::Command::ResetConfirm { token: "abc".into() });
+
+    assert!(parser::parse_command("RESET CONFIRM").is_err());
+    assert!(parser::parse_command("RESET everything").is_err());
+
+    Ok(())
+}
+
+#[test]
+// In the shell RESET only hands out a token; a bare second RESET or a wrong token clears nothing
+fn shell_reset_requires_confirmation_token() -> Result<(), DbError> {
+    let db = DatabaseHandle::in_memory();
+    let mut state = ReplState::default();
+    db.insert(1, "Alice".into(), 30)?;
+
+    assert!(parser::handle_shell_command("RESET", &db, &mut state));
+    assert!(parser::handle_shell_command("RESET", &db, &mut state));
+    assert_eq!(db.select_all()?.len(), 1);
+
+    let token = state.pending_reset.clone().expect("RESET leaves a pending token");
+    assert!(parser::handle_shell_command("RESET CONFIRM wrong", &db, &mut state));
+    assert_eq!(db.select_all()?.len(), 1);
+
+    assert!(parser::handle_shell_command(&format!("RESET CONFIRM {token}"), &db, &mut state));
+    assert!(db.select_all()?.is_empty());
+    assert_eq!(state.pending_reset, None);
+
+    Ok(())
+}
+
+#[test]
+// Replaying a recorded RESET from history only hands out a new token and clears nothing
+fn history_replay_of_reset_requires_confirmation() -> Result<(), DbError> {
+    let db = DatabaseHandle::in_memory();
+    let mut state = ReplState::default();
+
+    assert!(parser::handle_shell_command("INSERT 1 Alice 30", &db, &mut state));
+    assert!(parser::handle_shell_command("RESET", &db, &mut state));
+    state.pending_reset = None;
+
+    assert!(parser::handle_shell_command("HISTORY REPLAY 2", &db, &mut state));
+    assert_eq!(db.select_all()?.len(), 1);
+    assert!(state.pending_reset.is_some());
+
+    Ok(())
+}
+
+#[test]
+// Piped (non-interactive) input runs every line without writing a prompt; interactive mode prompts before each line
+fn run_repl_prompts_only_when_interactive() -> Result<(), DbError> {
+    let script = "INSERT 1 Alice 30\nINSERT 2 Bob 25\n";
+
+    let db = DatabaseHandle::in_memory();
+    let mut out = Vec::new();
+    parser::run_repl(script.as_bytes(), &mut out, &db, false)?;
+    assert!(!String::from_utf8(out).unwrap().contains("mini_db> "));
+    assert_eq!(db.select_all()?.len(), 2);
+
+    let db = DatabaseHandle::in_memory();
+    let mut out = Vec::new();
+    parser::run_repl(script.as_bytes(), &mut out, &db, true)?;
+    assert_eq!(String::from_utf8(out).unwrap().matches("mini_db> ").count(), 3);
+    assert_eq!(db.select_all()?.len(), 2);
+
+    Ok(())
+}
+
+#[test]
+// A scripted session through run_repl captures every response, errors included, in the writer
+fn run_repl_writes_session_output() -> Result<(), DbError> {
+    let dir = tempdir()?;
+    let batch_path = dir.path().join("batch.txt");
+    std::fs::write(&batch_path, "INSERT 3 Dave 50\nSELECT WHERE ID=3\nnonsense\n")?;
+
+    let script = format!(
+        "INSERT 1 Alice 30\nINSERT 1 Bob 25\nFORMAT JSON\nSELECT WHERE ID=1\nBOGUS\nEXEC BATCH {}\nEXIT\nINSERT 2 Carol 40\n",
+        batch_path.display(),
+    );
+
+    let db = DatabaseHandle::in_memory();
+    let mut out = Vec::new();
+    parser::run_repl(script.as_bytes(), &mut out, &db, false)?;
+    let out = String::from_utf8(out).unwrap();
+
+    assert!(out.starts_with("Inserted row with id 1.\nError: "));
+    assert!(out.contains("Output format set to Json.\n"));
+    assert!(out.contains(r#""name":"Alice""#));
+    // Batch queries and failing lines are written too, in the shell's format
+    assert!(out.contains(r#""name":"Dave""#));
+    assert!(out.contains("Error: line 3: "));
+    assert!(out.contains("Batch executed: 2 command(s) succeeded, 1 failed.\n"));
+    assert!(out.ends_with("Exiting mini_db... Goodbye!\n"));
+    // Nothing after EXIT runs
+    assert!(!db.contains_id(2));
+
+    Ok(())
+}
+
+#[test]
+// PROMPT changes the prompt written before every following line
+fn run_repl_uses_prompt_set_with_prompt_command() -> Result<(), DbError> {
+    let db = DatabaseHandle::in_memory();
+    let mut out = Vec::new();
+    parser::run_repl("PROMPT foo>\nINSERT 1 Alice 30\n".as_bytes(), &mut out, &db, true)?;
+    let out = String::from_utf8(out).unwrap();
+
+    assert!(out.starts_with(parser::DEFAULT_PROMPT));
+    assert_eq!(out.matches("foo>").count(), 3);
+    assert!(out.contains("foo>Inserted row with id 1."));
+    assert_eq!(out.matches(parser::DEFAULT_PROMPT).count(), 1);
+
+    Ok(())
+}
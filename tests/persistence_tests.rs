@@ -1,6 +1,11 @@
-use mini_db::engine::Database;
+use mini_db::engine::{Database, DatabaseHandle, ImportReport, MergePolicy, MergeReport};
 use mini_db::errors::DbError;
+use mini_db::parser::{self, CommandOutput};
 use mini_db::model::Row;
+use mini_db::storage::{self, FileHeader, LogEntry, LogSink, LogWarning, RepairReport, ReplayOrder, ReplayPolicy, Storage, StorageBackend, FORMAT_VERSION};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::fs;
 use tempfile::tempdir;
 
 #[test]
@@ -28,9 +33,9 @@ fn persistence_across_restart() -> Result<(), DbError> {
     let mut actual = rows.clone();
 
     let mut expected = vec![
-        Row {id: 1, name: "Alice".into(), age: 20},
-        Row {id: 2, name: "Bob".into(), age: 30},
-        Row {id: 3, name: "John".into(), age: 40}
+        Row::new(1, "Alice".into(), 20),
+        Row::new(2, "Bob".into(), 30),
+        Row::new(3, "John".into(), 40)
     ];
 
     actual.sort_by_key(|row| row.id );
@@ -82,8 +87,1653 @@ fn reinsert_after_delete_persists() -> Result<(), DbError> {
     
     let db = Database::new(&file_path)?;
     
-    assert!(matches!(db.select_by_id(1)?, None));
-    assert_eq!(db.select_by_id(2)?, Some(Row {id: 2, name: "Bob".into(), age: 30}));
+    assert!(db.select_by_id(1)?.is_none());
+    assert_eq!(db.select_by_id(2)?, Some(Row::new(2, "Bob".into(), 30)));
+
+    Ok(())
+}
+
+#[test]
+// Two inserts for the same id are written with timestamps that contradict their file order
+fn replay_order_resolves_conflicting_inserts() -> Result<(), DbError> {
+    let dir = tempdir()?;
+
+    let path = "temp_data.json";
+    let file_path = dir.path().join(path);
+
+    let entries = [
+        LogEntry::Insert { row: Row::new(1, "Newer".into(), 31), timestamp: 200 },
+        LogEntry::Insert { row: Row::new(1, "Older".into(), 30), timestamp: 100 },
+    ];
+    let lines: Vec<String> = entries.iter()
+        .map(|entry| serde_json::to_string(entry).unwrap())
+        .collect();
+    fs::write(&file_path, lines.join("\n") + "\n")?;
+
+    // Default: last write in file order wins
+    let db = Database::new(&file_path)?;
+    assert_eq!(db.select_all().len(), 1);
+    assert_eq!(db.select_by_id(1)?.map(|row| row.name), Some("Older".to_string()));
+    drop(db);
+
+    // Timestamp order: the latest write by time wins
+    let mut storage = Storage::new(&file_path)?;
+    storage.replay_order = ReplayOrder::Timestamp;
+    let db = Database::from_storage(storage)?;
+    assert_eq!(db.select_all().len(), 1);
+    assert_eq!(db.select_by_id(1)?.map(|row| row.name), Some("Newer".to_string()));
+
+    Ok(())
+}
+
+#[test]
+// Rolls the log into several segments, compresses the older ones and replays across all of them
+fn replay_across_compressed_and_plain_segments() -> Result<(), DbError> {
+    let dir = tempdir()?;
+
+    let path = "temp_data.json";
+    let file_path = dir.path().join(path);
+
+    {
+        let mut storage = Storage::new(&file_path)?;
+
+        storage.append_entry(&Row::new(1, "Alice".into(), 20))?;
+        storage.append_entry(&Row::new(2, "Bob".into(), 30))?;
+        storage.roll_segment()?;
+
+        storage.append_delete(1)?;
+        storage.append_entry(&Row::new(3, "John".into(), 40))?;
+        storage.roll_segment()?;
+
+        // Both rolled segments are compressed, then a third one stays plain
+        assert_eq!(storage.compress_segments()?, 2);
+
+        storage.append_entry(&Row::new(4, "Jane".into(), 50))?;
+        storage.compress_rolled = true;
+        storage.roll_segment()?;
+        storage.compress_rolled = false;
+        storage.append_entry(&Row::new(5, "Jim".into(), 60))?;
+        storage.roll_segment()?;
+
+        storage.append_entry(&Row::new(6, "Joe".into(), 70))?;
+        storage.flush()?;
+
+        let compressed = storage.segment_paths().iter()
+            .filter(|segment| segment.extension().is_some_and(|ext| ext == "gz"))
+            .count();
+        assert_eq!(storage.segment_paths().len(), 4);
+        assert_eq!(compressed, 3);
+    }
+
+    let db = Database::new(&file_path)?;
+    let mut ids: Vec<u32> = db.select_all().iter().map(|row| row.id).collect();
+    ids.sort();
+
+    assert_eq!(ids, vec![2, 3, 4, 5, 6]);
+
+    Ok(())
+}
+
+#[test]
+// Rows inserted before the retention window are purged, recent ones survive a restart
+fn enforce_retention_purges_old_rows() -> Result<(), DbError> {
+    let dir = tempdir()?;
+
+    let path = "temp_data.json";
+    let file_path = dir.path().join(path);
+
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64;
+    let entries = [
+        LogEntry::Insert { row: Row::new(1, "Old".into(), 20), timestamp: now - 10_000 },
+        LogEntry::Insert { row: Row::new(2, "New".into(), 30), timestamp: now },
+        LogEntry::Insert { row: Row::new(3, "Older".into(), 40), timestamp: now - 20_000 },
+    ];
+    let lines: Vec<String> = entries.iter()
+        .map(|entry| serde_json::to_string(entry).unwrap())
+        .collect();
+    fs::write(&file_path, lines.join("\n") + "\n")?;
+
+    {
+        let mut db = Database::new(&file_path)?;
+
+        assert_eq!(db.enforce_retention(3_600)?, 2);
+        assert_eq!(db.select_all().len(), 1);
+        assert!(db.select_by_id(2)?.is_some());
+    }
+
+    let db = Database::new(&file_path)?;
+    let ids: Vec<u32> = db.select_all().iter().map(|row| row.id).collect();
+    assert_eq!(ids, vec![2]);
+
+    Ok(())
+}
+
+#[test]
+// Mirrors the Ctrl-C / Ctrl-D path of the REPL: shutdown, drop, then reopen
+fn shutdown_then_reopen_keeps_committed_rows() -> Result<(), DbError> {
+    let dir = tempdir()?;
+
+    let path = "temp_data.json";
+    let file_path = dir.path().join(path);
+
+    {
+        let db = DatabaseHandle::new(&file_path)?;
+
+        db.insert(1, "Alice".into(), 20)?;
+        db.insert(2, "Bob".into(), 30)?;
+        db.delete_by_id(1)?;
+        db.shutdown()?;
+    }
+
+    let db = DatabaseHandle::new(&file_path)?;
+
+    assert_eq!(db.select_all()?, vec![Row::new(2, "Bob".into(), 30)]);
+
+    Ok(())
+}
+
+#[test]
+// Inserted rows carry timestamps; an update bumps updated_at but keeps created_at, also after restart
+fn update_bumps_updated_at_and_keeps_created_at() -> Result<(), DbError> {
+    let dir = tempdir()?;
+
+    let path = "temp_data.json";
+    let file_path = dir.path().join(path);
+
+    let created_at;
+    {
+        let mut db = Database::new(&file_path)?;
+
+        db.insert(1, "Alice".into(), 20)?;
+        let row = db.select_by_id_strict(1)?;
+        assert!(row.created_at > 0);
+        assert_eq!(row.created_at, row.updated_at);
+        created_at = row.created_at;
+
+        // Timestamps have second resolution
+        std::thread::sleep(std::time::Duration::from_millis(1_100));
+
+        assert!(db.update(1, "Alicia".into(), 21)?);
+        let row = db.select_by_id_strict(1)?;
+        assert_eq!(row.created_at, created_at);
+        assert!(row.updated_at > created_at);
+    }
+
+    let db = Database::new(&file_path)?;
+    let row = db.select_by_id_strict(1)?;
+
+    assert_eq!(row, Row::new(1, "Alicia".into(), 21));
+    assert_eq!(row.created_at, created_at);
+    assert!(row.updated_at > created_at);
+
+    Ok(())
+}
+
+#[test]
+// The 3rd of 5 log lines is cut off mid-way; replay skips it and keeps going instead of stopping early
+fn torn_middle_line_does_not_abort_replay() -> Result<(), DbError> {
+    let dir = tempdir()?;
+
+    let path = "temp_data.json";
+    let file_path = dir.path().join(path);
+
+    let mut lines: Vec<String> = (1..=5)
+        .map(|id| {
+            let entry = LogEntry::Insert { row: Row::new(id, format!("User{id}"), 20), timestamp: 100 };
+            serde_json::to_string(&entry).unwrap()
+        })
+        .collect();
+    let torn = &lines[2];
+    lines[2] = torn[..torn.len() / 2].to_string();
+    fs::write(&file_path, lines.join("\n") + "\n")?;
+
+    let db = Database::new(&file_path)?;
+    let ids: Vec<u32> = db.select_all().iter().map(|row| row.id).collect();
+
+    assert_eq!(ids, vec![1, 2, 4, 5]);
+
+    Ok(())
+}
+
+#[test]
+// With snapshot_on_shutdown the log is folded into the snapshot, and reopening loads from it alone
+fn snapshot_on_shutdown_truncates_log() -> Result<(), DbError> {
+    let dir = tempdir()?;
+
+    let path = "temp_data.json";
+    let file_path = dir.path().join(path);
+
+    {
+        let mut db = Database::new(&file_path)?;
+        db.set_snapshot_on_shutdown(true);
+
+        db.insert(1, "Alice".into(), 20)?;
+        db.insert(2, "Bob".into(), 30)?;
+        db.insert(3, "Carol".into(), 40)?;
+        db.delete_by_id(2)?;
+        db.shutdown()?;
+    }
+
+    assert_eq!(fs::metadata(&file_path)?.len(), 0);
+    assert!(dir.path().join("temp_data.snapshot").exists());
+
+    let db = Database::new(&file_path)?;
+
+    assert_eq!(db.select_all(), &vec![Row::new(1, "Alice".into(), 20), Row::new(3, "Carol".into(), 40)]);
+
+    Ok(())
+}
+
+#[test]
+// Files written by this build start with the current header and reopen cleanly
+fn current_version_files_open_cleanly() -> Result<(), DbError> {
+    let dir = tempdir()?;
+
+    let path = "temp_data.json";
+    let file_path = dir.path().join(path);
+
+    {
+        let mut db = Database::new(&file_path)?;
+        db.insert(1, "Alice".into(), 20)?;
+        db.shutdown()?;
+    }
+
+    assert_eq!(Storage::read_header(&file_path)?, Some(FileHeader::current()));
+
+    let mut db = Database::new(&file_path)?;
+    assert_eq!(db.ids(), vec![1]);
+
+    // The snapshot carries the version too
+    db.compact()?;
+    drop(db);
+    let snapshot = fs::read_to_string(dir.path().join("temp_data.snapshot"))?;
+    assert!(snapshot.contains(&format!("\"version\":{FORMAT_VERSION}")));
+
+    let db = Database::new(&file_path)?;
+    assert_eq!(db.ids(), vec![1]);
+
+    Ok(())
+}
+
+#[test]
+// A log or snapshot from a newer format version is refused instead of misread
+fn newer_version_files_are_rejected() -> Result<(), DbError> {
+    let dir = tempdir()?;
+
+    let path = "temp_data.json";
+    let file_path = dir.path().join(path);
+
+    let bumped = FileHeader { version: FORMAT_VERSION + 1, ..FileHeader::current() };
+    let header = serde_json::to_string(&bumped)?;
+    let entry = serde_json::to_string(&LogEntry::Insert { row: Row::new(1, "Alice".into(), 20), timestamp: 100 })?;
+    fs::write(&file_path, format!("{header}\n{entry}\n"))?;
+
+    match Database::new(&file_path) {
+        Err(DbError::UnsupportedVersion { found, expected }) => {
+            assert_eq!(found, FORMAT_VERSION + 1);
+            assert_eq!(expected, FORMAT_VERSION);
+        },
+        other => panic!("expected UnsupportedVersion, got {:?}", other.map(|db| db.ids())),
+    }
+
+    // Same for the snapshot, with an otherwise valid log
+    fs::write(&file_path, "")?;
+    fs::write(
+        dir.path().join("temp_data.snapshot"),
+        format!(r#"{{"magic":"mini_db","version":{},"rows":[]}}"#, FORMAT_VERSION + 1),
+    )?;
+
+    assert!(matches!(Database::new(&file_path), Err(DbError::UnsupportedVersion { .. })));
+
+    Ok(())
+}
+
+#[test]
+// A hand-written version 0 log is upgraded in place, keeping a backup; migrating again changes nothing
+fn migrate_upgrades_version_zero_log() -> Result<(), DbError> {
+    let dir = tempdir()?;
+
+    let path = "temp_data.json";
+    let file_path = dir.path().join(path);
+
+    let original = concat!(
+        r#"{"Insert":{"row":{"id":1,"name":"Alice","age":20},"timestamp":100}}"#, "\n",
+        r#"{"Insert":{"row":{"id":2,"name":"Bob","age":30},"timestamp":200}}"#, "\n",
+        r#"{"Delete":{"id":1}}"#, "\n",
+        r#"{"Insert":{"row":{"id":3,"name":"Carol","age":40},"timestamp":300}}"#, "\n",
+    );
+    fs::write(&file_path, original)?;
+    assert_eq!(Storage::read_header(&file_path)?, None);
+
+    Database::migrate(&file_path)?;
+
+    let backup_path = dir.path().join("temp_data.json.bak");
+    assert_eq!(fs::read_to_string(&backup_path)?, original);
+    assert_eq!(Storage::read_header(&file_path)?, Some(FileHeader::current()));
+
+    let migrated = fs::read_to_string(&file_path)?;
+    assert!(migrated.contains(r#""created_at":300"#));
+    Database::migrate(&file_path)?;
+    assert_eq!(fs::read_to_string(&file_path)?, migrated);
+    assert_eq!(fs::read_to_string(&backup_path)?, original);
+
+    let db = Database::new(&file_path)?;
+    assert_eq!(db.select_all(), &vec![Row::new(2, "Bob".into(), 30), Row::new(3, "Carol".into(), 40)]);
+    assert_eq!(db.select_by_id_strict(3)?.created_at, 300);
+
+    Ok(())
+}
+
+#[test]
+// Vacuum leaves one log entry per surviving row, and the rewritten log replays to the same rows
+fn vacuum_keeps_one_entry_per_surviving_row() -> Result<(), DbError> {
+    let dir = tempdir()?;
+
+    let path = "temp_data.json";
+    let file_path = dir.path().join(path);
+
+    let before;
+    {
+        let mut db = Database::new(&file_path)?;
+
+        db.insert(1, "Alice".into(), 20)?;
+        db.insert(2, "Bob".into(), 30)?;
+        db.insert(3, "Carol".into(), 40)?;
+        db.update(2, "Robert".into(), 31)?;
+        db.update(2, "Bobby".into(), 32)?;
+        db.delete_by_id(1)?;
+
+        assert_eq!(db.vacuum()?, 4);
+        before = db.select_all().clone();
+        db.shutdown()?;
+    }
+
+    let log = fs::read_to_string(&file_path)?;
+    let entries: Vec<LogEntry> = log.lines()
+        .skip(1)
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    let ids: Vec<u32> = entries.iter().map(LogEntry::id).collect();
+    assert_eq!(ids, vec![2, 3]);
+
+    let db = Database::new(&file_path)?;
+    assert_eq!(db.select_all(), &before);
+    assert_eq!(db.select_by_id_strict(2)?.name, "Bobby");
+
+    Ok(())
+}
+
+#[test]
+// Patching one field leaves the other untouched, also after a restart
+fn patch_by_id_preserves_untouched_fields() -> Result<(), DbError> {
+    let dir = tempdir()?;
+
+    let path = "temp_data.json";
+    let file_path = dir.path().join(path);
+
+    {
+        let mut db = Database::new(&file_path)?;
+        db.insert(1, "Alice".into(), 20)?;
+        db.insert(2, "Bob".into(), 30)?;
+        db.insert(3, "Carol".into(), 40)?;
+
+        assert!(db.patch_by_id(1, None, Some(21))?);
+        assert!(db.patch_by_id(2, Some("Robert".into()), None)?);
+        assert!(db.patch_by_id(3, Some("Caroline".into()), Some(41))?);
+        assert!(!db.patch_by_id(4, None, Some(50))?);
+    }
+
+    let db = Database::new(&file_path)?;
+    assert_eq!(db.select_all(), &vec![
+        Row::new(1, "Alice".into(), 21),
+        Row::new(2, "Robert".into(), 30),
+        Row::new(3, "Caroline".into(), 41),
+    ]);
+
+    Ok(())
+}
+
+#[test]
+// After compact + reset, a restart must not resurrect rows from the old snapshot
+fn reset_after_compact_stays_empty() -> Result<(), DbError> {
+    let dir = tempdir()?;
+
+    let path = "temp_data.json";
+    let file_path = dir.path().join(path);
+
+    {
+        let mut db = Database::new(&file_path)?;
+        db.insert(1, "Alice".into(), 20)?;
+        db.insert(2, "Bob".into(), 30)?;
+        db.compact()?;
+        assert!(dir.path().join("temp_data.snapshot").exists());
+
+        db.reset_db()?;
+        assert!(!dir.path().join("temp_data.snapshot").exists());
+    }
+
+    let db = Database::new(&file_path)?;
+    assert_eq!(db.select_all().len(), 0);
+
+    Ok(())
+}
+
+#[test]
+// A row deleted just before compaction must not come back from the snapshot
+fn delete_before_compact_stays_deleted() -> Result<(), DbError> {
+    let dir = tempdir()?;
+
+    let path = "temp_data.json";
+    let file_path = dir.path().join(path);
+
+    {
+        let mut db = Database::new(&file_path)?;
+        db.insert(1, "Alice".into(), 20)?;
+        db.insert(2, "Bob".into(), 30)?;
+        db.insert(3, "Carol".into(), 40)?;
+        db.delete_by_id(2)?;
+        db.compact()?;
+    }
+
+    let db = Database::new(&file_path)?;
+    assert!(db.select_by_id(2)?.is_none());
+    assert_eq!(db.select_all(), &vec![Row::new(1, "Alice".into(), 20), Row::new(3, "Carol".into(), 40)]);
+
+    Ok(())
+}
+
+/// Inserts Alice, Bob and Carol and compacts them into a snapshot.
+fn compacted_db(file_path: &std::path::Path) -> Result<Database, DbError> {
+    let mut db = Database::new(file_path)?;
+    db.insert(1, "Alice".into(), 20)?;
+    db.insert(2, "Bob".into(), 30)?;
+    db.insert(3, "Carol".into(), 40)?;
+    db.compact()?;
+    Ok(db)
+}
+
+#[test]
+// A delete logged after the snapshot removes the snapshot's row on reload
+fn delete_after_snapshot_removes_row() -> Result<(), DbError> {
+    let dir = tempdir()?;
+
+    let path = "temp_data.json";
+    let file_path = dir.path().join(path);
+
+    {
+        let mut db = compacted_db(&file_path)?;
+        db.delete_by_id(2)?;
+    }
+
+    let db = Database::new(&file_path)?;
+    assert_eq!(db.ids(), vec![1, 3]);
+
+    Ok(())
+}
+
+#[test]
+// Deleting and re-inserting a snapshot row after the snapshot leaves a single copy
+fn reinsert_after_snapshot_does_not_duplicate() -> Result<(), DbError> {
+    let dir = tempdir()?;
+
+    let path = "temp_data.json";
+    let file_path = dir.path().join(path);
+
+    {
+        let mut db = compacted_db(&file_path)?;
+        db.delete_by_id(2)?;
+        db.insert(2, "Bobby".into(), 31)?;
+    }
+
+    let db = Database::new(&file_path)?;
+    assert_eq!(db.select_all().len(), 3);
+    assert_eq!(db.select_by_id_strict(2)?, Row::new(2, "Bobby".into(), 31));
+
+    Ok(())
+}
+
+#[test]
+// An update logged after the snapshot replaces the snapshot's values on reload
+fn update_after_snapshot_reflects_new_values() -> Result<(), DbError> {
+    let dir = tempdir()?;
+
+    let path = "temp_data.json";
+    let file_path = dir.path().join(path);
+
+    {
+        let mut db = compacted_db(&file_path)?;
+        db.update(3, "Caroline".into(), 41)?;
+    }
+
+    let db = Database::new(&file_path)?;
+    assert_eq!(db.select_all(), &vec![
+        Row::new(1, "Alice".into(), 20),
+        Row::new(2, "Bob".into(), 30),
+        Row::new(3, "Caroline".into(), 41),
+    ]);
+
+    Ok(())
+}
+
+/// A trivial backend keeping its log and snapshot in shared memory.
+#[derive(Clone, Default)]
+struct MemoryBackend {
+    log: Arc<Mutex<Vec<LogEntry>>>,
+    snapshot: Arc<Mutex<Option<Vec<Row>>>>,
+}
+
+impl StorageBackend for MemoryBackend {
+    fn append_entry(&mut self, row: &Row) -> Result<(), DbError> {
+        self.log.lock().unwrap().push(LogEntry::Insert { row: row.clone(), timestamp: row.created_at });
+        Ok(())
+    }
+
+    fn append_update(&mut self, row: &Row) -> Result<(), DbError> {
+        self.log.lock().unwrap().push(LogEntry::Update { row: row.clone(), timestamp: row.updated_at });
+        Ok(())
+    }
+
+    fn append_delete(&mut self, id: u32) -> Result<(), DbError> {
+        self.log.lock().unwrap().push(LogEntry::Delete { id, timestamp: 0 });
+        Ok(())
+    }
+
+    fn load_onto(&self, base: Vec<Row>) -> Result<Vec<Row>, DbError> {
+        let log = self.log.lock().unwrap();
+        Ok(storage::replay(base, log.iter().cloned()))
+    }
+
+    fn flush(&mut self) -> Result<(), DbError> {
+        Ok(())
+    }
+
+    fn snapshot_write(&mut self, rows: &[Row]) -> Result<(), DbError> {
+        *self.snapshot.lock().unwrap() = Some(rows.to_vec());
+        Ok(())
+    }
+
+    fn snapshot_read(&self) -> Result<Option<Vec<Row>>, DbError> {
+        Ok(self.snapshot.lock().unwrap().clone())
+    }
+
+    fn log_truncate(&mut self) -> Result<(), DbError> {
+        self.log.lock().unwrap().clear();
+        Ok(())
+    }
+}
+
+#[test]
+// The engine runs its full lifecycle, restarts and compaction included, on a custom backend
+fn custom_backend_runs_full_lifecycle() -> Result<(), DbError> {
+    let backend = MemoryBackend::default();
+
+    {
+        let mut db = Database::from_backend(Box::new(backend.clone()))?;
+        db.insert(1, "Alice".into(), 20)?;
+        db.insert(2, "Bob".into(), 30)?;
+        db.insert(3, "Carol".into(), 40)?;
+        db.delete_by_id(2)?;
+        assert_eq!(db.select_by_id(1)?, Some(Row::new(1, "Alice".into(), 20)));
+        db.shutdown()?;
+    }
+    assert_eq!(backend.log.lock().unwrap().len(), 4);
+
+    {
+        let mut db = Database::from_backend(Box::new(backend.clone()))?;
+        assert_eq!(db.ids(), vec![1, 3]);
+
+        db.compact()?;
+        db.update(3, "Caroline".into(), 41)?;
+    }
+    assert_eq!(backend.log.lock().unwrap().len(), 1);
+
+    let db = Database::from_backend(Box::new(backend.clone()))?;
+    assert_eq!(db.select_all(), &vec![Row::new(1, "Alice".into(), 20), Row::new(3, "Caroline".into(), 41)]);
+
+    Ok(())
+}
+
+#[test]
+// Deletes are logged with a timestamp, and deletes from before they had one still replay
+fn delete_entries_carry_timestamps() -> Result<(), DbError> {
+    let dir = tempdir()?;
+
+    let path = "temp_data.json";
+    let file_path = dir.path().join(path);
+
+    let mut db = Database::new(&file_path)?;
+    db.insert(1, "Alice".into(), 30)?;
+    db.delete_by_id(1)?;
+    db.shutdown()?;
+    drop(db);
+
+    let log = fs::read_to_string(&file_path)?;
+    let last = log.lines().last().unwrap();
+    match serde_json::from_str::<LogEntry>(last)? {
+        LogEntry::Delete { id, timestamp } => {
+            assert_eq!(id, 1);
+            assert!(timestamp > 0);
+        },
+        other => panic!("expected a delete entry, got {:?}", other),
+    }
+
+    // An old-format delete line without a timestamp
+    let insert = serde_json::to_string(&LogEntry::Insert { row: Row::new(2, "Bob".into(), 40), timestamp: 100 })?;
+    let keep = serde_json::to_string(&LogEntry::Insert { row: Row::new(3, "Carol".into(), 50), timestamp: 100 })?;
+    fs::write(&file_path, format!("{insert}\n{keep}\n{{\"Delete\":{{\"id\":2}}}}\n"))?;
+
+    let db = Database::new(&file_path)?;
+    assert_eq!(db.select_by_id(2)?, None);
+    assert_eq!(db.select_all().len(), 1);
+
+    Ok(())
+}
+
+#[test]
+// Replaying up to a cutoff between two inserts only yields the earlier rows
+fn snapshot_at_replays_entries_up_to_cutoff() -> Result<(), DbError> {
+    let dir = tempdir()?;
+
+    let path = "temp_data.json";
+    let file_path = dir.path().join(path);
+
+    let entries = [
+        LogEntry::Insert { row: Row::new(1, "Alice".into(), 30), timestamp: 100 },
+        LogEntry::Insert { row: Row::new(2, "Bob".into(), 40), timestamp: 200 },
+        LogEntry::Delete { id: 1, timestamp: 300 },
+        LogEntry::Insert { row: Row::new(3, "Carol".into(), 50), timestamp: 400 },
+    ];
+    let lines: Vec<String> = entries.iter()
+        .map(|entry| serde_json::to_string(entry).unwrap())
+        .collect();
+    fs::write(&file_path, lines.join("\n") + "\n")?;
+
+    let storage = Storage::new(&file_path)?;
+    let rows = storage.load_until(150)?;
+    assert_eq!(rows.iter().map(|row| row.id).collect::<Vec<_>>(), vec![1]);
+    drop(storage);
+
+    let db = Database::new(&file_path)?;
+    let view = db.snapshot_at(250)?;
+    assert_eq!(view.select_all_sorted().iter().map(|row| row.id).collect::<Vec<_>>(), vec![1, 2]);
+
+    let view = db.snapshot_at(350)?;
+    assert_eq!(view.select_all_sorted().iter().map(|row| row.id).collect::<Vec<_>>(), vec![2]);
+
+    // The live database is unaffected
+    assert_eq!(db.select_all_sorted().iter().map(|row| row.id).collect::<Vec<_>>(), vec![2, 3]);
+
+    Ok(())
+}
+
+#[test]
+// A tiny maximum log size forces several rotations; replay across the segments restores everything
+fn rotation_rolls_log_and_replays_all_segments() -> Result<(), DbError> {
+    let dir = tempdir()?;
+
+    let path = "temp_data.json";
+    let file_path = dir.path().join(path);
+
+    let mut storage = Storage::new(&file_path)?;
+    storage.max_log_bytes = Some(200);
+    let mut db = Database::from_storage(storage)?;
+
+    for id in 1..=10 {
+        db.insert(id, format!("User{id}"), 20 + id as u8)?;
+    }
+    db.delete_by_id(3)?;
+    db.update(5, "Renamed".into(), 50)?;
+    db.shutdown()?;
+    drop(db);
+
+    let storage = Storage::new(&file_path)?;
+    assert!(storage.segment_paths().len() >= 3);
+    assert!(fs::metadata(&file_path)?.len() <= 200);
+    drop(storage);
+
+    let db = Database::new(&file_path)?;
+    let ids: Vec<u32> = db.select_all_sorted().iter().map(|row| row.id).collect();
+    assert_eq!(ids, vec![1, 2, 4, 5, 6, 7, 8, 9, 10]);
+    assert_eq!(db.select_by_id(5)?.map(|row| row.name), Some("Renamed".to_string()));
+
+    Ok(())
+}
+
+#[test]
+// Extra fields survive a restart from the log and from a snapshot
+fn extra_fields_survive_restart() -> Result<(), DbError> {
+    let dir = tempdir()?;
+
+    let path = "temp_data.json";
+    let file_path = dir.path().join(path);
+
+    let mut row = Row::new(1, "Alice".into(), 30);
+    row.set_extra("city", "Paris".into());
+    row.set_extra("score", 42.into());
+
+    {
+        let mut db = Database::new(&file_path)?;
+        db.insert_row(row.clone())?;
+        db.insert(2, "Bob".into(), 40)?;
+        db.update(1, "Alicia".into(), 31)?;
+        db.shutdown()?;
+    }
+
+    let mut db = Database::new(&file_path)?;
+    let loaded = db.select_by_id(1)?.unwrap();
+    assert_eq!(loaded.name, "Alicia");
+    assert_eq!(loaded.get_extra("city"), Some(&"Paris".into()));
+    assert_eq!(loaded.get_extra("score"), Some(&42.into()));
+    assert!(db.select_by_id(2)?.unwrap().extra.is_empty());
+
+    db.compact()?;
+    drop(db);
+
+    let db = Database::new(&file_path)?;
+    assert_eq!(db.select_by_id(1)?.unwrap().get_extra("city"), Some(&"Paris".into()));
+
+    Ok(())
+}
+
+#[test]
+// Repair drops two corrupt lines, keeps the snapshot's rows and the valid entries, and leaves a clean log
+fn repair_rebuilds_clean_files_from_corrupt_log() -> Result<(), DbError> {
+    let dir = tempdir()?;
+
+    let path = "temp_data.json";
+    let file_path = dir.path().join(path);
+
+    {
+        let mut db = Database::new(&file_path)?;
+        db.insert(1, "Alice".into(), 20)?;
+        db.compact()?;
+        db.shutdown()?;
+    }
+
+    let entries = [
+        LogEntry::Insert { row: Row::new(2, "Bob".into(), 30), timestamp: 100 },
+        LogEntry::Insert { row: Row::new(3, "Carol".into(), 40), timestamp: 100 },
+        LogEntry::Delete { id: 1, timestamp: 200 },
+    ];
+    let mut lines: Vec<String> = entries.iter()
+        .map(|entry| serde_json::to_string(entry).unwrap())
+        .collect();
+    lines.insert(1, "{\"Insert\":{\"row\":".to_string());
+    lines.insert(3, "not json at all".to_string());
+    fs::write(&file_path, lines.join("\n") + "\n")?;
+
+    let report = Database::repair(&file_path)?;
+    assert_eq!(report, RepairReport { entries_kept: 3, entries_dropped: 2, rows: 2 });
+
+    let log = fs::read_to_string(&file_path)?;
+    assert_eq!(log.lines().count(), 1, "repaired log only holds its header");
+    assert!(dir.path().join("temp_data.json.bak").exists());
+
+    let db = Database::new(&file_path)?;
+    let ids: Vec<u32> = db.select_all_sorted().iter().map(|row| row.id).collect();
+    assert_eq!(ids, vec![2, 3]);
+
+    Ok(())
+}
+
+#[test]
+// Folding over a rolled and an active log counts inserts and deletes entry by entry
+fn fold_entries_counts_inserts_and_deletes() -> Result<(), DbError> {
+    let dir = tempdir()?;
+
+    let path = "temp_data.json";
+    let file_path = dir.path().join(path);
+
+    let mut storage = Storage::new(&file_path)?;
+    storage.append_entry(&Row::new(1, "Alice".into(), 20))?;
+    storage.append_entry(&Row::new(2, "Bob".into(), 30))?;
+    storage.roll_segment()?;
+    storage.append_delete(1)?;
+    storage.append_entry(&Row::new(3, "Carol".into(), 40))?;
+    storage.append_delete(3)?;
+    storage.flush()?;
+
+    let (inserts, deletes) = storage.fold_entries((0, 0), |(inserts, deletes), entry| match entry {
+        LogEntry::Insert { .. } => (inserts + 1, deletes),
+        LogEntry::Delete { .. } => (inserts, deletes + 1),
+        LogEntry::Update { .. } => (inserts, deletes),
+    })?;
+
+    assert_eq!((inserts, deletes), (3, 2));
+
+    Ok(())
+}
+
+#[test]
+// The data directory is resolved against the given base, not the working directory
+fn base_path_pins_data_directory() -> Result<(), DbError> {
+    let dir = tempdir()?;
+    let base = dir.path().join("app");
+    fs::create_dir(&base)?;
+
+    let storage = Storage::with_base(&base, "db.log")?;
+    assert_eq!(storage.path, base.join("data").join("db.log"));
+    assert!(base.join("data").is_dir());
+    drop(storage);
+
+    {
+        let mut db = Database::with_base(&base, "db.log")?;
+        db.insert(1, "Alice".into(), 30)?;
+        db.shutdown()?;
+    }
+
+    // A second open with the same base finds the same rows
+    let db = DatabaseHandle::with_base(&base, "db.log")?;
+    assert_eq!(db.select_all()?, vec![Row::new(1, "Alice".into(), 30)]);
+
+    // A different base is a different database
+    let other = Database::with_base(dir.path(), "db.log")?;
+    assert!(other.select_all().is_empty());
+
+    Ok(())
+}
+
+#[test]
+// Missing directories are created on open; one that cannot be created is reported clearly
+fn open_creates_missing_data_directory() -> Result<(), DbError> {
+    let dir = tempdir()?;
+    assert!(!dir.path().join("data").exists());
+
+    let mut db = Database::with_base(dir.path(), "mini_db.log")?;
+    db.insert(1, "Alice".into(), 30)?;
+    assert!(dir.path().join("data").is_dir());
+    assert!(dir.path().join("data").join("mini_db.log").is_file());
+
+    let nested = dir.path().join("tenants").join("acme").join("db.log");
+    Database::new(&nested)?;
+    assert!(nested.is_file());
+
+    // A file in the way of the data directory
+    let blocked = dir.path().join("blocked");
+    fs::create_dir(&blocked)?;
+    fs::write(blocked.join("data"), "")?;
+    let err = Database::with_base(&blocked, "mini_db.log");
+    assert!(matches!(err, Err(DbError::DataDirError { path, .. }) if path == blocked.join("data")));
+
+    Ok(())
+}
+
+#[test]
+// Deleting a list of IDs with one absent counts only real deletions, and survives a restart
+fn delete_by_ids_skips_missing_ids() -> Result<(), DbError> {
+    let dir = tempdir()?;
+
+    let path = "temp_data.json";
+    let file_path = dir.path().join(path);
+
+    {
+        let mut db = Database::new(&file_path)?;
+        for id in 1..=5 {
+            db.insert(id, format!("User{id}"), 20)?;
+        }
+
+        assert_eq!(db.delete_by_ids(&[2, 4, 9])?, 2);
+        let ids: Vec<u32> = db.select_all_sorted().iter().map(|row| row.id).collect();
+        assert_eq!(ids, vec![1, 3, 5]);
+        db.shutdown()?;
+    }
+
+    let db = DatabaseHandle::new(&file_path)?;
+    let ids: Vec<u32> = db.select_all_sorted().iter().map(|row| row.id).collect();
+    assert_eq!(ids, vec![1, 3, 5]);
+
+    assert!(matches!(parser::run_command("DELETE WHERE ID IN (1, 3, 7)", &db), CommandOutput::Affected(2)));
+    assert_eq!(db.select_all()?, vec![Row::new(5, "User5".into(), 20)]);
+
+    Ok(())
+}
+
+#[test]
+// A database opened read-only serves selects but rejects every write without touching the log
+fn readonly_open_rejects_writes() -> Result<(), DbError> {
+    let dir = tempdir()?;
+
+    let path = "temp_data.json";
+    let file_path = dir.path().join(path);
+
+    {
+        let mut db = Database::new(&file_path)?;
+        db.insert(1, "Alice".into(), 30)?;
+        db.insert(2, "Bob".into(), 25)?;
+        db.shutdown()?;
+    }
+    let before = fs::read(&file_path)?;
+
+    let mut db = Database::open_readonly(&file_path)?;
+    assert!(db.is_read_only());
+    assert_eq!(db.select_all().len(), 2);
+    assert_eq!(db.select_by_id(1)?, Some(Row::new(1, "Alice".into(), 30)));
+
+    assert!(matches!(db.insert(3, "Carol".into(), 40), Err(DbError::ReadOnly)));
+    assert!(matches!(db.delete_by_id(1), Err(DbError::ReadOnly)));
+    assert!(matches!(db.update(1, "Alice".into(), 31), Err(DbError::ReadOnly)));
+    assert!(matches!(db.reset_db(), Err(DbError::ReadOnly)));
+    assert!(matches!(db.compact(), Err(DbError::ReadOnly)));
+    db.shutdown()?;
+
+    assert_eq!(db.select_all().len(), 2);
+    assert_eq!(fs::read(&file_path)?, before);
+    assert!(!Storage::open_readonly(&file_path)?.snapshot_path().exists());
+
+    let handle = DatabaseHandle::open_readonly(&file_path)?;
+    assert!(matches!(handle.insert(3, "Carol".into(), 40), Err(DbError::ReadOnly)));
+    assert_eq!(handle.select_all()?.len(), 2);
+
+    Ok(())
+}
+
+#[test]
+// Rows written while auto-flush is running are on disk after an interval, without an explicit flush
+fn auto_flush_persists_writes() -> Result<(), DbError> {
+    let dir = tempdir()?;
+
+    let path = "temp_data.json";
+    let file_path = dir.path().join(path);
+
+    let db = DatabaseHandle::new(&file_path)?;
+    db.start_auto_flush(Duration::from_millis(20));
+    db.insert(1, "Alice".into(), 30)?;
+    std::thread::sleep(Duration::from_millis(100));
+
+    let reopened = Database::open_readonly(&file_path)?;
+    assert_eq!(reopened.select_all(), &vec![Row::new(1, "Alice".into(), 30)]);
+
+    db.stop_auto_flush();
+    db.stop_auto_flush();
+
+    Ok(())
+}
+
+#[test]
+// Two databases sharing a data directory each compact into a snapshot named after their own log
+fn snapshots_are_named_after_their_log() -> Result<(), DbError> {
+    let dir = tempdir()?;
+
+    let users_path = dir.path().join("users.log");
+    let orders_path = dir.path().join("orders.log");
+
+    {
+        let mut users = Database::new(&users_path)?;
+        let mut orders = Database::new(&orders_path)?;
+        users.insert(1, "Alice".into(), 30)?;
+        orders.insert(1, "Order".into(), 2)?;
+        orders.insert(2, "Order".into(), 5)?;
+
+        users.compact()?;
+        orders.compact()?;
+    }
+
+    assert!(dir.path().join("users.snapshot").exists());
+    assert!(dir.path().join("orders.snapshot").exists());
+
+    let mut users = Database::new(&users_path)?;
+    let orders = Database::new(&orders_path)?;
+    assert_eq!(users.select_all(), &vec![Row::new(1, "Alice".into(), 30)]);
+    assert_eq!(orders.ids(), vec![1, 2]);
+
+    users.reset_db()?;
+    assert!(!dir.path().join("users.snapshot").exists());
+    assert!(dir.path().join("orders.snapshot").exists());
+
+    Ok(())
+}
+
+#[test]
+// A backup restored into a database elsewhere replaces its rows, and the restored rows survive a restart
+fn backup_restores_into_new_location() -> Result<(), DbError> {
+    let dir = tempdir()?;
+
+    let path = "temp_data.json";
+    let file_path = dir.path().join(path);
+    let backup_path = dir.path().join("backup.json");
+    let restored_path = dir.path().join("restored").join("restored.log");
+
+    let mut db = Database::new(&file_path)?;
+    db.insert(1, "Alice".into(), 30)?;
+    db.insert(2, "Bob".into(), 25)?;
+    db.update(2, "Robert".into(), 26)?;
+    db.insert(3, "Carol".into(), 40)?;
+    db.delete_by_id(1)?;
+    db.backup_to(&backup_path)?;
+
+    {
+        let mut restored = Database::new(&restored_path)?;
+        restored.insert(9, "Stale".into(), 99)?;
+
+        assert_eq!(restored.restore_from(&backup_path)?, 2);
+        assert_eq!(restored.select_all_sorted(), db.select_all_sorted());
+    }
+
+    let restored = Database::new(&restored_path)?;
+    assert_eq!(restored.select_all_sorted(), db.select_all_sorted());
+
+    Ok(())
+}
+
+#[test]
+// Group-committed rows reach the log once the delay elapses, and an explicit flush commits at once
+fn group_commit_writes_after_delay_or_flush() -> Result<(), DbError> {
+    let dir = tempdir()?;
+
+    let path = "temp_data.json";
+    let file_path = dir.path().join(path);
+
+    let mut storage = Storage::new(&file_path)?;
+    storage.configure_group_commit(1_000, Duration::from_millis(50))?;
+    let mut db = Database::from_storage(storage)?;
+
+    for id in 1..=20 {
+        db.insert(id, format!("User{id}"), 30)?;
+    }
+    std::thread::sleep(Duration::from_millis(200));
+    assert_eq!(Database::open_readonly(&file_path)?.select_all().len(), 20);
+    drop(db);
+
+    // A long delay keeps the next burst pending until the flush
+    let mut storage = Storage::new(&file_path)?;
+    storage.configure_group_commit(1_000, Duration::from_secs(60))?;
+    let mut db = Database::from_storage(storage)?;
+
+    for id in 21..=30 {
+        db.insert(id, format!("User{id}"), 30)?;
+    }
+    assert_eq!(Database::open_readonly(&file_path)?.select_all().len(), 20);
+
+    db.flush()?;
+    assert_eq!(Database::open_readonly(&file_path)?.select_all().len(), 30);
+
+    Ok(())
+}
+
+#[test]
+// A hand-edited log inserting the same id twice loads a single row that the index can find
+fn duplicate_insert_keeps_one_indexed_row() -> Result<(), DbError> {
+    let dir = tempdir()?;
+
+    let path = "temp_data.json";
+    let file_path = dir.path().join(path);
+
+    let entries = [
+        LogEntry::Insert { row: Row::new(1, "Alice".into(), 30), timestamp: 100 },
+        LogEntry::Insert { row: Row::new(2, "Bob".into(), 25), timestamp: 110 },
+        LogEntry::Insert { row: Row::new(1, "Alicia".into(), 31), timestamp: 120 },
+    ];
+    let lines: Vec<String> = entries.iter()
+        .map(|entry| serde_json::to_string(entry).unwrap())
+        .collect();
+    fs::write(&file_path, lines.join("\n") + "\n")?;
+
+    let db = Database::new(&file_path)?;
+    assert_eq!(db.select_all().len(), 2);
+    assert_eq!(db.select_by_id(1)?.map(|row| row.name), Some("Alicia".to_string()));
+    db.check_integrity()?;
+
+    Ok(())
+}
+
+#[test]
+// Rows still buffered when the database is dropped without shutdown are flushed and survive a reopen
+fn drop_flushes_without_shutdown() -> Result<(), DbError> {
+    let dir = tempdir()?;
+
+    let path = "temp_data.json";
+    let file_path = dir.path().join(path);
+
+    {
+        let mut storage = Storage::new(&file_path)?;
+        storage.configure_group_commit(1_000, Duration::from_secs(60))?;
+        let mut db = Database::from_storage(storage)?;
+        for id in 1..=5 {
+            db.insert(id, format!("User{id}"), 30)?;
+        }
+    }
+
+    let db = Database::new(&file_path)?;
+    assert_eq!(db.ids(), vec![1, 2, 3, 4, 5]);
+
+    Ok(())
+}
+
+#[test]
+// Reset and compact drop entries still buffered by group commit, so they never land in the truncated log
+fn truncating_discards_group_commit_buffer() -> Result<(), DbError> {
+    let dir = tempdir()?;
+
+    let path = "temp_data.json";
+    let file_path = dir.path().join(path);
+
+    {
+        let mut storage = Storage::new(&file_path)?;
+        storage.configure_group_commit(1_000, Duration::from_secs(60))?;
+        let mut db = Database::from_storage(storage)?;
+        db.insert(1, "Alice".into(), 30)?;
+        db.insert(2, "Bob".into(), 25)?;
+        db.reset_db()?;
+    }
+
+    let db = Database::new(&file_path)?;
+    assert!(db.ids().is_empty());
+    drop(db);
+
+    {
+        let mut storage = Storage::new(&file_path)?;
+        storage.configure_group_commit(1_000, Duration::from_secs(60))?;
+        let mut db = Database::from_storage(storage)?;
+        db.insert(1, "Alice".into(), 30)?;
+        db.insert(2, "Bob".into(), 25)?;
+        db.compact()?;
+        db.insert(3, "Carol".into(), 41)?;
+    }
+
+    let db = Database::new(&file_path)?;
+    assert_eq!(db.ids(), vec![1, 2, 3]);
+    assert_eq!(db.stats()?.entry_count, 1);
+
+    Ok(())
+}
+
+#[test]
+// Renaming keeps the typed case and the age, survives a restart, and reports a missing id
+fn rename_by_id_persists() -> Result<(), DbError> {
+    let dir = tempdir()?;
+
+    let path = "temp_data.json";
+    let file_path = dir.path().join(path);
+
+    {
+        let db = DatabaseHandle::new(&file_path)?;
+        db.insert(5, "alice".into(), 30)?;
+
+        assert!(matches!(parser::run_command(r#"RENAME ID=5 TO "Alice Smith""#, &db), CommandOutput::Affected(1)));
+        assert!(!db.rename_by_id(6, "Nobody".into())?);
+        db.shutdown()?;
+    }
+
+    let db = Database::new(&file_path)?;
+    assert_eq!(db.select_by_id(5)?, Some(Row::new(5, "Alice Smith".into(), 30)));
+    assert_eq!(db.select_all().len(), 1);
+
+    Ok(())
+}
+
+#[test]
+// With timestamps disabled an insert is logged byte-for-byte deterministically,
+// and entries without a timestamp field still load
+fn insert_without_timestamps_is_deterministic() -> Result<(), DbError> {
+    let dir = tempdir()?;
+
+    let path = "temp_data.json";
+    let file_path = dir.path().join(path);
+
+    {
+        let mut storage = Storage::new(&file_path)?;
+        storage.record_timestamps = false;
+        let mut db = Database::from_storage(storage)?;
+        db.insert(1, "Alice".into(), 30)?;
+        db.flush()?;
+    }
+
+    let expected = concat!(
+        "{\"magic\":\"mini_db\",\"version\":1}\n",
+        "{\"Insert\":{\"row\":{\"id\":1,\"name\":\"Alice\",\"age\":30,\"created_at\":0,\"updated_at\":0},\"timestamp\":0}}\n",
+    );
+    assert_eq!(fs::read_to_string(&file_path)?, expected);
+
+    let mut contents = fs::read_to_string(&file_path)?;
+    contents.push_str("{\"Insert\":{\"row\":{\"id\":2,\"name\":\"Bob\",\"age\":25}}}\n");
+    fs::write(&file_path, contents)?;
+
+    let db = Database::new(&file_path)?;
+    assert_eq!(db.select_all(), &vec![Row::new(1, "Alice".into(), 30), Row::new(2, "Bob".into(), 25)]);
+
+    Ok(())
+}
+
+#[test]
+// Rows logged with timestamps turned off have no known insert time, so retention is a no-op
+fn retention_keeps_rows_logged_without_timestamps() -> Result<(), DbError> {
+    let dir = tempdir()?;
+
+    let path = "temp_data.json";
+    let file_path = dir.path().join(path);
+
+    let mut storage = Storage::new(&file_path)?;
+    storage.record_timestamps = false;
+    let mut db = Database::from_storage(storage)?;
+    db.insert(1, "Alice".into(), 30)?;
+    db.insert(2, "Bob".into(), 25)?;
+
+    assert_eq!(db.enforce_retention(60)?, 0);
+    assert_eq!(db.ids(), vec![1, 2]);
+
+    Ok(())
+}
+
+#[test]
+// Without recorded timestamps a point-in-time view cannot be rebuilt, so snapshot_at fails instead of
+// returning the current table
+fn snapshot_at_fails_without_timestamps() -> Result<(), DbError> {
+    let dir = tempdir()?;
+
+    let path = "temp_data.json";
+    let file_path = dir.path().join(path);
+
+    let mut storage = Storage::new(&file_path)?;
+    storage.record_timestamps = false;
+    let mut db = Database::from_storage(storage)?;
+    db.insert(1, "Alice".into(), 30)?;
+
+    match db.snapshot_at(1) {
+        Err(DbError::IoError(e)) => assert_eq!(e.kind(), std::io::ErrorKind::Unsupported),
+        other => panic!("expected Unsupported, got {:?}", other.map(|view| view.ids())),
+    }
+
+    Ok(())
+}
+
+#[test]
+// Reopening right after compaction builds the table from the snapshot without reading the empty log
+fn compacted_open_skips_log_replay() -> Result<(), DbError> {
+    let dir = tempdir()?;
+
+    let path = "temp_data.json";
+    let file_path = dir.path().join(path);
+
+    {
+        let mut db = Database::new(&file_path)?;
+        for id in 1..=50 {
+            db.insert(id, format!("User{id}"), 30)?;
+        }
+        db.compact()?;
+    }
+    assert_eq!(fs::metadata(&file_path)?.len(), 0);
+
+    let storage = Storage::new(&file_path)?;
+    let snapshot_rows = storage.snapshot_read()?.unwrap_or_default();
+    let rows = storage.load_onto(snapshot_rows)?;
+    assert_eq!(rows.len(), 50);
+    assert_eq!(storage.lines_read(), 0);
+
+    let db = Database::load_from_disk(storage)?;
+    assert_eq!(db.ids(), (1..=50).collect::<Vec<_>>());
+
+    Ok(())
+}
+
+/// Collects warnings so tests can inspect them.
+struct CapturingSink(Arc<Mutex<Vec<LogWarning>>>);
+
+impl LogSink for CapturingSink {
+    fn warn(&self, warning: &LogWarning) {
+        self.0.lock().unwrap().push(warning.clone());
+    }
+}
+
+#[test]
+// A corrupt line is reported once to an installed sink, with its line number, and skipped
+fn corrupt_line_is_reported_to_log_sink() -> Result<(), DbError> {
+    let dir = tempdir()?;
+
+    let path = "temp_data.json";
+    let file_path = dir.path().join(path);
+
+    {
+        let mut db = Database::new(&file_path)?;
+        db.insert(1, "Alice".into(), 30)?;
+        db.flush()?;
+    }
+
+    let mut contents = fs::read_to_string(&file_path)?;
+    contents.push_str("not json\n");
+    contents.push_str("{\"Insert\":{\"row\":{\"id\":2,\"name\":\"Bob\",\"age\":25},\"timestamp\":1}}\n");
+    fs::write(&file_path, contents)?;
+
+    let warnings = Arc::new(Mutex::new(Vec::new()));
+    let mut storage = Storage::new(&file_path)?;
+    storage.log_sink = Arc::new(CapturingSink(Arc::clone(&warnings)));
+
+    let db = Database::load_from_disk(storage)?;
+    assert_eq!(db.ids(), vec![1, 2]);
+
+    let warnings = warnings.lock().unwrap();
+    assert_eq!(warnings.len(), 1);
+    assert_eq!(warnings[0].line, 3);
+    assert!(warnings[0].message.contains("line 3"));
+
+    Ok(())
+}
+
+#[test]
+// An unreadable snapshot and a repeated insert are reported to an installed sink, not stderr
+fn load_warnings_are_reported_to_log_sink() -> Result<(), DbError> {
+    let dir = tempdir()?;
+
+    let path = "temp_data.json";
+    let file_path = dir.path().join(path);
+
+    {
+        let mut db = Database::new(&file_path)?;
+        db.insert(1, "Alice".into(), 30)?;
+        db.flush()?;
+    }
+
+    let mut contents = fs::read_to_string(&file_path)?;
+    contents.push_str("{\"Insert\":{\"row\":{\"id\":1,\"name\":\"Alicia\",\"age\":31},\"timestamp\":1}}\n");
+    fs::write(&file_path, contents)?;
+    fs::write(Storage::open_readonly(&file_path)?.snapshot_path(), "not a snapshot")?;
+
+    let warnings = Arc::new(Mutex::new(Vec::new()));
+    let mut storage = Storage::new(&file_path)?;
+    storage.log_sink = Arc::new(CapturingSink(Arc::clone(&warnings)));
+
+    let db = Database::load_from_disk(storage)?;
+    assert_eq!(db.select_by_id(1)?.map(|row| row.name), Some("Alicia".to_string()));
+
+    let warnings = warnings.lock().unwrap();
+    assert_eq!(warnings.len(), 2);
+    assert!(warnings.iter().any(|w| w.line == 0 && w.message.contains("unreadable snapshot")));
+    assert!(warnings.iter().any(|w| w.line == 0 && w.message.contains("inserts id 1 again")));
+
+    Ok(())
+}
+
+#[test]
+// A large snapshot streamed to disk is valid JSON holding every row, and reloads intact
+fn large_snapshot_round_trips() -> Result<(), DbError> {
+    let dir = tempdir()?;
+
+    let path = "temp_data.json";
+    let file_path = dir.path().join(path);
+
+    let rows: Vec<Row> = (1..=20_000)
+        .map(|id| Row::new(id, format!("User{id}"), (id % 100) as u8))
+        .collect();
+
+    let mut storage = Storage::new(&file_path)?;
+    storage.snapshot_write(&rows)?;
+
+    let snapshot: serde_json::Value = serde_json::from_str(&fs::read_to_string(storage.snapshot_path())?)?;
+    assert_eq!(snapshot["rows"].as_array().map(Vec::len), Some(20_000));
+    assert!(!dir.path().join("temp_data.snapshot.tmp").exists());
+
+    assert_eq!(storage.snapshot_read()?, Some(rows.clone()));
+    drop(storage);
+
+    let db = Database::new(&file_path)?;
+    assert_eq!(db.select_all(), &rows);
+
+    Ok(())
+}
+
+#[test]
+// A deleted row leaves a timestamped tombstone while live rows exclude it, and re-inserting the id clears it
+fn load_with_tombstones_lists_deleted_ids() -> Result<(), DbError> {
+    let dir = tempdir()?;
+
+    let path = "temp_data.json";
+    let file_path = dir.path().join(path);
+
+    {
+        let mut db = Database::new(&file_path)?;
+        db.insert(1, "Alice".into(), 30)?;
+        db.insert(2, "Bob".into(), 25)?;
+        db.insert(3, "Carol".into(), 40)?;
+        db.delete_by_id(2)?;
+        db.delete_by_id(3)?;
+        db.insert(3, "Carol".into(), 41)?;
+        db.delete_by_id(99)?;
+        db.flush()?;
+    }
+
+    let storage = Storage::new(&file_path)?;
+    let (rows, tombstones) = storage.load_with_tombstones()?;
+
+    assert_eq!(rows, vec![Row::new(1, "Alice".into(), 30), Row::new(3, "Carol".into(), 41)]);
+    assert_eq!(tombstones.len(), 1);
+    assert_eq!(tombstones[0].0, 2);
+    assert!(tombstones[0].1 > 0);
+    assert_eq!(storage.load_all()?, rows);
+
+    Ok(())
+}
+
+#[test]
+// A batch cut off at any point while it is appended leaves none of it in the log; a finished batch is replayed whole
+fn append_batch_is_all_or_nothing() -> Result<(), DbError> {
+    let dir = tempdir()?;
+
+    let path = "temp_data.json";
+    let file_path = dir.path().join(path);
+    let tmp_path = dir.path().join("temp_data.json.batch.tmp");
+
+    let mut storage = Storage::new(&file_path)?;
+    storage.append_entry(&Row::new(1, "Alice".into(), 30))?;
+    storage.flush()?;
+    let before = fs::read(&file_path)?;
+
+    let batch: Vec<Row> = (2..=4).map(|id| Row::new(id, format!("user{id}"), 20)).collect();
+    storage.append_batch(&batch)?;
+    assert_eq!(storage.load_all()?.len(), 4);
+    assert!(!tmp_path.exists());
+    drop(storage);
+    let after = fs::read(&file_path)?;
+
+    // Crash while appending: the recorded length cuts the log back, even once every line is written
+    for cut in (before.len()..=after.len()).step_by(7).chain([after.len()]) {
+        fs::write(&file_path, &after[..cut])?;
+        fs::write(&tmp_path, format!("{}\n", before.len()))?;
+
+        let storage = Storage::new(&file_path)?;
+        assert_eq!(storage.load_all()?, vec![Row::new(1, "Alice".into(), 30)]);
+        assert!(!tmp_path.exists());
+    }
+
+    // Crash while recording the length: the log was not touched yet
+    fs::write(&file_path, &before)?;
+    fs::write(&tmp_path, "1")?;
+    let storage = Storage::new(&file_path)?;
+    assert_eq!(storage.load_all()?.len(), 1);
+    assert!(!tmp_path.exists());
+    drop(storage);
+
+    // Crash after the record is removed: the whole batch is there
+    fs::write(&file_path, &after)?;
+    let storage = Storage::new(&file_path)?;
+    assert_eq!(storage.load_all()?.len(), 4);
+
+    Ok(())
+}
+
+#[test]
+// A second writable open of a log fails while the first holds its lock, and succeeds once the first is dropped
+fn second_open_fails_while_locked() -> Result<(), DbError> {
+    let dir = tempdir()?;
+
+    let path = "temp_data.json";
+    let file_path = dir.path().join(path);
+
+    let mut db = Database::new(&file_path)?;
+    db.insert(1, "Alice".into(), 30)?;
+
+    match Database::new(&file_path) {
+        Err(DbError::AlreadyLocked { path }) => assert_eq!(path, file_path),
+        other => panic!("expected AlreadyLocked, got {:?}", other.map(|_| ())),
+    }
+    // Readers do not need the lock
+    assert_eq!(Database::open_readonly(&file_path)?.select_all().len(), 1);
+
+    drop(db);
+    let db = Database::new(&file_path)?;
+    assert_eq!(db.select_all().len(), 1);
+
+    Ok(())
+}
+
+#[test]
+// Importing a JSONL file applies its inserts and deletes, logs them, and skips and counts a malformed line,
+// reporting it to the installed sink
+fn import_jsonl_applies_entries() -> Result<(), DbError> {
+    let dir = tempdir()?;
+
+    let path = "temp_data.json";
+    let file_path = dir.path().join(path);
+    let import_path = dir.path().join("import.jsonl");
+
+    let entries = [
+        LogEntry::Insert { row: Row::new(1, "Alice".into(), 30), timestamp: 1 },
+        LogEntry::Insert { row: Row::new(2, "Bob".into(), 25), timestamp: 2 },
+        LogEntry::Delete { id: 1, timestamp: 3 },
+    ];
+    let mut lines: Vec<String> = entries.iter()
+        .map(serde_json::to_string)
+        .collect::<Result<_, _>>()?;
+    lines.insert(2, "{not json".to_string());
+    fs::write(&import_path, lines.join("\n") + "\n")?;
+
+    let warnings = Arc::new(Mutex::new(Vec::new()));
+    {
+        let mut storage = Storage::new(&file_path)?;
+        storage.log_sink = Arc::new(CapturingSink(Arc::clone(&warnings)));
+        let mut db = Database::from_storage(storage)?;
+        assert_eq!(db.import_jsonl(&import_path)?, ImportReport { applied: 3, skipped: 1 });
+        assert_eq!(db.select_all(), &vec![Row::new(2, "Bob".into(), 25)]);
+    }
+
+    let warnings = warnings.lock().unwrap();
+    assert_eq!(warnings.len(), 1);
+    assert_eq!(warnings[0].line, 3);
+
+    let db = Database::new(&file_path)?;
+    assert_eq!(db.select_all(), &vec![Row::new(2, "Bob".into(), 25)]);
+
+    Ok(())
+}
+
+// Writes a log holding Alice, a corrupt line 3 and Bob, for the replay policy tests
+fn write_log_with_corrupt_line(file_path: &std::path::Path) -> Result<(), DbError> {
+    {
+        let mut db = Database::new(file_path)?;
+        db.insert(1, "Alice".into(), 30)?;
+        db.flush()?;
+    }
+
+    let mut contents = fs::read_to_string(file_path)?;
+    contents.push_str("not json\n");
+    contents.push_str("{\"Insert\":{\"row\":{\"id\":2,\"name\":\"Bob\",\"age\":25},\"timestamp\":1}}\n");
+    fs::write(file_path, contents)?;
+    Ok(())
+}
+
+#[test]
+// The default lenient policy skips the corrupt line and loads the rows around it
+fn lenient_replay_skips_corrupt_line() -> Result<(), DbError> {
+    let dir = tempdir()?;
+
+    let path = "temp_data.json";
+    let file_path = dir.path().join(path);
+    write_log_with_corrupt_line(&file_path)?;
+
+    let storage = Storage::new(&file_path)?;
+    assert_eq!(storage.replay_policy, ReplayPolicy::Lenient);
+
+    let db = Database::load_from_disk(storage)?;
+    assert_eq!(db.ids(), vec![1, 2]);
+
+    Ok(())
+}
+
+#[test]
+// The strict policy refuses to load a log with a corrupt line and names the line
+fn strict_replay_rejects_corrupt_line() -> Result<(), DbError> {
+    let dir = tempdir()?;
+
+    let path = "temp_data.json";
+    let file_path = dir.path().join(path);
+    write_log_with_corrupt_line(&file_path)?;
+
+    let mut storage = Storage::new(&file_path)?;
+    storage.replay_policy = ReplayPolicy::Strict;
+
+    match Database::load_from_disk(storage) {
+        Err(DbError::CorruptLog { line, .. }) => assert_eq!(line, 3),
+        other => panic!("expected CorruptLog, got {:?}", other.map(|db| db.ids())),
+    }
+
+    // Opening through Database names the file as well as the line
+    match Database::open_with_policy(&file_path, ReplayPolicy::Strict) {
+        Err(DbError::CorruptLog { line, detail }) => {
+            assert_eq!(line, 3);
+            assert!(detail.starts_with(&file_path.display().to_string()));
+        },
+        other => panic!("expected CorruptLog, got {:?}", other.map(|db| db.ids())),
+    }
+    assert_eq!(Database::open_with_policy(&file_path, ReplayPolicy::Lenient)?.ids(), vec![1, 2]);
+
+    Ok(())
+}
+
+#[test]
+// Merging a secondary database inserts its new ids and keeps the primary's row for the overlapping one
+fn merge_from_inserts_new_ids_and_keeps_primary_on_conflict() -> Result<(), DbError> {
+    let dir = tempdir()?;
+
+    let path = "temp_data.json";
+    let file_path = dir.path().join(path);
+    let other_path = dir.path().join("other.json");
+
+    {
+        let mut other = Database::new(&other_path)?;
+        other.insert(2, "Bobby".into(), 26)?;
+        other.insert(3, "Carol".into(), 41)?;
+    }
+
+    {
+        let mut db = Database::new(&file_path)?;
+        db.insert(1, "Alice".into(), 30)?;
+        db.insert(2, "Bob".into(), 25)?;
+
+        let report = db.merge_from(&other_path)?;
+        assert_eq!(report, MergeReport { inserted: 1, skipped: 1, replaced: 0 });
+    }
+
+    let db = Database::new(&file_path)?;
+    let rows: Vec<(u32, &str)> = db.select_all().iter().map(|r| (r.id, r.name.as_str())).collect();
+    assert_eq!(rows, vec![(1, "Alice"), (2, "Bob"), (3, "Carol")]);
+
+    Ok(())
+}
+
+#[test]
+// With PreferNewer, a conflicting row replaces the primary's only when its updated_at is newer
+fn merge_from_with_prefer_newer_takes_newer_row() -> Result<(), DbError> {
+    let dir = tempdir()?;
+
+    let path = "temp_data.json";
+    let file_path = dir.path().join(path);
+    let other_path = dir.path().join("other.json");
+
+    let row = |id, name: &str, updated_at| Row { updated_at, ..Row::new(id, name.into(), 30) };
+
+    {
+        let mut other = Database::new(&other_path)?;
+        other.insert_row(row(1, "Alice v2", 200))?;
+        other.insert_row(row(2, "Bob v0", 50))?;
+    }
+
+    let mut db = Database::new(&file_path)?;
+    db.insert_row(row(1, "Alice", 100))?;
+    db.insert_row(row(2, "Bob", 100))?;
+
+    let report = db.merge_from_with(&other_path, MergePolicy::PreferNewer)?;
+    assert_eq!(report, MergeReport { inserted: 0, skipped: 1, replaced: 1 });
+
+    let names: Vec<&str> = db.select_all().iter().map(|r| r.name.as_str()).collect();
+    assert_eq!(names, vec!["Alice v2", "Bob"]);
 
     Ok(())
 }